@@ -0,0 +1,155 @@
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+///  Hours (right ascension) to radians
+pub fn hours_to_radians(hours: f64) -> f64 {
+    hours * core::f64::consts::PI / 12.0
+}
+
+/// Angle in radians
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Radians(pub f64);
+
+impl Radians {
+    pub fn sin(self) -> f64 {
+        libm::sin(self.0)
+    }
+
+    pub fn cos(self) -> f64 {
+        libm::cos(self.0)
+    }
+
+    pub fn tan(self) -> f64 {
+        libm::tan(self.0)
+    }
+
+    pub fn abs(self) -> Radians {
+        Radians(libm::fabs(self.0))
+    }
+
+    pub fn asin(value: f64) -> Radians {
+        Radians(libm::asin(value))
+    }
+
+    pub fn acos(value: f64) -> Radians {
+        Radians(libm::acos(value))
+    }
+
+    pub fn atan2(y: f64, x: f64) -> Radians {
+        Radians(libm::atan2(y, x))
+    }
+
+    /// Angle wrapped to [0, 2π)
+    pub fn normalized(self) -> Radians {
+        let turn = 2.0 * core::f64::consts::PI;
+        let angle = libm::fmod(self.0, turn);
+        Radians(if angle < 0.0 { angle + turn } else { angle })
+    }
+}
+
+impl Add for Radians {
+    type Output = Radians;
+
+    fn add(self, other: Radians) -> Radians {
+        Radians(self.0 + other.0)
+    }
+}
+
+impl Sub for Radians {
+    type Output = Radians;
+
+    fn sub(self, other: Radians) -> Radians {
+        Radians(self.0 - other.0)
+    }
+}
+
+impl Neg for Radians {
+    type Output = Radians;
+
+    fn neg(self) -> Radians {
+        Radians(-self.0)
+    }
+}
+
+impl Mul<f64> for Radians {
+    type Output = Radians;
+
+    fn mul(self, factor: f64) -> Radians {
+        Radians(self.0 * factor)
+    }
+}
+
+impl Div<f64> for Radians {
+    type Output = Radians;
+
+    fn div(self, divisor: f64) -> Radians {
+        Radians(self.0 / divisor)
+    }
+}
+
+/// Angle in degrees
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Degrees(pub f64);
+
+/// Angle in hours (right ascension)
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hours(pub f64);
+
+impl From<Hours> for Radians {
+    fn from(hours: Hours) -> Radians {
+        Radians(hours_to_radians(hours.0))
+    }
+}
+
+impl From<Degrees> for Radians {
+    fn from(degrees: Degrees) -> Radians {
+        Radians(degrees.0.to_radians())
+    }
+}
+
+impl From<Radians> for Hours {
+    fn from(radians: Radians) -> Hours {
+        Hours(radians.0 / hours_to_radians(1.0))
+    }
+}
+
+impl From<Radians> for Degrees {
+    fn from(radians: Radians) -> Degrees {
+        Degrees(radians.0.to_degrees())
+    }
+}
+
+impl From<Hours> for Degrees {
+    fn from(hours: Hours) -> Degrees {
+        Degrees(hours.0 * 15.0)
+    }
+}
+
+impl From<Degrees> for Hours {
+    fn from(degrees: Degrees) -> Hours {
+        Hours(degrees.0 / 15.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_between_units() {
+        let ra = Hours(5.5);
+        assert!((Hours::from(Radians::from(ra)).0 - 5.5).abs() < 1e-12);
+        assert!((Degrees::from(Radians::from(ra)).0 - 82.5).abs() < 1e-12);
+        assert!((Radians::from(Degrees(-90.0)) + Radians(core::f64::consts::FRAC_PI_2)).abs().0 < 1e-15);
+    }
+
+    #[test]
+    fn wraps_to_one_turn() {
+        let turn = 2.0 * core::f64::consts::PI;
+        assert!((Radians(-0.5).normalized().0 - (turn - 0.5)).abs() < 1e-12);
+        assert!((Radians(turn + 0.5).normalized().0 - 0.5).abs() < 1e-12);
+        assert_eq!(Radians(0.0).normalized(), Radians(0.0));
+    }
+}
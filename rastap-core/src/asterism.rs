@@ -0,0 +1,217 @@
+use alloc::{vec, vec::Vec};
+
+use crate::angle::Radians;
+use crate::polygon::{Star, StarMatch};
+
+/// Fewest and most image stars matched as an asterism template
+pub const MIN_STARS: usize = 3;
+pub const MAX_STARS: usize = 5;
+/// Brightest catalog stars tried by default
+pub const CATALOG_CANDIDATES: usize = 40;
+/// Default magnitudes by which the brightness order may be broken (different passbands)
+pub const MAGNITUDE_SLACK: f64 = 1.0;
+/// Minimum ratio of the runner-up to the best RMS separation error for an unambiguous match
+pub const MIN_DISTINCTNESS: f64 = 2.0;
+
+/// Thresholds of an asterism match
+#[derive(Clone, Debug)]
+pub struct AsterismTolerance {
+    pub relative: f64,        // Separation error, relative to the separation (scale uncertainty)
+    pub absolute: f64,        // Separation error added to it, in radians (centroid errors)
+    pub magnitude_slack: f64, // Magnitudes by which the brightness order may be broken
+    pub candidates: usize,    // Brightest catalog stars tried
+}
+
+/// Asterism found in the catalog
+pub struct AsterismMatch {
+    pub matches: Vec<StarMatch>,    // One per image star of the template, brightest first
+    pub rms_relative: f64,          // RMS relative separation error
+    pub runner_up_rms: Option<f64>, // RMS relative separation error of the second best asterism
+    pub candidates: usize,          // Catalog asterisms within the tolerance
+}
+
+impl AsterismMatch {
+    /// Whether the best asterism stands out from the other candidates
+    pub fn is_unique(&self) -> bool {
+        match self.runner_up_rms {
+            Some(runner_up) => runner_up >= MIN_DISTINCTNESS * self.rms_relative,
+            None => true,
+        }
+    }
+}
+
+/// Angular separation of two catalog stars (haversine)
+fn separation(a: &Star, b: &Star) -> f64 {
+    let sin_dec = ((b.dec_rad - a.dec_rad) / 2.0).sin();
+    let sin_ra = ((b.ra_rad - a.ra_rad) / 2.0).sin();
+    let h = sin_dec * sin_dec + a.dec_rad.cos() * b.dec_rad.cos() * sin_ra * sin_ra;
+    (Radians::asin(libm::sqrt(h).min(1.0)) * 2.0).0
+}
+
+/// Search state of the template match
+struct Search<'a> {
+    image: &'a [Star],
+    catalog: &'a [Star],
+    image_separations: Vec<Vec<f64>>,
+    catalog_separations: Vec<Vec<f64>>,
+    tolerance: &'a AsterismTolerance,
+    assigned: Vec<usize>,
+    best: Option<(f64, Vec<usize>)>,
+    runner_up: Option<f64>,
+    found: usize,
+}
+
+impl<'a> Search<'a> {
+    /// Assigns a catalog star to the next image star, depth first
+    fn extend(&mut self, error: f64) {
+        let i = self.assigned.len();
+        if i == self.image.len() {
+            self.found += 1;
+            match &self.best {
+                Some((best, _)) if error >= *best => match self.runner_up {
+                    Some(runner_up) if runner_up <= error => (),
+                    _ => self.runner_up = Some(error),
+                },
+                _ => {
+                    self.runner_up = self.best.as_ref().map(|(best, _)| *best);
+                    self.best = Some((error, self.assigned.clone()));
+                }
+            }
+            return;
+        }
+        'candidate: for c in 0..self.catalog.len() {
+            if self.assigned.contains(&c) {
+                continue;
+            }
+            let mut candidate_error = error;
+            for (k, &assigned) in self.assigned.iter().enumerate() {
+                // Brighter image stars match brighter catalog stars
+                if self.catalog[assigned].magnitude > self.catalog[c].magnitude + self.tolerance.magnitude_slack {
+                    continue 'candidate;
+                }
+                let expected = self.image_separations[k][i];
+                let difference = (self.catalog_separations[assigned][c] - expected).abs();
+                if difference > self.tolerance.relative * expected + self.tolerance.absolute {
+                    continue 'candidate;
+                }
+                let relative = difference / expected;
+                candidate_error += relative * relative;
+            }
+            self.assigned.push(c);
+            self.extend(candidate_error);
+            self.assigned.pop();
+        }
+    }
+}
+
+/// Matches the brightest image stars (MIN_STARS to MAX_STARS), as an exact asterism, against the brightest
+/// catalog stars: every pairwise separation must agree and the brightness order must be kept. The image
+/// star list must be at the image scale (radians, as in polygon matching), so no polygons are needed.
+/// Both lists are sorted by magnitude. None if too few stars or no asterism is within the tolerance.
+pub fn match_asterism(image: &[Star], catalog: &[Star], tolerance: &AsterismTolerance) -> Option<AsterismMatch> {
+    let image = &image[..image.len().min(MAX_STARS)];
+    let catalog = &catalog[..catalog.len().min(tolerance.candidates)];
+    if image.len() < MIN_STARS || catalog.len() < image.len() {
+        return None;
+    }
+    let mut image_separations = vec![vec![0.0; image.len()]; image.len()];
+    for i in 0..image.len() {
+        for j in 0..image.len() {
+            image_separations[i][j] = libm::hypot((image[i].ra_rad - image[j].ra_rad).0, (image[i].dec_rad - image[j].dec_rad).0);
+        }
+    }
+    if image_separations.iter().flatten().enumerate().any(|(n, &d)| n / image.len() != n % image.len() && d <= 0.0) {
+        // Coincident image stars
+        return None;
+    }
+    let catalog_separations = catalog.iter().map(|a| catalog.iter().map(|b| separation(a, b)).collect()).collect();
+    let mut search = Search {
+        image,
+        catalog,
+        image_separations,
+        catalog_separations,
+        tolerance,
+        assigned: Vec::with_capacity(image.len()),
+        best: None,
+        runner_up: None,
+        found: 0,
+    };
+    search.extend(0.0);
+    let (error, assigned) = search.best?;
+    let pairs = image.len() * (image.len() - 1) / 2;
+    Some(AsterismMatch {
+        matches: assigned.iter().enumerate().map(|(image_star, &star)| StarMatch { image_star, star, votes: 1 }).collect(),
+        rms_relative: libm::sqrt(error / pairs as f64),
+        runner_up_rms: search.runner_up.map(|error| libm::sqrt(error / pairs as f64)),
+        candidates: search.found,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::{Degrees, Hours};
+
+    const TOLERANCE: AsterismTolerance = AsterismTolerance { relative: 0.02, absolute: 1e-5, magnitude_slack: MAGNITUDE_SLACK, candidates: CATALOG_CANDIDATES };
+
+    /// Catalog stars at R.A./Dec offsets (degrees) from R.A. 60, Dec 20, sorted by magnitude
+    fn sky_stars(stars: &[(f64, f64, f64)]) -> Vec<Star> {
+        let mut catalog: Vec<Star> = stars.iter().enumerate()
+            .map(|(i, s)| Star::sky(i as u64, i as u64, Hours::from(Degrees(60.0 + s.0)), Degrees(20.0 + s.1), s.2))
+            .collect();
+        catalog.sort_by(|a, b| a.magnitude.total_cmp(&b.magnitude));
+        catalog
+    }
+
+    /// Image of catalog stars on the tangent plane, rotated and scaled, with their magnitudes
+    fn image_of(catalog: &[Star], stars: &[u64], rotation: f64, scale: f64) -> Vec<Star> {
+        let (ra0, dec0) = (Radians::from(Degrees(60.0)), Radians::from(Degrees(20.0)));
+        let (sin, cos) = (libm::sin(rotation), libm::cos(rotation));
+        stars.iter().enumerate().map(|(i, db_id)| {
+            let star = catalog.iter().find(|star| star.db_id == *db_id).unwrap();
+            let (xi, eta) = ((star.ra_rad - ra0).0 * dec0.cos(), (star.dec_rad - dec0).0);
+            Star::image_plane(i as u64, Radians(scale * (cos * xi - sin * eta)), Radians(scale * (sin * xi + cos * eta)), star.magnitude + 5.0)
+        }).collect()
+    }
+
+    fn matched_ids(catalog: &[Star], found: &AsterismMatch) -> Vec<u64> {
+        found.matches.iter().map(|m| catalog[m.star].db_id).collect()
+    }
+
+    #[test]
+    fn finds_rotated_and_scaled_asterism() {
+        let catalog = sky_stars(&[(0.0, 0.0, 3.0), (0.6, 0.2, 3.5), (-0.3, 0.5, 4.0), (0.2, -0.7, 4.2), (1.1, 0.9, 2.5),
+            (-0.9, -0.4, 3.8), (0.4, 0.8, 4.5), (-0.6, 1.0, 5.0)]);
+        let image = image_of(&catalog, &[0, 1, 2, 3], 0.8, 1.01);
+        let found = match_asterism(&image, &catalog, &TOLERANCE).unwrap();
+        assert_eq!(matched_ids(&catalog, &found), vec![0, 1, 2, 3]);
+        assert!(found.rms_relative < 0.011);
+        assert!(found.is_unique());
+        // Out of the scale tolerance
+        assert!(match_asterism(&image_of(&catalog, &[0, 1, 2, 3], 0.8, 1.05), &catalog, &TOLERANCE).is_none());
+    }
+
+    #[test]
+    fn breaks_the_brightness_order_within_the_slack() {
+        // Star 1 is half a magnitude fainter than star 2 in the catalog, brighter in the image
+        let catalog = sky_stars(&[(0.0, 0.0, 3.0), (0.6, 0.2, 4.5), (-0.3, 0.5, 4.0), (0.2, -0.7, 5.0)]);
+        let mut image = image_of(&catalog, &[0, 1, 2, 3], 0.3, 1.0);
+        image[1].magnitude = image[2].magnitude - 0.1;
+        let found = match_asterism(&image, &catalog, &TOLERANCE).unwrap();
+        assert_eq!(matched_ids(&catalog, &found), vec![0, 1, 2, 3]);
+        let strict = AsterismTolerance { magnitude_slack: 0.0, ..TOLERANCE };
+        assert!(match_asterism(&image, &catalog, &strict).is_none());
+    }
+
+    #[test]
+    fn reports_ambiguous_templates() {
+        // Two copies of the same triangle, with the same magnitudes
+        let catalog = sky_stars(&[(0.0, 0.0, 3.0), (0.5, 0.1, 3.5), (0.1, 0.4, 4.0),
+            (2.0, 1.0, 3.0), (2.5, 1.1, 3.5), (2.1, 1.4, 4.0)]);
+        let image = image_of(&catalog, &[0, 1, 2], 1.5, 1.0);
+        let found = match_asterism(&image, &catalog, &TOLERANCE).unwrap();
+        assert!(found.candidates >= 2);
+        assert!(found.runner_up_rms.is_some());
+        assert!(!found.is_unique());
+    }
+}
@@ -0,0 +1,178 @@
+use alloc::{string::String, vec::Vec};
+use core::{convert::TryInto, fmt};
+
+use crate::angle::{Degrees, Hours};
+use crate::limits::BrightestStars;
+use crate::polygon::Star;
+
+/// Mini Gaia DR2 header: three strings, each a length byte and 255 bytes
+pub const GAIA_HEADER_SIZE: usize = 3 * 256;
+
+/// Mini Gaia DR2 record size in bytes
+pub const GAIA_RECORD_SIZE: usize = 28;
+
+/// Mini Gaia DR2 catalog epoch (Julian year), when the header does not state it
+pub const GAIA_DR2_EPOCH: f64 = 2015.5;
+
+/// Problem found reading a Mini Gaia DR2 database
+#[derive(Debug)]
+pub enum CatalogError {
+    ShortHeader { size: usize },                                        // Fewer bytes than the header
+    TruncatedRecord { offset: u64 },                                    // Partial record at the end
+    CorruptRecord { record: u64, offset: u64, problem: &'static str }, // Invalid decoded values
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CatalogError::ShortHeader { size } =>
+                write!(f, "{} bytes, shorter than the {} bytes header", size, GAIA_HEADER_SIZE),
+            CatalogError::TruncatedRecord { offset } =>
+                write!(f, "truncated record at byte offset {}", offset),
+            CatalogError::CorruptRecord { record, offset, problem } =>
+                write!(f, "corrupt record {} at byte offset {}: {}", record, offset, problem),
+        }
+    }
+}
+
+/// Reads the three Mini Gaia DR2 header strings
+pub fn gaia_headers(bytes: &[u8; GAIA_HEADER_SIZE]) -> Vec<String> {
+    bytes.chunks_exact(256)
+        .map(|header| {
+            let length = header[0] as usize;
+            String::from_utf8_lossy(&header[1..1 + length]).into_owned()
+        })
+        .collect()
+}
+
+/// Catalog epoch from a header string such as "epoch=2015.5" or "EPOCH=J2015.5"
+pub fn gaia_epoch(headers: &[String]) -> f64 {
+    for header in headers {
+        let mut parts = header.splitn(2, '=');
+        let key = parts.next().unwrap_or("").trim();
+        if let Some(value) = parts.next() {
+            if key.eq_ignore_ascii_case("epoch") {
+                if let Ok(epoch) = value.trim().trim_start_matches('J').parse::<f64>() {
+                    return epoch;
+                }
+            }
+        }
+    }
+    GAIA_DR2_EPOCH
+}
+
+// Mini Gaia DR2 record struct (28 bytes, little endian)
+// #[repr(C)]
+// struct DbStar {
+//     id: u64,
+//     ra: f64,
+//     dec: f64,
+//     magnitude: f32
+// }
+
+/// Converts a Mini Gaia DR2 record to a star. `n` is the record number.
+pub fn gaia_record_to_star(n: u64, record: &[u8]) -> Star {
+    let ra = Hours(f64::from_le_bytes(record[8..16].try_into().unwrap()) / 360.0 * 24.0); // Convert from degrees to hours
+    let dec = Degrees(f64::from_le_bytes(record[16..24].try_into().unwrap()));
+    Star::sky(n, u64::from_le_bytes(record[0..8].try_into().unwrap()), ra, dec, f32::from_le_bytes(record[24..28].try_into().unwrap()) as f64)
+}
+
+/// Checks that a decoded star has finite, in range coordinates and magnitude
+pub fn gaia_star_problem(star: &Star) -> Option<&'static str> {
+    let (ra, dec) = (star.ra(), star.dec());
+    if !(ra.0 >= 0.0 && ra.0 < 24.0) {
+        Some("R.A. out of range or NaN")
+    } else if !(dec.0 >= -90.0 && dec.0 <= 90.0) {
+        Some("Dec out of range or NaN")
+    } else if !(star.magnitude > -5.0 && star.magnitude < 30.0) {
+        Some("magnitude out of range or NaN")
+    } else {
+        None
+    }
+}
+
+/// Byte offset of a record in the Mini Gaia DR2 database
+pub fn gaia_record_offset(n: u64) -> u64 {
+    GAIA_HEADER_SIZE as u64 + n * GAIA_RECORD_SIZE as u64
+}
+
+/// Reads the stars of a Mini Gaia DR2 database held in memory, keeping those accepted by `filter`,
+/// sorted by magnitude. At most `max_stars` of the brightest are kept. Short, truncated or corrupt
+/// databases are rejected, with the byte offset of the first bad record.
+pub fn gaia_stars_from_bytes<F>(bytes: &[u8], filter: F, max_stars: Option<usize>) -> Result<Vec<Star>, CatalogError>
+where
+    F: Fn(&Star) -> bool,
+{
+    if bytes.len() < GAIA_HEADER_SIZE {
+        return Err(CatalogError::ShortHeader { size: bytes.len() });
+    }
+    let records = bytes[GAIA_HEADER_SIZE..].chunks_exact(GAIA_RECORD_SIZE);
+    if !records.remainder().is_empty() {
+        let complete = ((bytes.len() - GAIA_HEADER_SIZE) / GAIA_RECORD_SIZE) as u64;
+        return Err(CatalogError::TruncatedRecord { offset: gaia_record_offset(complete) });
+    }
+    let mut star_list = BrightestStars::new(max_stars);
+    for (n, record) in records.enumerate() {
+        let star = gaia_record_to_star(n as u64, record);
+        if let Some(problem) = gaia_star_problem(&star) {
+            return Err(CatalogError::CorruptRecord { record: n as u64, offset: gaia_record_offset(n as u64), problem });
+        }
+        if filter(&star) {
+            star_list.push(star);
+        }
+    }
+    Ok(star_list.into_sorted())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn record(ra_deg: f64, dec_deg: f64, magnitude: f32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&42_u64.to_le_bytes());
+        bytes.extend_from_slice(&ra_deg.to_le_bytes());
+        bytes.extend_from_slice(&dec_deg.to_le_bytes());
+        bytes.extend_from_slice(&magnitude.to_le_bytes());
+        bytes
+    }
+
+    fn database(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = vec![0_u8; GAIA_HEADER_SIZE];
+        for record in records {
+            bytes.extend_from_slice(record);
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_sorted_stars() {
+        let bytes = database(&[record(10.0, 5.0, 9.0), record(20.0, -5.0, 7.0)]);
+        let stars = gaia_stars_from_bytes(&bytes, |_| true, None).unwrap();
+        assert_eq!(stars.len(), 2);
+        assert_eq!(stars[0].magnitude, 7.0);
+    }
+
+    #[test]
+    fn rejects_short_header() {
+        assert!(matches!(gaia_stars_from_bytes(&[0_u8; 100], |_| true, None), Err(CatalogError::ShortHeader { size: 100 })));
+    }
+
+    #[test]
+    fn rejects_truncated_record() {
+        let mut bytes = database(&[record(10.0, 5.0, 9.0)]);
+        bytes.extend_from_slice(&[0_u8; 5]);
+        let offset = (GAIA_HEADER_SIZE + GAIA_RECORD_SIZE) as u64;
+        assert!(matches!(gaia_stars_from_bytes(&bytes, |_| true, None), Err(CatalogError::TruncatedRecord { offset: o }) if o == offset));
+    }
+
+    #[test]
+    fn rejects_non_finite_values() {
+        for bad in [record(f64::NAN, 5.0, 9.0), record(10.0, f64::INFINITY, 9.0), record(10.0, 5.0, f32::NAN)] {
+            let bytes = database(&[record(10.0, 5.0, 9.0), bad]);
+            let offset = (GAIA_HEADER_SIZE + GAIA_RECORD_SIZE) as u64;
+            assert!(matches!(gaia_stars_from_bytes(&bytes, |_| true, Some(1)), Err(CatalogError::CorruptRecord { record: 1, offset: o, .. }) if o == offset));
+        }
+    }
+}
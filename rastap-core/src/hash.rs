@@ -0,0 +1,221 @@
+use alloc::vec::Vec;
+use log::{debug};
+
+use crate::kdtree::CodeTree;
+use crate::polygon::{self, Polygon, PolygonLimits, PolygonMatch, PolygonShape, Star, Tolerance, MAX_POLYGON_CONNECTIONS, TOLERANCE_SIGMAS};
+
+/// Stars of a hashed quad
+pub const QUAD_STARS: usize = 4;
+/// Values of a quad code: the positions of its two inner stars
+pub const CODE_DIMENSIONS: usize = 4;
+/// Code value difference accepted when the centroid error is unknown
+pub const CODE_TOLERANCE: f64 = 0.01;
+/// Tightest and loosest code value difference derived from the centroid error
+pub const MIN_CODE_TOLERANCE: f64 = 0.001;
+pub const MAX_CODE_TOLERANCE: f64 = 0.05;
+
+/// Puts a code in its canonical form, so the order of the stars does not change it:
+/// C left of D, and C and D closer to A than to B on average
+fn canonical(code: [f64; CODE_DIMENSIONS]) -> [f64; CODE_DIMENSIONS] {
+    let mut code = code;
+    if code[0] + code[2] > 1.0 {
+        // Swap A and B: (x, y) -> (1 - x, 1 - y)
+        code = [1.0 - code[0], 1.0 - code[1], 1.0 - code[2], 1.0 - code[3]];
+    }
+    if code[0] > code[2] {
+        code = [code[2], code[3], code[0], code[1]];
+    }
+    code
+}
+
+/// Geometric hash code of four stars (astrometry.net quads). The two most separated stars, A and B, set a frame
+/// where A is (0, 0) and B is (1, 1), and the code is the position of the other two in that frame. It does not
+/// change with translation, rotation and scale. Positions are plane coordinates, as in the polygon matcher.
+pub fn quad_code(star_list: &[Star], stars: &[usize]) -> [f64; CODE_DIMENSIONS] {
+    let position = |i: usize| (star_list[stars[i]].ra_rad.0, star_list[stars[i]].dec_rad.0);
+    // Most separated pair
+    let (mut a, mut b, mut longest) = (0, 1, -1.0);
+    for i in 0..QUAD_STARS {
+        for j in (i + 1)..QUAD_STARS {
+            let (pi, pj) = (position(i), position(j));
+            let distance = (pj.0 - pi.0) * (pj.0 - pi.0) + (pj.1 - pi.1) * (pj.1 - pi.1);
+            if distance > longest {
+                (a, b, longest) = (i, j, distance);
+            }
+        }
+    }
+    let (pa, pb) = (position(a), position(b));
+    let ab = (pb.0 - pa.0, pb.1 - pa.1);
+    // Divide by AB and multiply by 1 + i, as complex numbers, so B lands on (1, 1)
+    let frame = |i: usize| {
+        let p = position(i);
+        let d = (p.0 - pa.0, p.1 - pa.1);
+        let z = ((d.0 * ab.0 + d.1 * ab.1) / longest, (d.1 * ab.0 - d.0 * ab.1) / longest);
+        (z.0 - z.1, z.0 + z.1)
+    };
+    let mut inner = (0..QUAD_STARS).filter(|&i| i != a && i != b).map(frame);
+    let (c, d) = (inner.next().unwrap(), inner.next().unwrap());
+    canonical([c.0, c.1, d.0, d.1])
+}
+
+/// Code of the mirror image of a quad. Mirroring across the AB line swaps the coordinates of the frame.
+pub fn mirror_code(code: [f64; CODE_DIMENSIONS]) -> [f64; CODE_DIMENSIONS] {
+    canonical([code[1], code[0], code[3], code[2]])
+}
+
+/// Code stored in a hashed polygon
+fn polygon_code(polygon: &Polygon) -> [f64; CODE_DIMENSIONS] {
+    let mut code = [0.0; CODE_DIMENSIONS];
+    code.copy_from_slice(&polygon.lengths[..CODE_DIMENSIONS]);
+    code
+}
+
+/// Error of the code values of a quad, per unit of vertex coordinate error. The code frame divides positions
+/// by AB and scales them by sqrt(2); the inner star and the AB pair errors add about as much again.
+fn code_error(star_list: &[Star], stars: &[usize]) -> f64 {
+    let position = |i: usize| (star_list[i].ra_rad.0, star_list[i].dec_rad.0);
+    let longest = stars.iter().enumerate()
+        .flat_map(|(i, &a)| stars[i + 1..].iter().map(move |&b| libm::hypot(position(b).0 - position(a).0, position(b).1 - position(a).1)))
+        .fold(0.0, f64::max);
+    2.0 / longest
+}
+
+/// Largest code value difference accepted for an image quad. Centroid errors move the stars of small
+/// quads more, relative to their size, so they get a looser tolerance.
+pub fn code_tolerance(tolerance: &Tolerance, polygon: &Polygon) -> f64 {
+    match tolerance.centroid_error {
+        Some(error) => (TOLERANCE_SIGMAS * error * polygon.length_error).clamp(MIN_CODE_TOLERANCE, MAX_CODE_TOLERANCE),
+        None => CODE_TOLERANCE,
+    }
+}
+
+/// Find the quads of a star list, like the polygon matcher, with their hash codes stored in place of the lengths
+/// and the code error in place of the length error
+pub fn find_polygons(star_list: &[Star], limits: &PolygonLimits, shape: &PolygonShape) -> Option<Vec<Polygon>> {
+    let mut polygons = polygon::find_polygons(star_list, QUAD_STARS, limits, shape)?;
+    for polygon in polygons.iter_mut() {
+        let code = quad_code(star_list, polygon.star_list());
+        polygon.lengths = [0.0; MAX_POLYGON_CONNECTIONS];
+        polygon.lengths[..CODE_DIMENSIONS].copy_from_slice(&code);
+        polygon.length_error = code_error(star_list, polygon.star_list());
+    }
+    Some(polygons)
+}
+
+/// Compare image and star database quad codes until `expired` returns true (checked before each image quad).
+/// The codes accepted differ by up to the code tolerance on every value. Mirrored quads are
+/// searched too, as the image may be flipped. Returns the similar quads found and whether the search was completed.
+pub fn find_fit_until<F: FnMut() -> bool>(image_polygons: &[Polygon], star_polygons: &[Polygon], tolerance: &Tolerance, mut expired: F) -> (Vec<PolygonMatch>, bool) {
+    debug!("Hash fit > Searching similar quads");
+    let tree = CodeTree::with_dimensions(star_polygons, QUAD_STARS, CODE_DIMENSIONS);
+    let mut matches = Vec::new();
+    for (image_index, image_pol) in image_polygons.iter().enumerate() {
+        if expired() {
+            debug!("Hash fit > Stopped at image quad {} of {}", image_index, image_polygons.len());
+            return (matches, false);
+        }
+        let max_distance = code_tolerance(tolerance, image_pol);
+        let code = polygon_code(image_pol);
+        // Closest code distance of each star quad, direct or mirrored
+        let mut found: Vec<(usize, f64)> = Vec::new();
+        for code in [code, mirror_code(code)].iter() {
+            let low: Vec<f64> = code.iter().map(|value| value - max_distance).collect();
+            let high: Vec<f64> = code.iter().map(|value| value + max_distance).collect();
+            for star_index in tree.range(&low, &high) {
+                let star_code = polygon_code(&star_polygons[star_index]);
+                let distance = (0..CODE_DIMENSIONS).map(|i| (code[i] - star_code[i]).abs()).fold(0.0, f64::max);
+                match found.iter_mut().find(|(index, _)| *index == star_index) {
+                    Some(pair) => pair.1 = pair.1.min(distance),
+                    None => found.push((star_index, distance)),
+                }
+            }
+        }
+        for (star_index, distance) in found {
+            debug!("Hash fit > Similar quad found image:{} star:{} distance:{}", image_index, star_index, distance);
+            matches.push(PolygonMatch { image_polygon: image_index, star_polygon: star_index, similarity: 1.0 - distance });
+        }
+    }
+    debug!("Found {} similar quads", matches.len());
+    (matches, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::Radians;
+
+    fn quad(points: &[(f64, f64)]) -> Vec<Star> {
+        points.iter().enumerate().map(|(i, p)| Star::image_plane(i as u64, Radians(p.0), Radians(p.1), 0.0)).collect()
+    }
+
+    #[test]
+    fn code_is_similarity_invariant() {
+        let points = [(0.0, 0.0), (0.3, 0.1), (0.4, 0.6), (1.0, 0.8)];
+        let code = quad_code(&quad(&points), &[0, 1, 2, 3]);
+        // Rotated by 0.7 radians, scaled, shifted and with the stars in another order
+        let (sin, cos) = (libm::sin(0.7), libm::cos(0.7));
+        let moved: Vec<(f64, f64)> = points.iter().rev()
+            .map(|p| (2.5 * (cos * p.0 - sin * p.1) + 3.0, 2.5 * (sin * p.0 + cos * p.1) - 1.0))
+            .collect();
+        let moved_code = quad_code(&quad(&moved), &[0, 1, 2, 3]);
+        for i in 0..CODE_DIMENSIONS {
+            assert!((code[i] - moved_code[i]).abs() < 1e-12);
+        }
+        // A mirror image has another code, unless mirrored back
+        let mirrored: Vec<(f64, f64)> = points.iter().map(|p| (-p.0, p.1)).collect();
+        let mirrored_code = quad_code(&quad(&mirrored), &[0, 1, 2, 3]);
+        assert!((0..CODE_DIMENSIONS).any(|i| (code[i] - mirrored_code[i]).abs() > 1e-3));
+        let unmirrored = mirror_code(mirrored_code);
+        for i in 0..CODE_DIMENSIONS {
+            assert!((code[i] - unmirrored[i]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn finds_mirrored_quads() {
+        let limits = PolygonLimits { grid_cells: 1, max_per_cell: None, max_polygons: None };
+        let shape = PolygonShape { min_edge_ratio: 0.0, min_area_ratio: 0.0 };
+        let points = [(0.0, 0.0), (0.3, 0.1), (0.4, 0.6), (1.0, 0.8), (5.0, 5.0), (5.2, 5.9), (5.9, 5.4), (6.1, 6.0)];
+        let stars = quad(&points);
+        let image = quad(&points.iter().map(|p| (0.5 * p.1, 0.5 * p.0)).collect::<Vec<(f64, f64)>>());
+        let star_polygons = find_polygons(&stars, &limits, &shape).unwrap();
+        let image_polygons = find_polygons(&image, &limits, &shape).unwrap();
+        let (matches, completed) = find_fit_until(&image_polygons, &star_polygons, &Tolerance { centroid_error: None }, || false);
+        assert!(completed);
+        // Each image quad matches the star quad built for the same star
+        for (i, image_pol) in image_polygons.iter().enumerate() {
+            assert!(matches.iter().any(|m| m.image_polygon == i
+                && star_polygons[m.star_polygon].star_index == image_pol.star_index
+                && m.similarity > 1.0 - 1e-9));
+        }
+    }
+
+    #[test]
+    fn code_tolerance_covers_the_centroid_error() {
+        let limits = PolygonLimits { grid_cells: 1, max_per_cell: None, max_polygons: None };
+        let shape = PolygonShape { min_edge_ratio: 0.0, min_area_ratio: 0.0 };
+        let points = [(0.0, 0.0), (0.3, 0.1), (0.4, 0.6), (1.0, 0.8)];
+        let error = 0.002;
+        let polygon = &find_polygons(&quad(&points), &limits, &shape).unwrap()[0];
+        let tolerance = Tolerance { centroid_error: Some(error) };
+        let max_distance = code_tolerance(&tolerance, polygon);
+        // Every star moved by the centroid error, in turning directions
+        let mut seed = 12345_u64;
+        for _ in 0..100 {
+            let moved: Vec<(f64, f64)> = points.iter().map(|p| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                let angle = (seed >> 11) as f64 / (1_u64 << 53) as f64 * core::f64::consts::TAU;
+                (p.0 + error * libm::cos(angle), p.1 + error * libm::sin(angle))
+            }).collect();
+            let moved_code = quad_code(&quad(&moved), &[0, 1, 2, 3]);
+            let distance = (0..CODE_DIMENSIONS).map(|i| (polygon_code(polygon)[i] - moved_code[i]).abs()).fold(0.0, f64::max);
+            assert!(distance < max_distance, "{} >= {}", distance, max_distance);
+        }
+        // Smaller quads get a looser tolerance, up to the loosest one
+        let small: Vec<(f64, f64)> = points.iter().map(|p| (0.1 * p.0, 0.1 * p.1)).collect();
+        let small_polygon = &find_polygons(&quad(&small), &limits, &shape).unwrap()[0];
+        assert!(code_tolerance(&tolerance, small_polygon) > max_distance);
+        assert_eq!(code_tolerance(&Tolerance { centroid_error: Some(1.0) }, polygon), MAX_CODE_TOLERANCE);
+        assert_eq!(code_tolerance(&Tolerance { centroid_error: None }, polygon), CODE_TOLERANCE);
+    }
+}
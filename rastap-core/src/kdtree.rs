@@ -0,0 +1,142 @@
+use alloc::vec::Vec;
+
+use crate::polygon::{polygon_connections, Polygon};
+
+/// Static k-d tree over polygon codes: the normalized connection lengths, as points in code space.
+/// Each node is the median of a range of `indices`, split on the axis `depth % dimensions`.
+pub struct CodeTree<'a> {
+    polygons: &'a [Polygon],
+    indices: Vec<usize>,
+    dimensions: usize,
+}
+
+impl<'a> CodeTree<'a> {
+    /// Builds the tree of the polygons with the given number of vertices
+    pub fn new(polygons: &'a [Polygon], edges: usize) -> CodeTree<'a> {
+        // The longest length is always 1.0, it does not tell polygons apart
+        CodeTree::with_dimensions(polygons, edges, (polygon_connections(edges) - 1).max(1))
+    }
+
+    /// Builds the tree of the polygons with the given number of vertices, over the first `dimensions` code values
+    pub fn with_dimensions(polygons: &'a [Polygon], edges: usize, dimensions: usize) -> CodeTree<'a> {
+        let indices = (0..polygons.len()).filter(|&i| polygons[i].edges == edges).collect();
+        let mut tree = CodeTree { polygons, indices, dimensions };
+        let len = tree.indices.len();
+        tree.build(0, len, 0);
+        tree
+    }
+
+    /// Number of dimensions of the code space
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Number of polygons in the tree
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Whether the tree holds no polygons
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn build(&mut self, start: usize, end: usize, depth: usize) {
+        if end - start <= 1 {
+            return;
+        }
+        let axis = depth % self.dimensions;
+        let mid = (start + end) / 2;
+        let polygons = self.polygons;
+        self.indices[start..end].select_nth_unstable_by(mid - start, |&a, &b| {
+            polygons[a].lengths[axis].partial_cmp(&polygons[b].lengths[axis]).unwrap()
+        });
+        self.build(start, mid, depth + 1);
+        self.build(mid + 1, end, depth + 1);
+    }
+
+    /// Indices of the polygons with codes inside the box [low, high] on every dimension, in ascending order
+    pub fn range(&self, low: &[f64], high: &[f64]) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.search(0, self.indices.len(), 0, low, high, &mut found);
+        found.sort_unstable();
+        found
+    }
+
+    fn search(&self, start: usize, end: usize, depth: usize, low: &[f64], high: &[f64], found: &mut Vec<usize>) {
+        if start >= end {
+            return;
+        }
+        let axis = depth % self.dimensions;
+        let mid = (start + end) / 2;
+        let index = self.indices[mid];
+        let code = &self.polygons[index].lengths[..self.dimensions];
+        if (0..self.dimensions).all(|i| code[i] >= low[i] && code[i] <= high[i]) {
+            found.push(index);
+        }
+        // Visit only the halves the box reaches
+        if low[axis] <= code[axis] {
+            self.search(start, mid, depth + 1, low, high, found);
+        }
+        if high[axis] >= code[axis] {
+            self.search(mid + 1, end, depth + 1, low, high, found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::Radians;
+    use crate::polygon::{MAX_POLYGON_CONNECTIONS, MAX_POLYGON_EDGES};
+
+    /// Polygons with pseudo-random codes, a fifth of them with another number of vertices
+    fn random_polygons(count: usize) -> Vec<Polygon> {
+        let mut seed = 12345_u64;
+        let mut random = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 11) as f64 / (1_u64 << 53) as f64
+        };
+        (0..count).map(|i| {
+            let mut lengths = [0.0; MAX_POLYGON_CONNECTIONS];
+            for length in lengths.iter_mut() {
+                *length = random();
+            }
+            Polygon {
+                star_index: i,
+                edges: if i % 5 == 0 { 5 } else { 4 },
+                stars: [0; MAX_POLYGON_EDGES],
+                lengths,
+                center_ra_rad: Radians(0.0),
+                center_dec_rad: Radians(0.0),
+                length_error: 0.0,
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn range_matches_brute_force() {
+        let polygons = random_polygons(500);
+        let tree = CodeTree::new(&polygons, 4);
+        assert_eq!(tree.dimensions(), 5);
+        assert_eq!(tree.len(), 400);
+        for (n, center) in polygons.iter().enumerate().take(50) {
+            let half_width = 0.05 + 0.01 * n as f64;
+            let low: Vec<f64> = center.lengths[..5].iter().map(|length| length - half_width).collect();
+            let high: Vec<f64> = center.lengths[..5].iter().map(|length| length + half_width).collect();
+            let expected: Vec<usize> = (0..polygons.len())
+                .filter(|&i| polygons[i].edges == 4)
+                .filter(|&i| (0..5).all(|d| polygons[i].lengths[d] >= low[d] && polygons[i].lengths[d] <= high[d]))
+                .collect();
+            assert_eq!(tree.range(&low, &high), expected);
+        }
+    }
+
+    #[test]
+    fn empty_tree_finds_nothing() {
+        let polygons = random_polygons(10);
+        let tree = CodeTree::new(&polygons, 6);
+        assert!(tree.is_empty());
+        assert!(tree.range(&[0.0; 14], &[1.0; 14]).is_empty());
+    }
+}
@@ -0,0 +1,16 @@
+//! Star polygon geometry and matching of rastap, without std (alloc only),
+//! so the matcher can run on embedded star trackers.
+#![no_std]
+
+extern crate alloc;
+
+pub mod angle;
+pub mod asterism;
+pub mod catalog;
+pub mod hash;
+pub mod kdtree;
+pub mod limits;
+pub mod matcher;
+pub mod output;
+pub mod polygon;
+pub mod solution;
@@ -0,0 +1,96 @@
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::polygon::{Polygon, PolygonLimits, Star};
+
+/// Per-solve resource limits, for applications that must bound the work of each request.
+/// Exceeding a limit drops the faintest stars and polygons instead of failing. None: unlimited.
+#[derive(Clone, Debug, Default)]
+pub struct SolverLimits {
+    pub max_memory_mb: Option<usize>,     // Memory for the star lists and their polygons
+    pub max_catalog_stars: Option<usize>, // Catalog stars kept, brightest first
+    pub max_image_stars: Option<usize>,   // Image stars kept, brightest first
+    pub max_quads: Option<usize>,         // Polygons built from each star list
+}
+
+impl SolverLimits {
+    /// Stars of a list that fit in its half of the memory: each star, its reading buffer slot,
+    /// its polygon and the polygon search index
+    fn memory_stars(&self) -> Option<usize> {
+        let bytes_per_star = 2 * size_of::<Star>() + size_of::<Polygon>() + size_of::<usize>();
+        self.max_memory_mb.map(|mb| mb * 1024 * 1024 / 2 / bytes_per_star)
+    }
+
+    /// Maximum catalog stars, from the star cap and the memory
+    pub fn catalog_stars(&self) -> Option<usize> {
+        min_limit(self.max_catalog_stars, self.memory_stars())
+    }
+
+    /// Maximum image stars, from the star cap and the memory
+    pub fn image_stars(&self) -> Option<usize> {
+        min_limit(self.max_image_stars, self.memory_stars())
+    }
+
+    /// Tightens the polygon limits with the polygon cap and the memory
+    pub fn polygon_limits(&self, limits: PolygonLimits) -> PolygonLimits {
+        let max_polygons = min_limit(limits.max_polygons, min_limit(self.max_quads, self.memory_stars()));
+        PolygonLimits { max_polygons, ..limits }
+    }
+}
+
+/// Tightest of two optional limits
+fn min_limit(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Truncates a list sorted by magnitude to its brightest `max` items. Returns the number dropped.
+pub fn truncate_brightest<T>(list: &mut Vec<T>, max: Option<usize>) -> usize {
+    match max {
+        Some(max) if list.len() > max => {
+            let dropped = list.len() - max;
+            list.truncate(max);
+            dropped
+        }
+        _ => 0,
+    }
+}
+
+/// Star list that keeps only the brightest stars while it is filled, so reading a catalog holds
+/// at most twice the limit in memory
+pub struct BrightestStars {
+    max_stars: Option<usize>,
+    stars: Vec<Star>,
+}
+
+impl BrightestStars {
+    pub fn new(max_stars: Option<usize>) -> BrightestStars {
+        BrightestStars { max_stars, stars: Vec::new() }
+    }
+
+    /// Adds a star, dropping the faintest ones when the buffer is full
+    pub fn push(&mut self, star: Star) {
+        self.stars.push(star);
+        if let Some(max) = self.max_stars {
+            if self.stars.len() >= 2 * max.max(1) {
+                self.sort_and_truncate(max);
+            }
+        }
+    }
+
+    /// The stars kept, sorted by magnitude
+    pub fn into_sorted(mut self) -> Vec<Star> {
+        let max = self.max_stars.unwrap_or(self.stars.len());
+        self.sort_and_truncate(max);
+        self.stars
+    }
+
+    fn sort_and_truncate(&mut self, max: usize) {
+        // Total order: a NaN magnitude sorts last instead of panicking
+        self.stars.sort_by(|a, b| a.magnitude.total_cmp(&b.magnitude));
+        self.stars.truncate(max);
+    }
+}
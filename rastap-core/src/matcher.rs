@@ -0,0 +1,108 @@
+use alloc::{borrow::ToOwned, boxed::Box, string::String, vec::Vec};
+
+use crate::hash;
+use crate::polygon::{self, Polygon, PolygonLimits, PolygonMatch, PolygonShape, Star, Tolerance};
+
+/// Matcher algorithm names, as accepted by the command line
+pub const MATCHER_NAMES: &[&str] = &["quads", "triangles", "hash"];
+
+/// Star pattern matching algorithm
+pub trait Matcher {
+    /// Algorithm name
+    fn name(&self) -> &str;
+
+    /// Stars per pattern
+    fn edges(&self) -> usize;
+
+    /// Find the patterns formed by a star list
+    fn find_polygons(&self, star_list: &[Star]) -> Option<Vec<Polygon>>;
+
+    /// Compare image and star database patterns
+    fn find_fit(&self, image_polygons: &[Polygon], star_polygons: &[Polygon]) -> Vec<PolygonMatch>;
+
+    /// Compare patterns until `expired` returns true. Returns the matches and whether the search was completed.
+    fn find_fit_until(&self, image_polygons: &[Polygon], star_polygons: &[Polygon], expired: &mut dyn FnMut() -> bool) -> (Vec<PolygonMatch>, bool);
+}
+
+/// ASTAP polygon matcher. Each star and its closest neighbours form a polygon.
+pub struct PolygonMatcher {
+    name: String,
+    edges: usize,
+    limits: PolygonLimits,
+    shape: PolygonShape,
+    tolerance: Tolerance,
+}
+
+impl PolygonMatcher {
+    pub fn new(name: &str, edges: usize, limits: PolygonLimits, shape: PolygonShape, tolerance: Tolerance) -> PolygonMatcher {
+        PolygonMatcher { name: name.to_owned(), edges, limits, shape, tolerance }
+    }
+}
+
+impl Matcher for PolygonMatcher {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn edges(&self) -> usize {
+        self.edges
+    }
+
+    fn find_polygons(&self, star_list: &[Star]) -> Option<Vec<Polygon>> {
+        polygon::find_polygons(star_list, self.edges, &self.limits, &self.shape)
+    }
+
+    fn find_fit(&self, image_polygons: &[Polygon], star_polygons: &[Polygon]) -> Vec<PolygonMatch> {
+        polygon::find_fit(image_polygons, star_polygons, &self.tolerance)
+    }
+
+    fn find_fit_until(&self, image_polygons: &[Polygon], star_polygons: &[Polygon], expired: &mut dyn FnMut() -> bool) -> (Vec<PolygonMatch>, bool) {
+        polygon::find_fit_until(image_polygons, star_polygons, &self.tolerance, expired)
+    }
+}
+
+/// Geometric hash matcher (astrometry.net). Quads of neighbour stars are compared by a code that does not
+/// depend on their position, rotation and scale, mirrored quads included.
+pub struct HashMatcher {
+    limits: PolygonLimits,
+    shape: PolygonShape,
+    tolerance: Tolerance,
+}
+
+impl HashMatcher {
+    pub fn new(limits: PolygonLimits, shape: PolygonShape, tolerance: Tolerance) -> HashMatcher {
+        HashMatcher { limits, shape, tolerance }
+    }
+}
+
+impl Matcher for HashMatcher {
+    fn name(&self) -> &str {
+        "hash"
+    }
+
+    fn edges(&self) -> usize {
+        hash::QUAD_STARS
+    }
+
+    fn find_polygons(&self, star_list: &[Star]) -> Option<Vec<Polygon>> {
+        hash::find_polygons(star_list, &self.limits, &self.shape)
+    }
+
+    fn find_fit(&self, image_polygons: &[Polygon], star_polygons: &[Polygon]) -> Vec<PolygonMatch> {
+        hash::find_fit_until(image_polygons, star_polygons, &self.tolerance, || false).0
+    }
+
+    fn find_fit_until(&self, image_polygons: &[Polygon], star_polygons: &[Polygon], expired: &mut dyn FnMut() -> bool) -> (Vec<PolygonMatch>, bool) {
+        hash::find_fit_until(image_polygons, star_polygons, &self.tolerance, expired)
+    }
+}
+
+/// Gets a matcher by its name
+pub fn from_name(name: &str, limits: PolygonLimits, shape: PolygonShape, tolerance: Tolerance) -> Option<Box<dyn Matcher>> {
+    match name {
+        "quads" => Some(Box::new(PolygonMatcher::new(name, polygon::POLYGON_EDGES, limits, shape, tolerance))),
+        "triangles" => Some(Box::new(PolygonMatcher::new(name, 3, limits, shape, tolerance))),
+        "hash" => Some(Box::new(HashMatcher::new(limits, shape, tolerance))),
+        _ => None,
+    }
+}
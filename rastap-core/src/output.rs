@@ -0,0 +1,79 @@
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+use crate::polygon::{ImageStar, Star, StarMatch};
+use crate::solution::Solution;
+
+/// Writer of the star matches, run after matching
+pub trait OutputWriter {
+    /// Writer name, for error messages
+    fn name(&self) -> &str;
+
+    /// Writes or publishes the star matches and the plate solution, if found
+    fn write(&self, image_star_list: &[ImageStar], star_list: &[Star], matches: &[StarMatch], solution: Option<&Solution>) -> Result<(), String>;
+}
+
+/// Output writers run after matching, in the order they were registered
+#[derive(Default)]
+pub struct OutputRegistry {
+    writers: Vec<Box<dyn OutputWriter>>,
+}
+
+impl OutputRegistry {
+    pub fn new() -> OutputRegistry {
+        OutputRegistry::default()
+    }
+
+    /// Adds a writer, run after the ones already registered
+    pub fn register(&mut self, writer: Box<dyn OutputWriter>) {
+        self.writers.push(writer);
+    }
+
+    /// Names of the registered writers
+    pub fn names(&self) -> Vec<&str> {
+        self.writers.iter().map(|writer| writer.name()).collect()
+    }
+
+    /// Runs every writer, even after one fails. Returns the name and error of the failed ones.
+    pub fn write_all(&self, image_star_list: &[ImageStar], star_list: &[Star], matches: &[StarMatch], solution: Option<&Solution>) -> Vec<(&str, String)> {
+        self.writers.iter()
+            .filter_map(|writer| writer.write(image_star_list, star_list, matches, solution).err().map(|err| (writer.name(), err)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{borrow::ToOwned, rc::Rc, vec};
+    use core::cell::RefCell;
+
+    struct Recorder {
+        name: &'static str,
+        fail: bool,
+        calls: Rc<RefCell<Vec<(&'static str, usize)>>>,
+    }
+
+    impl OutputWriter for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn write(&self, _image_star_list: &[ImageStar], _star_list: &[Star], matches: &[StarMatch], _solution: Option<&Solution>) -> Result<(), String> {
+            self.calls.borrow_mut().push((self.name, matches.len()));
+            if self.fail { Err("disk full".to_owned()) } else { Ok(()) }
+        }
+    }
+
+    #[test]
+    fn runs_every_writer_in_order() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut registry = OutputRegistry::new();
+        registry.register(Box::new(Recorder { name: "first", fail: true, calls: calls.clone() }));
+        registry.register(Box::new(Recorder { name: "second", fail: false, calls: calls.clone() }));
+        assert_eq!(registry.names(), vec!["first", "second"]);
+        let matches = vec![StarMatch { image_star: 0, star: 0, votes: 1 }];
+        let errors = registry.write_all(&[], &[], &matches, None);
+        assert_eq!(errors, vec![("first", "disk full".to_owned())]);
+        assert_eq!(*calls.borrow(), vec![("first", 1), ("second", 1)]);
+    }
+}
@@ -0,0 +1,599 @@
+use alloc::{borrow::ToOwned, collections::BTreeMap, format, string::String, vec, vec::Vec};
+use log::{debug};
+
+use crate::angle::{Degrees, Hours, Radians};
+use crate::kdtree::CodeTree;
+
+pub const POLYGON_EDGES: usize = 4;
+pub const TOLERANCE: f64 = 0.01;
+/// Minimum edge length ratio of similar polygons
+pub const MIN_SIMILARITY: f64 = 0.99;
+/// Centroid error multiple covered by a tolerance derived from the centroid error
+pub const TOLERANCE_SIGMAS: f64 = 3.0;
+/// Loosest and tightest minimum similarity derived from the centroid error
+pub const MIN_TOLERANCE_SIMILARITY: f64 = 0.95;
+pub const MAX_TOLERANCE_SIMILARITY: f64 = 0.999;
+/// Maximum number of polygon vertices
+pub const MAX_POLYGON_EDGES: usize = 6;
+/// Maximum number of polygon vertex connections
+pub const MAX_POLYGON_CONNECTIONS: usize = MAX_POLYGON_EDGES * (MAX_POLYGON_EDGES - 1) / 2;
+
+/// Star data structure
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Star {
+    pub id: u64,
+    pub db_id: u64,    // Catalogue reference
+    pub ra_rad: Radians,  // R.A. in radians used for matching, after any position correction. Image stars: x offset on the image plane
+    pub dec_rad: Radians, // Dec in radians used for matching, after any position correction. Image stars: y offset on the image plane
+    pub catalog_ra_rad: Radians,  // R.A. in radians as in the catalog (ICRS)
+    pub catalog_dec_rad: Radians, // Dec in radians as in the catalog (ICRS)
+    pub magnitude: f64 // Magnitude
+}
+
+impl Star {
+    /// Catalog star at a sky position
+    pub fn sky(id: u64, db_id: u64, ra: Hours, dec: Degrees, magnitude: f64) -> Star {
+        let (ra_rad, dec_rad) = (Radians::from(ra), Radians::from(dec));
+        Star { id, db_id, ra_rad, dec_rad, catalog_ra_rad: ra_rad, catalog_dec_rad: dec_rad, magnitude }
+    }
+
+    /// Image star at an offset on the image plane, in radians at the image scale. It has no sky position.
+    pub fn image_plane(id: u64, x: Radians, y: Radians, magnitude: f64) -> Star {
+        Star { id, db_id: 0, ra_rad: x, dec_rad: y, catalog_ra_rad: x, catalog_dec_rad: y, magnitude }
+    }
+
+    /// Right Ascension (R.A.) of a catalog star, as in the catalog
+    pub fn ra(&self) -> Hours {
+        Hours::from(self.catalog_ra_rad)
+    }
+
+    /// Declination (Dec) of a catalog star, as in the catalog
+    pub fn dec(&self) -> Degrees {
+        Degrees::from(self.catalog_dec_rad)
+    }
+}
+
+/// Star position in image
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImageStar {
+    pub pixel_x: f64,
+    pub pixel_y: f64,
+    pub magnitude: f64
+}
+
+/// Polygon structure. Stars and lengths are stored inline, so a polygon list is a single flat allocation.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Polygon {
+    pub star_index: usize,
+    pub edges: usize,
+    pub stars: [usize; MAX_POLYGON_EDGES],
+    pub lengths: [f64; MAX_POLYGON_CONNECTIONS],
+    pub center_ra_rad: Radians,
+    pub center_dec_rad: Radians,
+    pub length_error: f64, // Worst relative error of a normalized length, per unit of vertex coordinate error
+}
+
+impl Polygon {
+    /// Star indices of the polygon. The first one is the star the polygon was built for.
+    pub fn star_list(&self) -> &[usize] {
+        &self.stars[..self.edges]
+    }
+
+    /// Lengths of the connections, sorted and normalized by the longest one
+    pub fn length_list(&self) -> &[f64] {
+        &self.lengths[..polygon_connections(self.edges)]
+    }
+}
+
+/// Limits on the number of polygons built from a star list
+#[derive(Clone, Debug)]
+pub struct PolygonLimits {
+    pub grid_cells: usize,             // Grid cells per side laid over the star field
+    pub max_per_cell: Option<usize>,   // Maximum polygons anchored on a grid cell
+    pub max_polygons: Option<usize>,   // Maximum polygons in total
+}
+
+/// Geometric thresholds to reject degenerate polygons
+#[derive(Clone, Debug)]
+pub struct PolygonShape {
+    pub min_edge_ratio: f64, // Minimum shortest/longest edge length ratio
+    pub min_area_ratio: f64, // Minimum area of any three vertices, relative to its longest side squared
+}
+
+/// Similarity tolerance of image polygons
+#[derive(Clone, Debug)]
+pub struct Tolerance {
+    pub centroid_error: Option<f64>, // Image centroid error, in the image star list units. None: fixed MIN_SIMILARITY.
+}
+
+impl Tolerance {
+    /// Minimum similarity of a match of an image polygon. Centroid errors move the short
+    /// connections more, relative to their length, so small polygons get a looser tolerance.
+    pub fn min_similarity(&self, polygon: &Polygon) -> f64 {
+        let error = match self.centroid_error {
+            Some(error) => error,
+            None => return MIN_SIMILARITY,
+        };
+        // Each coordinate difference has the error of two centroids
+        let relative_error = core::f64::consts::SQRT_2 * error * polygon.length_error;
+        (1.0 - TOLERANCE_SIGMAS * relative_error).clamp(MIN_TOLERANCE_SIMILARITY, MAX_TOLERANCE_SIMILARITY)
+    }
+}
+
+/// Pair of similar image and star database polygons
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PolygonMatch {
+    pub image_polygon: usize, // Index in the image polygon list
+    pub star_polygon: usize,  // Index in the star polygon list
+    pub similarity: f64,      // Worst edge length ratio (1.0 = identical)
+}
+
+/// Pair of image and star database stars, from the vertices of similar polygons
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarMatch {
+    pub image_star: usize, // Index in the image star list
+    pub star: usize,       // Index in the star database list
+    pub votes: usize,      // Number of similar polygons supporting the pair
+}
+
+/// Calculate the number of vertex connections of a polygon
+pub fn polygon_connections(polygon: usize) -> usize {
+    (1..polygon).map(|i| polygon - i).sum()
+}
+
+/// Calculate star distance between two stars
+pub fn star_distance_rad(star_a: &Star, star_b: &Star) -> f64 {
+    libm::sqrt((star_b.ra_rad - star_a.ra_rad).abs().0) + libm::sqrt((star_b.dec_rad - star_a.dec_rad).abs().0)
+}
+
+/// Relative error of the star distance per unit of error of each coordinate difference. The distance is
+/// sqrt|dRA| + sqrt|dDec|, so the error of each term is the coordinate error over twice its square root.
+pub fn star_distance_error(star_a: &Star, star_b: &Star) -> f64 {
+    let ra = (star_b.ra_rad - star_a.ra_rad).abs().0.max(f64::MIN_POSITIVE);
+    let dec = (star_b.dec_rad - star_a.dec_rad).abs().0.max(f64::MIN_POSITIVE);
+    0.5 * libm::sqrt(1.0 / ra + 1.0 / dec) / star_distance_rad(star_a, star_b)
+}
+
+/// Grid cell of each star, on a grid of `cells` x `cells` over the star field
+fn star_cells(star_list: &[Star], cells: usize) -> Vec<usize> {
+    let mut min = (f64::MAX, f64::MAX);
+    let mut max = (f64::MIN, f64::MIN);
+    for star in star_list {
+        min = (min.0.min(star.ra_rad.0), min.1.min(star.dec_rad.0));
+        max = (max.0.max(star.ra_rad.0), max.1.max(star.dec_rad.0));
+    }
+    let cell = |value: f64, min: f64, max: f64| {
+        if max > min {
+            (((value - min) / (max - min) * cells as f64) as usize).min(cells - 1)
+        } else {
+            0
+        }
+    };
+    star_list.iter()
+        .map(|star| cell(star.dec_rad.0, min.1, max.1) * cells + cell(star.ra_rad.0, min.0, max.0))
+        .collect()
+}
+
+/// Twice the area of the triangle of three stars, divided by its longest side squared. 0 when collinear.
+fn triangle_area_ratio(a: &Star, b: &Star, c: &Star) -> f64 {
+    let (abx, aby) = ((b.ra_rad - a.ra_rad).0, (b.dec_rad - a.dec_rad).0);
+    let (acx, acy) = ((c.ra_rad - a.ra_rad).0, (c.dec_rad - a.dec_rad).0);
+    let (bcx, bcy) = ((c.ra_rad - b.ra_rad).0, (c.dec_rad - b.dec_rad).0);
+    let longest = (abx * abx + aby * aby).max(acx * acx + acy * acy).max(bcx * bcx + bcy * bcy);
+    if longest == 0.0 {
+        return 0.0;
+    }
+    (abx * acy - aby * acx).abs() / longest
+}
+
+/// Reason why a polygon is degenerate, if it is. `length_vec` must be sorted.
+fn degenerate_polygon(star_list: &[Star], star_vec: &[usize], length_vec: &[f64], shape: &PolygonShape) -> Option<String> {
+    let shortest = length_vec[0];
+    let longest = length_vec[length_vec.len() - 1];
+    if shortest == 0.0 {
+        return Some("coincident stars".to_owned());
+    }
+    if shortest / longest < shape.min_edge_ratio {
+        return Some(format!("edge ratio {} < {}", shortest / longest, shape.min_edge_ratio));
+    }
+    for i in 0..star_vec.len() {
+        for j in (i + 1)..star_vec.len() {
+            for k in (j + 1)..star_vec.len() {
+                let ratio = triangle_area_ratio(&star_list[star_vec[i]], &star_list[star_vec[j]], &star_list[star_vec[k]]);
+                if ratio < shape.min_area_ratio {
+                    return Some(format!("collinear stars {} {} {}, area ratio {}", star_vec[i], star_vec[j], star_vec[k], ratio));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Find polygons of `edges` vertices. For each star, the edges-1 closest stars.
+/// Brighter stars get their polygons first, until the cell or global limits are reached.
+/// Degenerate polygons, by the `shape` thresholds, are discarded.
+pub fn find_polygons(star_list: &[Star], edges: usize, limits: &PolygonLimits, shape: &PolygonShape) -> Option<Vec<Polygon>> {
+    let conn_number = polygon_connections(edges);
+    if !(3..=MAX_POLYGON_EDGES).contains(&edges) {
+        // Unsupported polygon
+        return None;
+    }
+    if star_list.len() < edges {
+        // Not enough stars for the polygon
+        return None;
+    }
+    // Preallocate the polygon arena, at most one polygon per star
+    let capacity = limits.max_polygons.unwrap_or(star_list.len()).min(star_list.len());
+    let mut polygons: Vec<Polygon> = Vec::with_capacity(capacity);
+    // Anchor stars by brightness, so the limits keep the brightest polygons
+    let mut brightness_order: Vec<usize> = (0..star_list.len()).collect();
+    brightness_order.sort_by(|a, b| star_list[*a].magnitude.partial_cmp(&star_list[*b].magnitude).unwrap());
+    let cells = limits.grid_cells.max(1);
+    let star_cell = star_cells(star_list, cells);
+    let mut cell_count = vec![0_usize; cells * cells];
+    // For each star find the edges - 1 closest stars
+    for id_a in brightness_order {
+        let star_a = &star_list[id_a];
+        if let Some(max_polygons) = limits.max_polygons {
+            if polygons.len() >= max_polygons {
+                debug!("Find polygon > Limit of {} polygons reached", max_polygons);
+                break;
+            }
+        }
+        if let Some(max_per_cell) = limits.max_per_cell {
+            if cell_count[star_cell[id_a]] >= max_per_cell {
+                debug!("Find polygon > Cell {} full, skipping star i:{}", star_cell[id_a], id_a);
+                continue;
+            }
+        }
+        debug!("Find polygon > Searching for star i:{} id:({})", id_a, star_a.id);
+        // The first vertex of the polygon is the star itself, followed by the closest stars
+        let mut star_vec = [0_usize; MAX_POLYGON_EDGES];
+        let mut dist_vec = [f64::MAX; MAX_POLYGON_EDGES];
+        let mut length_vec = [0_f64; MAX_POLYGON_CONNECTIONS];
+        star_vec[0] = id_a;
+        dist_vec[0] = 0.0;
+        for (id_b, star_b) in star_list.iter().enumerate() {
+            if id_a != id_b {
+                // Calculate distance between the stars
+                let distance = star_distance_rad(star_a, star_b);
+                // Compare this distance with the current list of closest stars
+                if distance < dist_vec[edges - 1] {
+                    // Star is closer: shift the farther stars, discarding the last one, and insert it
+                    let mut i = edges - 1;
+                    while i > 1 && distance < dist_vec[i - 1] {
+                        star_vec[i] = star_vec[i - 1];
+                        dist_vec[i] = dist_vec[i - 1];
+                        i -= 1;
+                    }
+                    star_vec[i] = id_b;
+                    dist_vec[i] = distance;
+                }
+            }
+        }
+        let star_vec = &star_vec[..edges];
+        debug!("  Star vec {:?}", star_vec);
+        debug!("  Dist vec {:?}", &dist_vec[..edges]);
+        // Calculate center of the polygon
+        let mut center_ra_rad = Radians(0.0);
+        let mut center_dec_rad = Radians(0.0);
+        for star_id in star_vec.iter() {
+            center_ra_rad = center_ra_rad + star_list[*star_id].ra_rad;
+            center_dec_rad = center_dec_rad + star_list[*star_id].dec_rad;
+        }
+        center_ra_rad = center_ra_rad / edges as f64;
+        center_dec_rad = center_dec_rad / edges as f64;
+        // Don't store if polygon already exists
+        let mut polygon_exists = false;
+        'hexist: for h in polygons.iter() {
+            if h.center_ra_rad == center_ra_rad && h.center_dec_rad == center_dec_rad {
+                debug!("  !! Polygon already exists: {} = {}", id_a, h.star_index);
+                polygon_exists = true;
+                break 'hexist;
+            }
+        }
+        if !polygon_exists {
+            // Calculate the lengths of the polygon connections
+            let mut k = 0;
+            // Relative errors of the lengths, and of the longest one that normalizes them
+            let mut length_errors = [0.0_f64; MAX_POLYGON_CONNECTIONS];
+            let (mut longest, mut longest_error) = (0.0_f64, 0.0_f64);
+            for i in 0..star_vec.len() - 1 {
+                let star_a = &star_list[star_vec[i]];
+                debug!("  Exists - i:{} star_a:{}", i, star_vec[i]);
+                for n in (i + 1)..star_vec.len() {
+                    // Calculate distance between the stars
+                    let star_b = &star_list[star_vec[n]];
+                    let length = star_distance_rad(star_a, star_b);
+                    debug!("  Exists - {} length from {} to {} = {}", k, i, n, length);
+                    if length == 0.0 {
+                        debug!("  Exists - {} length 0. star_a:{:?} star_b:{:?}", k, star_vec[i], star_vec[n]);
+                    }
+                    length_vec[k] = length;
+                    length_errors[k] = star_distance_error(star_a, star_b);
+                    if length > longest {
+                        longest = length;
+                        longest_error = length_errors[k];
+                    }
+                    k += 1;
+                }
+            }
+            // A normalized length has the errors of its length and of the longest one
+            let length_error = length_errors[..conn_number].iter().map(|error| libm::hypot(*error, longest_error)).fold(0.0, f64::max);
+            // Sort: https://users.rust-lang.org/t/how-to-sort-a-vec-of-floats/2838
+            let length_list = &mut length_vec[..conn_number];
+            length_list.sort_by(|a, b| a.partial_cmp(b).unwrap()); 
+            // Discard degenerate polygons, their normalized lengths are unstable
+            if let Some(reason) = degenerate_polygon(star_list, star_vec, length_list, shape) {
+                debug!("  Degenerate polygon for star {}: {}", id_a, reason);
+                continue;
+            }
+            // Normalize the length of the connections by the longest length
+            let longest_length = length_list[length_list.len() - 1];
+            for length in length_list.iter_mut() {
+                *length /= longest_length;
+            }
+            // length_vec[0] = longest_length;
+            debug!("  Length vec: {:?}, longest_length (rad): {}", length_list, longest_length);
+            // Store polygon data
+            let mut stars = [0_usize; MAX_POLYGON_EDGES];
+            stars[..edges].copy_from_slice(star_vec);
+            let polygon = Polygon {
+                star_index: id_a,
+                edges,
+                stars,
+                lengths: length_vec,
+                center_ra_rad,
+                center_dec_rad,
+                length_error,
+            };
+            polygons.push(polygon);
+            cell_count[star_cell[id_a]] += 1;
+        }
+    }
+    Some(polygons)
+}
+
+/// Similarity of two polygons: the worst ratio between their normalized connection lengths (1.0 = identical)
+pub fn polygon_similarity(polygon_a: &Polygon, polygon_b: &Polygon) -> f64 {
+    if polygon_a.edges != polygon_b.edges {
+        return 0.0;
+    }
+    let connections = polygon_connections(polygon_a.edges);
+    let mut similarity = 1.0_f64;
+    for i in 0..connections - 1 {
+        let a = polygon_a.lengths[i];
+        let b = polygon_b.lengths[i];
+        similarity = similarity.min(if a > b { b / a } else { a / b });
+    }
+    similarity
+}
+
+/// Compare star database and image polygons
+pub fn find_fit(image_polygons: &[Polygon], star_polygons: &[Polygon], tolerance: &Tolerance) -> Vec<PolygonMatch> {
+    find_fit_until(image_polygons, star_polygons, tolerance, || false).0
+}
+
+/// Compare image and star database polygons until `expired` returns true (checked before each image polygon).
+/// Star polygon codes are looked up in a k-d tree, so each image polygon costs O(log M) instead of O(M).
+/// Returns the similar polygons found and whether the search was completed.
+pub fn find_fit_until<F: FnMut() -> bool>(image_polygons: &[Polygon], star_polygons: &[Polygon], tolerance: &Tolerance, mut expired: F) -> (Vec<PolygonMatch>, bool) {
+    debug!("Find fit > Searching similar polygons");
+    let mut matches = Vec::new();
+    // One tree per number of vertices, built on first use
+    let mut trees: BTreeMap<usize, CodeTree> = BTreeMap::new();
+    for (image_index, image_pol) in image_polygons.iter().enumerate() {
+        if expired() {
+            debug!("Find fit > Stopped at image polygon {} of {}", image_index, image_polygons.len());
+            return (matches, false);
+        }
+        let tree = trees.entry(image_pol.edges).or_insert_with(|| CodeTree::new(star_polygons, image_pol.edges));
+        // Lengths within the tolerance ratio of the image polygon ones
+        let min_similarity = tolerance.min_similarity(image_pol);
+        let mut low = [0_f64; MAX_POLYGON_CONNECTIONS];
+        let mut high = [0_f64; MAX_POLYGON_CONNECTIONS];
+        for i in 0..tree.dimensions() {
+            low[i] = image_pol.lengths[i] * min_similarity;
+            high[i] = image_pol.lengths[i] / min_similarity;
+        }
+        for star_index in tree.range(&low[..tree.dimensions()], &high[..tree.dimensions()]) {
+            let star_pol = &star_polygons[star_index];
+            let similarity = polygon_similarity(image_pol, star_pol);
+            if similarity < min_similarity {
+                continue;
+            }
+            debug!("Find fit > Similar polygon found\n  image_pol:{:?}\n   star_pol:{:?}\n similarity:{}", image_pol.length_list(), star_pol.length_list(), similarity);
+            matches.push(PolygonMatch {
+                image_polygon: image_index,
+                star_polygon: star_index,
+                similarity,
+            });
+        }
+    }
+    debug!("Found {} similar polygons", matches.len());
+    (matches, true)
+}
+
+/// Vertices of a polygon (positions in its star list), ordered by their summed distance to the other vertices
+fn vertex_order(star_list: &[Star], polygon: &Polygon) -> [usize; MAX_POLYGON_EDGES] {
+    let stars = polygon.star_list();
+    let mut signature = [0_f64; MAX_POLYGON_EDGES];
+    for i in 0..stars.len() {
+        for j in 0..stars.len() {
+            signature[i] += star_distance_rad(&star_list[stars[i]], &star_list[stars[j]]);
+        }
+    }
+    let mut order = [0_usize; MAX_POLYGON_EDGES];
+    for (i, vertex) in order.iter_mut().enumerate().take(stars.len()) {
+        *vertex = i;
+    }
+    order[..stars.len()].sort_by(|a, b| signature[*a].partial_cmp(&signature[*b]).unwrap());
+    order
+}
+
+/// Pair image and star database stars using the vertices of similar polygons.
+/// Each star is paired once, with its most voted counterpart. Sorted by votes.
+pub fn find_star_matches(image_star_list: &[Star], image_polygons: &[Polygon], star_list: &[Star], star_polygons: &[Polygon], matches: &[PolygonMatch]) -> Vec<StarMatch> {
+    // Vote for the vertex pairs of every similar polygon
+    let mut votes: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    for m in matches.iter() {
+        let image_pol = &image_polygons[m.image_polygon];
+        let star_pol = &star_polygons[m.star_polygon];
+        let image_order = vertex_order(image_star_list, image_pol);
+        let star_order = vertex_order(star_list, star_pol);
+        for i in 0..image_pol.edges {
+            let pair = (image_pol.stars[image_order[i]], star_pol.stars[star_order[i]]);
+            *votes.entry(pair).or_insert(0) += 1;
+        }
+    }
+    let mut candidates: Vec<StarMatch> = votes.into_iter()
+        .map(|((image_star, star), votes)| StarMatch { image_star, star, votes })
+        .collect();
+    candidates.sort_by_key(|m| core::cmp::Reverse(m.votes));
+    // Keep the most voted pair of each star
+    let mut star_matches: Vec<StarMatch> = Vec::new();
+    for candidate in candidates {
+        if star_matches.iter().all(|m| m.image_star != candidate.image_star && m.star != candidate.star) {
+            debug!("Star match > image:{} star:{} votes:{}", candidate.image_star, candidate.star, candidate.votes);
+            star_matches.push(candidate);
+        }
+    }
+    star_matches
+}
+
+/// Keep the star matches whose magnitudes agree within `tolerance`, after fitting the
+/// zero point (median of catalog minus image magnitude). Returns the zero point too.
+pub fn filter_star_matches_by_magnitude(image_star_list: &[Star], star_list: &[Star], matches: Vec<StarMatch>, tolerance: f64) -> (Vec<StarMatch>, f64) {
+    if matches.is_empty() {
+        return (matches, 0.0);
+    }
+    let difference = |m: &StarMatch| star_list[m.star].magnitude - image_star_list[m.image_star].magnitude;
+    let mut differences: Vec<f64> = matches.iter().map(difference).collect();
+    differences.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let zero_point = differences[differences.len() / 2];
+    let consistent = matches.into_iter()
+        .filter(|m| {
+            let residual = difference(m) - zero_point;
+            if residual.abs() > tolerance {
+                debug!("Magnitude check > Rejected image:{} star:{} residual:{}", m.image_star, m.star, residual);
+            }
+            residual.abs() <= tolerance
+        })
+        .collect();
+    (consistent, zero_point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn star(ra_rad: f64, dec_rad: f64) -> Star {
+        Star::image_plane(0, Radians(ra_rad), Radians(dec_rad), 0.0)
+    }
+
+    #[test]
+    fn distance_error_matches_perturbation() {
+        let (a, b) = (star(0.0, 0.0), star(0.004, 0.001));
+        let error = 1e-7;
+        // Both coordinate differences grow by the error: the terms add up
+        let moved = star(0.004 + error, 0.001 + error);
+        let change = (star_distance_rad(&a, &moved) - star_distance_rad(&a, &b)) / star_distance_rad(&a, &b) / error;
+        let expected = 0.5 * (1.0 / libm::sqrt(0.004) + 1.0 / libm::sqrt(0.001)) / star_distance_rad(&a, &b);
+        assert!((change - expected).abs() < 1e-3 * expected);
+        // Independent errors add in quadrature
+        let propagated = star_distance_error(&a, &b);
+        assert!(propagated < expected && propagated > expected / core::f64::consts::SQRT_2);
+    }
+
+    #[test]
+    fn loosens_tolerance_of_axis_aligned_connections() {
+        let tolerance = Tolerance { centroid_error: Some(1e-6) };
+        let polygon = |length_error: f64| Polygon {
+            star_index: 0,
+            edges: 3,
+            stars: [0; MAX_POLYGON_EDGES],
+            lengths: [0.0; MAX_POLYGON_CONNECTIONS],
+            center_ra_rad: Radians(0.0),
+            center_dec_rad: Radians(0.0),
+            length_error,
+        };
+        // A connection along the R.A. axis: its sqrt|dDec| term is unstable
+        let aligned = star_distance_error(&star(0.0, 0.0), &star(0.004, 1e-9));
+        let diagonal = star_distance_error(&star(0.0, 0.0), &star(0.004, 0.004));
+        assert!(tolerance.min_similarity(&polygon(aligned)) < tolerance.min_similarity(&polygon(diagonal)));
+        assert_eq!(tolerance.min_similarity(&polygon(f64::INFINITY)), MIN_TOLERANCE_SIMILARITY);
+        assert_eq!(Tolerance { centroid_error: None }.min_similarity(&polygon(diagonal)), MIN_SIMILARITY);
+    }
+
+    /// Star field with scattered positions and a distinct magnitude per star, not sorted by magnitude
+    fn star_field(count: usize) -> Vec<Star> {
+        let mut seed = 12345_u64;
+        let mut next = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 11) as f64 / (1_u64 << 53) as f64
+        };
+        (0..count).map(|i| Star::image_plane(i as u64, Radians(0.01 * next()), Radians(0.01 * next()), 5.0 + 10.0 * next())).collect()
+    }
+
+    #[test]
+    fn caps_keep_the_brightest_polygons() {
+        let star_list = star_field(60);
+        let shape = PolygonShape { min_edge_ratio: 0.0, min_area_ratio: 0.0 };
+        let unlimited = PolygonLimits { grid_cells: 3, max_per_cell: None, max_polygons: None };
+        let all = find_polygons(&star_list, 4, &unlimited, &shape).unwrap();
+        let anchors = |polygons: &[Polygon]| polygons.iter().map(|polygon| polygon.star_index).collect::<Vec<usize>>();
+        // Polygons come brightest first
+        assert!(all.windows(2).all(|pair| star_list[pair[0].star_index].magnitude <= star_list[pair[1].star_index].magnitude));
+
+        // Global cap: the first polygons of the unlimited search
+        let limits = PolygonLimits { max_polygons: Some(10), ..unlimited };
+        let capped = find_polygons(&star_list, 4, &limits, &shape).unwrap();
+        assert_eq!(anchors(&capped), anchors(&all[..10]));
+
+        // Cell cap: the first polygons of each cell
+        let limits = PolygonLimits { max_per_cell: Some(2), ..unlimited };
+        let capped = find_polygons(&star_list, 4, &limits, &shape).unwrap();
+        let cells = star_cells(&star_list, 3);
+        let mut cell_count = [0; 9];
+        let expected: Vec<usize> = anchors(&all).into_iter().filter(|&star| {
+            cell_count[cells[star]] += 1;
+            cell_count[cells[star]] <= 2
+        }).collect();
+        assert_eq!(anchors(&capped), expected);
+        assert!(capped.len() < all.len());
+    }
+
+    /// Degeneracy of the polygon of a few stars
+    fn degeneracy(points: &[(f64, f64)], shape: &PolygonShape) -> Option<String> {
+        let star_list: Vec<Star> = points.iter().map(|p| star(p.0, p.1)).collect();
+        let star_vec: Vec<usize> = (0..star_list.len()).collect();
+        let mut length_vec = Vec::new();
+        for i in 0..star_list.len() {
+            for j in (i + 1)..star_list.len() {
+                length_vec.push(star_distance_rad(&star_list[i], &star_list[j]));
+            }
+        }
+        length_vec.sort_by(|a, b| a.total_cmp(b));
+        degenerate_polygon(&star_list, &star_vec, &length_vec, shape)
+    }
+
+    #[test]
+    fn rejects_degenerate_polygons() {
+        let shape = PolygonShape { min_edge_ratio: 0.1, min_area_ratio: 0.05 };
+        // A well spread quad is kept
+        assert_eq!(degeneracy(&[(0.0, 0.0), (0.003, 0.0005), (0.001, 0.004), (0.004, 0.003)], &shape), None);
+        // Three collinear stars, and three almost collinear
+        assert!(degeneracy(&[(0.0, 0.0), (0.001, 0.001), (0.003, 0.003), (0.0, 0.004)], &shape).unwrap().starts_with("collinear"));
+        assert!(degeneracy(&[(0.0, 0.0), (0.002, 0.00005), (0.004, 0.0), (0.002, 0.004)], &shape).unwrap().starts_with("collinear"));
+        // A very short edge, and coincident stars
+        assert!(degeneracy(&[(0.0, 0.0), (0.000001, 0.000001), (0.004, 0.0), (0.002, 0.004)], &shape).unwrap().starts_with("edge ratio"));
+        assert_eq!(degeneracy(&[(0.0, 0.0), (0.0, 0.0), (0.004, 0.0)], &shape).unwrap(), "coincident stars");
+        // Without thresholds, only coincident stars are degenerate
+        let any = PolygonShape { min_edge_ratio: 0.0, min_area_ratio: 0.0 };
+        assert_eq!(degeneracy(&[(0.0, 0.0), (0.001, 0.001), (0.003, 0.003), (0.0, 0.004)], &any), None);
+    }
+}
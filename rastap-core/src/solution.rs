@@ -0,0 +1,709 @@
+use alloc::{vec, vec::Vec};
+
+use crate::angle::Degrees;
+use crate::polygon::{Star, StarMatch};
+
+/// Sigma clipping of the fit residuals: rejection threshold (in RMS) and passes
+pub const CLIP_SIGMAS: f64 = 3.0;
+pub const CLIP_PASSES: usize = 3;
+
+/// Sky position
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SkyCoord {
+    pub ra: Degrees,
+    pub dec: Degrees,
+}
+
+/// Image parity: the sky as seen (East counter-clockwise from North), or mirrored
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Parity {
+    Normal,
+    Flipped,
+}
+
+/// Residuals of the star matches kept by the fit
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FitStats {
+    pub stars: usize,    // Star matches fitted
+    pub rejected: usize, // Star matches rejected by sigma clipping
+    pub rms_arcsec: f64,
+    pub max_arcsec: f64,
+}
+
+/// SIP distortion polynomial: sum of coefficient(p, q) * u^p * v^q, for p + q <= order
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SipPolynomial {
+    pub order: usize,
+    pub coefficients: Vec<f64>, // (order + 1) x (order + 1), row p, column q
+}
+
+impl SipPolynomial {
+    /// Coefficient of u^p * v^q
+    pub fn coefficient(&self, p: usize, q: usize) -> f64 {
+        self.coefficients[p * (self.order + 1) + q]
+    }
+
+    /// Evaluates the polynomial
+    pub fn evaluate(&self, u: f64, v: f64) -> f64 {
+        let mut sum = 0.0;
+        for p in 0..=self.order {
+            for q in 0..=(self.order - p) {
+                sum += self.coefficient(p, q) * libm::pow(u, p as f64) * libm::pow(v, q as f64);
+            }
+        }
+        sum
+    }
+}
+
+/// SIP distortion: forward (A, B) and optional inverse (AP, BP) polynomials
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sip {
+    pub a: SipPolynomial,
+    pub b: SipPolynomial,
+    pub ap: Option<SipPolynomial>,
+    pub bp: Option<SipPolynomial>,
+}
+
+impl Sip {
+    /// Distorted pixel offsets from the reference pixel: u + A(u, v), v + B(u, v)
+    pub fn distort(&self, u: f64, v: f64) -> (f64, f64) {
+        (u + self.a.evaluate(u, v), v + self.b.evaluate(u, v))
+    }
+
+    /// Pixel offsets of distorted offsets: the inverse polynomials, or the inverse of A and B by fixed point iteration
+    pub fn undistort(&self, u: f64, v: f64) -> (f64, f64) {
+        if let (Some(ap), Some(bp)) = (&self.ap, &self.bp) {
+            return (u + ap.evaluate(u, v), v + bp.evaluate(u, v));
+        }
+        let (mut x, mut y) = (u, v);
+        for _ in 0..50 {
+            let (next_x, next_y) = (u - self.a.evaluate(x, y), v - self.b.evaluate(x, y));
+            let converged = (next_x - x).abs() < 1e-9 && (next_y - y).abs() < 1e-9;
+            x = next_x;
+            y = next_y;
+            if converged {
+                break;
+            }
+        }
+        (x, y)
+    }
+}
+
+/// Reference pixel (CRPIX) of the fit
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ReferencePixel {
+    /// Solved for: CRPIX is the pixel of the fitted tangent point
+    #[default]
+    Fit,
+    /// Fixed pixel (FITS pixels), such as the image center or the optical axis: the tangent point is its sky position
+    Fixed([f64; 2]),
+}
+
+/// Terms of the fitted model beyond the gnomonic projection
+#[derive(Clone, Debug, Default)]
+pub struct FitModel {
+    pub sip_order: Option<usize>, // SIP distortion order (2 or more), fitted when there are enough matches
+    pub refraction: Option<Vec<(f64, f64)>>, // Star positions before refraction (R.A., Dec, radians), indexed like the star list: fits the refraction scale
+    pub reference_pixel: ReferencePixel,
+}
+
+/// Plate solution: gnomonic (TAN) transform fitted to the star matches, tangent point included, with optional SIP distortion
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Solution {
+    pub center: SkyCoord,           // Tangent point (CRVAL)
+    pub reference_pixel: [f64; 2],  // Pixel of the tangent point (CRPIX, FITS pixels)
+    pub cd: [[f64; 2]; 2],          // Degrees per pixel
+    pub sip: Option<Sip>,
+    pub refraction_scale: Option<f64>, // Fitted refraction over the modelled one
+    pub scale_arcsec_px: f64,
+    pub rotation_deg: f64,          // Rotation of the Y axis from the North, through the East
+    pub parity: Parity,
+    pub matches: Vec<StarMatch>,    // Star matches kept by the fit
+    pub stats: FitStats,
+}
+
+impl Solution {
+    /// Sky position of a pixel (FITS pixels)
+    pub fn pixel_to_sky(&self, x: f64, y: f64) -> SkyCoord {
+        let (mut u, mut v) = (x - self.reference_pixel[0], y - self.reference_pixel[1]);
+        if let Some(sip) = &self.sip {
+            let (du, dv) = sip.distort(u, v);
+            u = du;
+            v = dv;
+        }
+        let xi = self.cd[0][0] * u + self.cd[0][1] * v;
+        let eta = self.cd[1][0] * u + self.cd[1][1] * v;
+        let (ra, dec) = deproject(self.center.ra.0.to_radians(), self.center.dec.0.to_radians(), xi, eta);
+        let ra = ra.to_degrees();
+        SkyCoord { ra: Degrees(if ra < 0.0 { ra + 360.0 } else if ra >= 360.0 { ra - 360.0 } else { ra }), dec: Degrees(dec.to_degrees()) }
+    }
+
+    /// Pixel (FITS pixels) of a sky position. None on the far side of the tangent point.
+    pub fn sky_to_pixel(&self, ra: Degrees, dec: Degrees) -> Option<(f64, f64)> {
+        let (xi, eta) = project(self.center.ra.0.to_radians(), self.center.dec.0.to_radians(), ra.0.to_radians(), dec.0.to_radians())?;
+        let determinant = self.cd[0][0] * self.cd[1][1] - self.cd[0][1] * self.cd[1][0];
+        let mut u = (self.cd[1][1] * xi - self.cd[0][1] * eta) / determinant;
+        let mut v = (self.cd[0][0] * eta - self.cd[1][0] * xi) / determinant;
+        if let Some(sip) = &self.sip {
+            let (du, dv) = sip.undistort(u, v);
+            u = du;
+            v = dv;
+        }
+        Some((self.reference_pixel[0] + u, self.reference_pixel[1] + v))
+    }
+}
+
+/// Gnomonic projection around a tangent point, in degrees. None on the far side.
+fn project(ra0: f64, dec0: f64, ra: f64, dec: f64) -> Option<(f64, f64)> {
+    let cos_c = libm::sin(dec0) * libm::sin(dec) + libm::cos(dec0) * libm::cos(dec) * libm::cos(ra - ra0);
+    if cos_c <= 0.0 {
+        return None;
+    }
+    let xi = libm::cos(dec) * libm::sin(ra - ra0) / cos_c;
+    let eta = (libm::cos(dec0) * libm::sin(dec) - libm::sin(dec0) * libm::cos(dec) * libm::cos(ra - ra0)) / cos_c;
+    Some((xi.to_degrees(), eta.to_degrees()))
+}
+
+/// Inverse gnomonic projection, in radians
+fn deproject(ra0: f64, dec0: f64, xi: f64, eta: f64) -> (f64, f64) {
+    let (xi, eta) = (xi.to_radians(), eta.to_radians());
+    let denominator = libm::cos(dec0) - eta * libm::sin(dec0);
+    let ra = ra0 + libm::atan2(xi, denominator);
+    let dec = libm::atan2(libm::sin(dec0) + eta * libm::cos(dec0), libm::hypot(xi, denominator));
+    (ra, dec)
+}
+
+/// Angular separation (haversine), in radians
+fn separation(ra1: f64, dec1: f64, ra2: f64, dec2: f64) -> f64 {
+    let sin_dec = libm::sin((dec2 - dec1) / 2.0);
+    let sin_ra = libm::sin((ra2 - ra1) / 2.0);
+    let h = sin_dec * sin_dec + libm::cos(dec1) * libm::cos(dec2) * sin_ra * sin_ra;
+    2.0 * libm::asin(libm::sqrt(h).min(1.0))
+}
+
+/// Least squares solution of rows · x = targets (normal equations, Gaussian elimination with partial
+/// pivoting). None if the rows are degenerate.
+fn least_squares(rows: &[Vec<f64>], targets: &[f64]) -> Option<Vec<f64>> {
+    let size = rows.first()?.len();
+    let mut m = vec![vec![0.0_f64; size]; size];
+    let mut r = vec![0.0_f64; size];
+    for (row, target) in rows.iter().zip(targets) {
+        for i in 0..size {
+            if row[i] == 0.0 {
+                continue;
+            }
+            for j in 0..size {
+                m[i][j] += row[i] * row[j];
+            }
+            r[i] += row[i] * target;
+        }
+    }
+    let largest = (0..size).map(|i| m[i][i]).fold(0.0, f64::max);
+    for column in 0..size {
+        let pivot = (column..size).max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))?;
+        // Collinear points: the pivot vanishes relative to the largest diagonal term
+        if m[pivot][column].abs() <= 1e-12 * largest {
+            return None;
+        }
+        m.swap(column, pivot);
+        r.swap(column, pivot);
+        let (upper, lower) = m.split_at_mut(column + 1);
+        let pivot_row = &upper[column];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[column] / pivot_row[column];
+            for (value, pivot_value) in row[column..].iter_mut().zip(&pivot_row[column..]) {
+                *value -= factor * pivot_value;
+            }
+            r[column + 1 + offset] -= factor * r[column];
+        }
+    }
+    let mut x = vec![0.0_f64; size];
+    for i in (0..size).rev() {
+        let sum: f64 = ((i + 1)..size).map(|j| m[i][j] * x[j]).sum();
+        x[i] = (r[i] - sum) / m[i][i];
+    }
+    Some(x)
+}
+
+/// Polynomial terms (p, q) of u^p * v^q fitted: the linear terms, then the SIP terms up to the order
+fn model_terms(sip_order: usize) -> Vec<(usize, usize)> {
+    let mut terms = vec![(1, 0), (0, 1)];
+    for degree in 2..=sip_order {
+        for p in (0..=degree).rev() {
+            terms.push((p, degree - p));
+        }
+    }
+    terms
+}
+
+/// Matched positions prepared for the fit: pixel offsets from an origin, scaled to about one, and sky positions
+struct FitData {
+    offsets: Vec<(f64, f64)>,
+    sky: Vec<(f64, f64)>,                // R.A. and Dec, radians
+    unrefracted: Option<Vec<(f64, f64)>>, // Sky positions before refraction, when its scale is fitted
+    norm: f64,                           // Pixels per unit offset
+}
+
+/// Linear least squares at a tangent point
+struct TermsFit {
+    coefficients: Vec<[f64; 2]>, // Of xi and eta (degrees), constant first
+    refraction: Option<f64>,     // Fitted refraction over the modelled one, minus one
+    residuals: Vec<f64>,         // Of xi and eta, degrees
+}
+
+impl FitData {
+    fn new(pixels: &[(f64, f64)], star_list: &[Star], matches: &[StarMatch], origin: (f64, f64), model: &FitModel) -> FitData {
+        let offsets: Vec<(f64, f64)> = matches.iter().map(|m| (pixels[m.image_star].0 - origin.0, pixels[m.image_star].1 - origin.1)).collect();
+        let norm = offsets.iter().map(|&(u, v)| u.abs().max(v.abs())).fold(1.0, f64::max);
+        let sky: Vec<(f64, f64)> = matches.iter().map(|m| (star_list[m.star].ra_rad.0, star_list[m.star].dec_rad.0)).collect();
+        // Without refraction in the positions there is no scale to fit
+        let unrefracted = model.refraction.as_ref().map(|positions| matches.iter().map(|m| positions[m.star]).collect::<Vec<(f64, f64)>>());
+        let unrefracted = unrefracted.filter(|positions| positions.iter().zip(&sky).any(|(a, b)| separation(a.0, a.1, b.0, b.1) > 1e-9));
+        FitData {
+            offsets: offsets.iter().map(|&(u, v)| (u / norm, v / norm)).collect(),
+            sky,
+            unrefracted,
+            norm,
+        }
+    }
+
+    /// Linear least squares of the projected positions (degrees) at a tangent point, over the terms, an
+    /// optional constant and the refraction scale
+    fn fit_at(&self, ra0: f64, dec0: f64, terms: &[(usize, usize)], constant: bool) -> Option<TermsFit> {
+        let size = terms.len() + constant as usize;
+        let unknowns = 2 * size + self.unrefracted.is_some() as usize;
+        let mut rows = Vec::with_capacity(2 * self.offsets.len());
+        let mut targets = Vec::with_capacity(2 * self.offsets.len());
+        for (n, (&(u, v), &(ra, dec))) in self.offsets.iter().zip(&self.sky).enumerate() {
+            let (xi, eta) = project(ra0, dec0, ra, dec)?;
+            let mut basis = Vec::with_capacity(size);
+            if constant {
+                basis.push(1.0);
+            }
+            basis.extend(terms.iter().map(|&(p, q)| libm::pow(u, p as f64) * libm::pow(v, q as f64)));
+            // Observed = unrefracted + (1 + k) refraction, so the refracted position is the model minus k refraction
+            let refraction = match &self.unrefracted {
+                Some(unrefracted) => {
+                    let (ra, dec) = unrefracted[n];
+                    let (xi_mean, eta_mean) = project(ra0, dec0, ra, dec)?;
+                    Some((xi_mean - xi, eta_mean - eta))
+                }
+                None => None,
+            };
+            for (k, target) in [xi, eta].iter().enumerate() {
+                let mut row = vec![0.0; unknowns];
+                row[k * size..(k + 1) * size].copy_from_slice(&basis);
+                if let Some((xi_term, eta_term)) = refraction {
+                    row[2 * size] = if k == 0 { xi_term } else { eta_term };
+                }
+                rows.push(row);
+                targets.push(*target);
+            }
+        }
+        let solution = least_squares(&rows, &targets)?;
+        let residuals = rows.iter().zip(&targets).map(|(row, target)| target - row.iter().zip(&solution).map(|(value, x)| value * x).sum::<f64>()).collect();
+        Some(TermsFit {
+            coefficients: (0..size).map(|i| [solution[i], solution[size + i]]).collect(),
+            refraction: self.unrefracted.as_ref().map(|_| solution[2 * size]),
+            residuals,
+        })
+    }
+}
+
+/// Offset (scaled like the fit) where the fitted xi and eta vanish: the pixel of the tangent point, by Newton's method
+fn tangent_offset(coefficients: &[[f64; 2]], terms: &[(usize, usize)]) -> Option<(f64, f64)> {
+    let power = |x: f64, n: usize| if n == 0 { 1.0 } else { libm::pow(x, n as f64) };
+    let (mut u, mut v) = (0.0, 0.0);
+    for _ in 0..50 {
+        let mut value = coefficients[0];
+        let mut jacobian = [[0.0; 2]; 2]; // d(xi, eta) / d(u, v)
+        for (&(p, q), c) in terms.iter().zip(&coefficients[1..]) {
+            let (du, dv) = (p as f64 * power(u, p.saturating_sub(1)) * power(v, q), q as f64 * power(u, p) * power(v, q.saturating_sub(1)));
+            for k in 0..2 {
+                value[k] += c[k] * power(u, p) * power(v, q);
+                jacobian[k][0] += c[k] * du;
+                jacobian[k][1] += c[k] * dv;
+            }
+        }
+        let determinant = jacobian[0][0] * jacobian[1][1] - jacobian[0][1] * jacobian[1][0];
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+        let step = ((jacobian[1][1] * value[0] - jacobian[0][1] * value[1]) / determinant, (jacobian[0][0] * value[1] - jacobian[1][0] * value[0]) / determinant);
+        u -= step.0;
+        v -= step.1;
+        if libm::hypot(step.0, step.1) < 1e-12 {
+            break;
+        }
+    }
+    Some((u, v))
+}
+
+/// Sum of squares
+fn cost(residuals: &[f64]) -> f64 {
+    residuals.iter().map(|r| r * r).sum()
+}
+
+/// Finite difference step of the tangent point, radians
+const TANGENT_STEP: f64 = 1e-6;
+/// Levenberg-Marquardt iterations over the tangent point
+const TANGENT_ITERATIONS: usize = 30;
+/// Minimum F statistic of the residual reduction for the fitted tangent point to replace the pinned one
+pub const MIN_TANGENT_SIGNIFICANCE: f64 = 10.0;
+
+/// Tangent point pinned to the sky position of the origin pixel, where the constant terms of the fit vanish
+fn pin_tangent_point(data: &FitData, initial: (f64, f64), terms: &[(usize, usize)]) -> Option<(f64, f64)> {
+    let (mut ra0, mut dec0) = initial;
+    for _ in 0..3 {
+        let coefficients = data.fit_at(ra0, dec0, terms, true)?.coefficients;
+        let (ra, dec) = deproject(ra0, dec0, coefficients[0][0], coefficients[0][1]);
+        ra0 = ra;
+        dec0 = dec;
+    }
+    Some((ra0, dec0))
+}
+
+/// Tangent point of the gnomonic fit over the terms, with CRPIX free: the tangent point is first pinned
+/// to the sky position of the origin pixel, then moved by Levenberg-Marquardt while it significantly
+/// reduces the residuals.
+fn fit_tangent_point(data: &FitData, initial: (f64, f64), terms: &[(usize, usize)]) -> Option<(f64, f64)> {
+    let pinned = pin_tangent_point(data, initial, terms)?;
+    let (ra0, dec0) = pinned;
+    let residuals = data.fit_at(ra0, dec0, terms, true)?.residuals;
+    let pinned_cost = cost(&residuals);
+
+    // Free: the constant terms place CRPIX away from the origin pixel
+    let mut current = (ra0, dec0, residuals, pinned_cost);
+    let mut lambda = 1e-3;
+    for _ in 0..TANGENT_ITERATIONS {
+        let (ra0, dec0, residuals, current_cost) = &current;
+        let mut jacobian = [Vec::new(), Vec::new()];
+        for (k, column) in jacobian.iter_mut().enumerate() {
+            let (ra, dec) = if k == 0 { (ra0 + TANGENT_STEP, *dec0) } else { (*ra0, dec0 + TANGENT_STEP) };
+            let stepped = data.fit_at(ra, dec, terms, true)?.residuals;
+            *column = stepped.iter().zip(residuals).map(|(s, r)| (s - r) / TANGENT_STEP).collect();
+        }
+        let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+        let jtj = [[dot(&jacobian[0], &jacobian[0]), dot(&jacobian[0], &jacobian[1])], [dot(&jacobian[1], &jacobian[0]), dot(&jacobian[1], &jacobian[1])]];
+        let jtr = [dot(&jacobian[0], residuals), dot(&jacobian[1], residuals)];
+        let mut improved = None;
+        while lambda < 1e12 {
+            let a = [[jtj[0][0] * (1.0 + lambda), jtj[0][1]], [jtj[1][0], jtj[1][1] * (1.0 + lambda)]];
+            let determinant = a[0][0] * a[1][1] - a[0][1] * a[1][0];
+            if determinant == 0.0 || !determinant.is_finite() {
+                break;
+            }
+            let step = ((a[1][1] * jtr[0] - a[0][1] * jtr[1]) / determinant, (a[0][0] * jtr[1] - a[1][0] * jtr[0]) / determinant);
+            let (ra, dec) = (ra0 - step.0, dec0 - step.1);
+            match data.fit_at(ra, dec, terms, true) {
+                Some(TermsFit { residuals: stepped, .. }) if cost(&stepped) < *current_cost => {
+                    let stepped_cost = cost(&stepped);
+                    improved = Some((ra, dec, stepped, stepped_cost));
+                    lambda /= 10.0;
+                    break;
+                }
+                _ => lambda *= 10.0,
+            }
+        }
+        match improved {
+            Some(next) => {
+                let converged = current_cost - next.3 <= 1e-12 * current_cost;
+                current = next;
+                if converged {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+    // Keep the free tangent point if the residual reduction (two more parameters) is significant
+    let (ra0, dec0, residuals, free_cost) = current;
+    let freedom = residuals.len() as f64 - 2.0 * (terms.len() + 2) as f64;
+    if freedom > 0.0 && (pinned_cost - free_cost) / 2.0 > MIN_TANGENT_SIGNIFICANCE * free_cost / freedom {
+        Some((ra0, dec0))
+    } else {
+        Some(pinned)
+    }
+}
+
+/// Fits a plate solution to the star matches. `pixels` are the positions of the image stars (FITS pixels),
+/// indexed like the image star list. Outliers are rejected by sigma clipping. None if fewer than
+/// `min_matches` remain or their positions are degenerate.
+pub fn fit(pixels: &[(f64, f64)], star_list: &[Star], matches: Vec<StarMatch>, min_matches: usize, model: &FitModel) -> Option<Solution> {
+    let count = matches.len();
+    let mut kept = matches;
+    for _ in 0..CLIP_PASSES {
+        let (solution, residuals) = fit_model(pixels, star_list, &kept, min_matches.max(3), model)?;
+        let limit = CLIP_SIGMAS * solution.stats.rms_arcsec;
+        let passing = residuals.iter().filter(|&&residual| residual <= limit).count();
+        if passing == kept.len() || passing < min_matches.max(3) {
+            return Some(Solution { stats: FitStats { rejected: count - kept.len(), ..solution.stats }, ..solution });
+        }
+        kept = kept.into_iter().zip(residuals.iter()).filter(|(_, &residual)| residual <= limit).map(|(m, _)| m).collect();
+    }
+    let (solution, _) = fit_model(pixels, star_list, &kept, min_matches.max(3), model)?;
+    Some(Solution { stats: FitStats { rejected: count - kept.len(), ..solution.stats }, ..solution })
+}
+
+/// Fit of the star matches, without rejection. Returns the solution and the residuals (arcseconds).
+fn fit_model(pixels: &[(f64, f64)], star_list: &[Star], matches: &[StarMatch], min_matches: usize, model: &FitModel) -> Option<(Solution, Vec<f64>)> {
+    if matches.len() < min_matches {
+        return None;
+    }
+    // Origin pixel and first tangent point: the mean positions of the matches
+    let n = matches.len() as f64;
+    let x0 = matches.iter().map(|m| pixels[m.image_star].0).sum::<f64>() / n;
+    let y0 = matches.iter().map(|m| pixels[m.image_star].1).sum::<f64>() / n;
+    let mut vector = [0.0_f64; 3];
+    for m in matches {
+        let star = &star_list[m.star];
+        let (ra, dec) = (star.ra_rad.0, star.dec_rad.0);
+        vector[0] += libm::cos(dec) * libm::cos(ra);
+        vector[1] += libm::cos(dec) * libm::sin(ra);
+        vector[2] += libm::sin(dec);
+    }
+    let initial = (libm::atan2(vector[1], vector[0]), libm::atan2(vector[2], libm::hypot(vector[0], vector[1])));
+
+    let order = model.sip_order.filter(|&order| order >= 2 && matches.len() > model_terms(order).len() + 2).unwrap_or(1);
+    let terms = model_terms(order);
+    let (crpix, ra0, dec0) = match model.reference_pixel {
+        // Tangent point, then CRPIX where the constant terms vanish
+        ReferencePixel::Fit => {
+            let data = FitData::new(pixels, star_list, matches, (x0, y0), model);
+            let (ra0, dec0) = fit_tangent_point(&data, initial, &terms)?;
+            let (u, v) = tangent_offset(&data.fit_at(ra0, dec0, &terms, true)?.coefficients, &terms)?;
+            ([x0 + data.norm * u, y0 + data.norm * v], ra0, dec0)
+        }
+        // Tangent point at the sky position of CRPIX
+        ReferencePixel::Fixed(crpix) => {
+            let data = FitData::new(pixels, star_list, matches, (crpix[0], crpix[1]), model);
+            let (ra0, dec0) = pin_tangent_point(&data, initial, &terms)?;
+            (crpix, ra0, dec0)
+        }
+    };
+    // The terms around CRPIX
+    let data = FitData::new(pixels, star_list, matches, (crpix[0], crpix[1]), model);
+    let TermsFit { coefficients, refraction, residuals } = data.fit_at(ra0, dec0, &terms, false)?;
+    let scale = |c: [f64; 2], degree: usize| [c[0] / libm::pow(data.norm, degree as f64), c[1] / libm::pow(data.norm, degree as f64)];
+    let (xi_u, xi_v) = (scale(coefficients[0], 1), scale(coefficients[1], 1));
+    let cd = [[xi_u[0], xi_v[0]], [xi_u[1], xi_v[1]]];
+    let sip = if order >= 2 {
+        let determinant = cd[0][0] * cd[1][1] - cd[0][1] * cd[1][0];
+        let size = (order + 1) * (order + 1);
+        let (mut a, mut b) = (vec![0.0; size], vec![0.0; size]);
+        for (&(p, q), &c) in terms.iter().zip(&coefficients).skip(2) {
+            // (xi, eta) = CD (u + A, v + B): A and B are the polynomial terms, in pixels
+            let [xi, eta] = scale(c, p + q);
+            a[p * (order + 1) + q] = (cd[1][1] * xi - cd[0][1] * eta) / determinant;
+            b[p * (order + 1) + q] = (cd[0][0] * eta - cd[1][0] * xi) / determinant;
+        }
+        Some(Sip { a: SipPolynomial { order, coefficients: a }, b: SipPolynomial { order, coefficients: b }, ap: None, bp: None })
+    } else {
+        None
+    };
+
+    let determinant = cd[0][0] * cd[1][1] - cd[0][1] * cd[1][0];
+    let ra_deg = ra0.to_degrees();
+    // Residuals on the tangent plane, arcseconds
+    let residuals: Vec<f64> = residuals.chunks_exact(2).map(|r| libm::hypot(r[0], r[1]) * 3600.0).collect();
+    let solution = Solution {
+        center: SkyCoord {
+            ra: Degrees(if ra_deg < 0.0 { ra_deg + 360.0 } else if ra_deg >= 360.0 { ra_deg - 360.0 } else { ra_deg }),
+            dec: Degrees(dec0.to_degrees()),
+        },
+        reference_pixel: crpix,
+        cd,
+        sip,
+        refraction_scale: refraction.map(|k| 1.0 + k),
+        scale_arcsec_px: libm::sqrt(determinant.abs()) * 3600.0,
+        rotation_deg: libm::atan2(cd[0][1], cd[1][1]).to_degrees(),
+        // FITS images of the sky as seen have a negative CD determinant
+        parity: if determinant < 0.0 { Parity::Normal } else { Parity::Flipped },
+        matches: matches.to_vec(),
+        stats: FitStats {
+            stars: matches.len(),
+            rejected: 0,
+            rms_arcsec: libm::sqrt(residuals.iter().map(|r| r * r).sum::<f64>() / n),
+            max_arcsec: residuals.iter().cloned().fold(0.0, f64::max),
+        },
+    };
+    Some((solution, residuals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::{Hours, Radians};
+
+    /// Plate of 2400 x 1600 pixels at 36"/px (24 x 16 degrees), tangent point near a corner
+    fn truth(sip: Option<Sip>) -> Solution {
+        let (scale, rotation) = (36.0 / 3600.0, 30.0_f64.to_radians());
+        Solution {
+            center: SkyCoord { ra: Degrees(80.0), dec: Degrees(30.0) },
+            reference_pixel: [300.0, 200.0],
+            cd: [[-scale * libm::cos(rotation), scale * libm::sin(rotation)], [scale * libm::sin(rotation), scale * libm::cos(rotation)]],
+            sip,
+            refraction_scale: None,
+            scale_arcsec_px: 36.0,
+            rotation_deg: 30.0,
+            parity: Parity::Normal,
+            matches: Vec::new(),
+            stats: FitStats { stars: 0, rejected: 0, rms_arcsec: 0.0, max_arcsec: 0.0 },
+        }
+    }
+
+    /// Detections on a grid, with uniform centroid errors of up to 0.05 pixels, and the stars at their
+    /// sky positions, matched one to one
+    fn plate(truth: &Solution) -> (Vec<(f64, f64)>, Vec<Star>, Vec<StarMatch>) {
+        let mut pixels = Vec::new();
+        let mut stars = Vec::new();
+        let mut seed = 12345_u64;
+        let mut noise = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 11) as f64 / (1_u64 << 53) as f64 - 0.5) * 0.1
+        };
+        for i in 0..24 {
+            for j in 0..16 {
+                let (x, y) = (50.0 + 100.0 * i as f64 + 7.0 * j as f64, 50.0 + 100.0 * j as f64 + 3.0 * i as f64);
+                let sky = truth.pixel_to_sky(x, y);
+                pixels.push((x + noise(), y + noise()));
+                stars.push(Star::sky(stars.len() as u64, stars.len() as u64, Hours::from(sky.ra), sky.dec, 8.0));
+            }
+        }
+        let matches = (0..pixels.len()).map(|i| StarMatch { image_star: i, star: i, votes: 1 }).collect();
+        (pixels, stars, matches)
+    }
+
+    #[test]
+    fn fits_tangent_point_of_wide_field() {
+        let truth = truth(None);
+        let (pixels, stars, matches) = plate(&truth);
+        let solution = fit(&pixels, &stars, matches, 3, &FitModel::default()).unwrap();
+        // The centroid errors alone: 0.04 pixels rms
+        assert!(solution.stats.rms_arcsec < 2.0, "rms {}", solution.stats.rms_arcsec);
+        assert!((solution.center.ra.0 - 80.0).abs() < 1e-2 && (solution.center.dec.0 - 30.0).abs() < 1e-2);
+        assert!((solution.reference_pixel[0] - 300.0).abs() < 0.5 && (solution.reference_pixel[1] - 200.0).abs() < 0.5);
+        assert!((solution.scale_arcsec_px - 36.0).abs() < 1e-3);
+        assert!((solution.rotation_deg - 30.0).abs() < 1e-2);
+        assert_eq!(solution.parity, Parity::Normal);
+        assert_eq!(solution.stats.rejected, 0);
+    }
+
+    #[test]
+    fn fits_at_a_fixed_reference_pixel() {
+        let truth = truth(None);
+        let (pixels, stars, matches) = plate(&truth);
+        // At the true tangent point, it fits as well as the free one
+        let model = FitModel { reference_pixel: ReferencePixel::Fixed([300.0, 200.0]), ..FitModel::default() };
+        let solution = fit(&pixels, &stars, matches.clone(), 3, &model).unwrap();
+        assert_eq!(solution.reference_pixel, [300.0, 200.0]);
+        assert!((solution.center.ra.0 - 80.0).abs() < 1e-3 && (solution.center.dec.0 - 30.0).abs() < 1e-3);
+        assert!(solution.stats.rms_arcsec < 2.0, "rms {}", solution.stats.rms_arcsec);
+        // Half a degree away, the tangent point follows the pixel, at the cost of the projection residuals of a wide field
+        let model = FitModel { reference_pixel: ReferencePixel::Fixed([350.0, 230.0]), ..FitModel::default() };
+        let solution = fit(&pixels, &stars, matches, 3, &model).unwrap();
+        assert_eq!(solution.reference_pixel, [350.0, 230.0]);
+        let sky = truth.pixel_to_sky(350.0, 230.0);
+        assert!((solution.center.ra.0 - sky.ra.0).abs() < 0.05 && (solution.center.dec.0 - sky.dec.0).abs() < 0.05);
+        assert!(solution.stats.rms_arcsec > 2.0);
+    }
+
+    #[test]
+    fn rejects_outliers() {
+        let truth = truth(None);
+        let (pixels, mut stars, matches) = plate(&truth);
+        stars[100].dec_rad = Radians(stars[100].dec_rad.0 + (60.0 / 3600.0_f64).to_radians());
+        let solution = fit(&pixels, &stars, matches, 3, &FitModel::default()).unwrap();
+        assert_eq!(solution.stats.rejected, 1);
+        assert!(solution.matches.iter().all(|m| m.star != 100));
+        assert!(solution.stats.rms_arcsec < 2.0);
+    }
+
+    #[test]
+    fn fits_sip_distortion() {
+        let mut a = vec![0.0; 9];
+        let mut b = vec![0.0; 9];
+        a[2 * 3] = 2e-6; // u^2
+        b[2] = -1e-6; // v^2
+        b[3 + 1] = 5e-7; // u v
+        let sip = Sip { a: SipPolynomial { order: 2, coefficients: a }, b: SipPolynomial { order: 2, coefficients: b }, ap: None, bp: None };
+        let truth = truth(Some(sip));
+        let (pixels, stars, matches) = plate(&truth);
+        let linear = fit(&pixels, &stars, matches.clone(), 3, &FitModel::default()).unwrap();
+        assert!(linear.sip.is_none());
+        let solution = fit(&pixels, &stars, matches, 3, &FitModel { sip_order: Some(2), ..FitModel::default() }).unwrap();
+        assert!(linear.stats.rms_arcsec > 10.0, "linear rms {}", linear.stats.rms_arcsec);
+        assert!(solution.stats.rms_arcsec < 2.0, "rms {}", solution.stats.rms_arcsec);
+        assert_eq!(solution.stats.rejected, 0);
+        assert_eq!(solution.sip.as_ref().map(|sip| sip.a.order), Some(2));
+    }
+
+    #[test]
+    fn round_trips_pixels() {
+        let mut a = vec![0.0; 9];
+        a[1] = 1e-6;
+        let sip = Sip { a: SipPolynomial { order: 2, coefficients: a }, b: SipPolynomial { order: 2, coefficients: vec![0.0; 9] }, ap: None, bp: None };
+        let truth = truth(Some(sip));
+        for &(x, y) in &[(1.0, 1.0), (2400.0, 1600.0), (1200.0, 10.0)] {
+            let sky = truth.pixel_to_sky(x, y);
+            let (x2, y2) = truth.sky_to_pixel(sky.ra, sky.dec).unwrap();
+            assert!((x - x2).abs() < 1e-6 && (y - y2).abs() < 1e-6);
+        }
+    }
+
+    /// Moves a position towards a zenith by scale * 120" * tan(zenith distance), radians
+    fn refract(ra: f64, dec: f64, zenith: (f64, f64), scale: f64) -> (f64, f64) {
+        let vector = |ra: f64, dec: f64| [libm::cos(dec) * libm::cos(ra), libm::cos(dec) * libm::sin(ra), libm::sin(dec)];
+        let (p, z) = (vector(ra, dec), vector(zenith.0, zenith.1));
+        let cos_z: f64 = (0..3).map(|i| p[i] * z[i]).sum();
+        let towards: Vec<f64> = (0..3).map(|i| z[i] - cos_z * p[i]).collect();
+        let norm = libm::sqrt(towards.iter().map(|t| t * t).sum::<f64>());
+        let shift = scale * (120.0 / 3600.0_f64).to_radians() * norm / cos_z;
+        let moved: Vec<f64> = (0..3).map(|i| p[i] + shift * towards[i] / norm).collect();
+        (libm::atan2(moved[1], moved[0]), libm::atan2(moved[2], libm::hypot(moved[0], moved[1])))
+    }
+
+    #[test]
+    fn fits_refraction_scale() {
+        let truth = truth(None);
+        let (pixels, mut stars, matches) = plate(&truth);
+        let zenith = (80.0_f64.to_radians(), 70.0_f64.to_radians());
+        // The detections are 20% more refracted than modelled in the catalog positions
+        let unrefracted: Vec<(f64, f64)> = stars.iter().map(|star| (star.ra_rad.0, star.dec_rad.0)).collect();
+        let mut observed = Vec::new();
+        for (star, &(ra, dec)) in stars.iter_mut().zip(&unrefracted) {
+            let (x, y) = pixels[observed.len()];
+            let (ra_obs, dec_obs) = refract(ra, dec, zenith, 1.2);
+            let (x_true, y_true) = truth.sky_to_pixel(Degrees(ra_obs.to_degrees()), Degrees(dec_obs.to_degrees())).unwrap();
+            let (x_clean, y_clean) = truth.sky_to_pixel(Degrees(ra.to_degrees()), Degrees(dec.to_degrees())).unwrap();
+            // Keep the centroid errors of the plate
+            observed.push((x_true + x - x_clean, y_true + y - y_clean));
+            let (ra_model, dec_model) = refract(ra, dec, zenith, 1.0);
+            star.ra_rad = Radians(ra_model);
+            star.dec_rad = Radians(dec_model);
+        }
+        let plain = fit(&observed, &stars, matches.clone(), 3, &FitModel::default()).unwrap();
+        assert!(plain.refraction_scale.is_none());
+        let model = FitModel { refraction: Some(unrefracted), ..FitModel::default() };
+        let solution = fit(&observed, &stars, matches, 3, &model).unwrap();
+        let scale = solution.refraction_scale.unwrap();
+        assert!((scale - 1.2).abs() < 0.05, "refraction scale {}", scale);
+        assert!(solution.stats.rms_arcsec < plain.stats.rms_arcsec, "rms {} plain {}", solution.stats.rms_arcsec, plain.stats.rms_arcsec);
+        assert!(solution.stats.rms_arcsec < 2.0);
+    }
+
+    #[test]
+    fn rejects_collinear_stars() {
+        let truth = truth(None);
+        let (pixels, stars, _) = plate(&truth);
+        // One column of the grid: the detections are on a line
+        let matches: Vec<StarMatch> = (0..16).map(|i| StarMatch { image_star: i, star: i, votes: 1 }).collect();
+        let collinear: Vec<(f64, f64)> = pixels.iter().map(|&(_, y)| (50.0, y)).collect();
+        assert!(fit(&collinear, &stars, matches, 3, &FitModel::default()).is_none());
+    }
+}
@@ -0,0 +1,26 @@
+use std::path::Path;
+
+use crate::archive::Footprint;
+use crate::wcs::Wcs;
+
+/// Aladin Lite (v3) snippet drawing the image footprint over a HiPS survey. None if the image size is unknown.
+pub fn footprint_snippet(path: &Path, wcs: &Wcs, survey: &str) -> Option<String> {
+    let footprint = Footprint::from_wcs(path, wcs)?;
+    let corners = wcs.corners()?.iter()
+        .map(|(ra, dec)| format!("[{:.6}, {:.6}]", ra, dec))
+        .collect::<Vec<String>>()
+        .join(", ");
+    let mut snippet = String::new();
+    snippet.push_str("<div id=\"aladin-lite-div\" style=\"width:600px;height:600px;\"></div>\n");
+    snippet.push_str("<script src=\"https://aladin.cds.unistra.fr/AladinLite/api/v3/latest/aladin.js\" charset=\"utf-8\"></script>\n");
+    snippet.push_str("<script>\n");
+    snippet.push_str("A.init.then(() => {\n");
+    snippet.push_str(&format!("    let aladin = A.aladin('#aladin-lite-div', {{survey: '{}', target: '{:.6} {:.6}', fov: {:.4}}});\n",
+        survey, footprint.ra, footprint.dec, footprint.radius * 3.0));
+    snippet.push_str("    let overlay = A.graphicOverlay({color: '#ee2345', lineWidth: 2});\n");
+    snippet.push_str("    aladin.addOverlay(overlay);\n");
+    snippet.push_str(&format!("    overlay.add(A.polygon([{}]));\n", corners));
+    snippet.push_str("});\n");
+    snippet.push_str("</script>\n");
+    Some(snippet)
+}
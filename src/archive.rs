@@ -0,0 +1,117 @@
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+use log::{debug};
+
+use rastap_core::angle::{Degrees, Radians};
+
+use crate::math;
+use crate::wcs::Wcs;
+
+/// Sky footprint of a solved frame, approximated by a circle
+pub struct Footprint {
+    pub path: PathBuf,
+    pub ra: f64,     // Center, degrees
+    pub dec: f64,    // Center, degrees
+    pub radius: f64, // Distance from the center to the farthest corner, degrees
+    pub scale: f64,  // Arcseconds per pixel
+}
+
+impl Footprint {
+    /// Footprint of a WCS. None if the image size is unknown.
+    pub fn from_wcs(path: &Path, wcs: &Wcs) -> Option<Footprint> {
+        let (ra, dec) = wcs.center()?;
+        let radius = wcs.corners()?.iter()
+            .map(|(corner_ra, corner_dec)| separation_deg(ra, dec, *corner_ra, *corner_dec))
+            .fold(0.0, f64::max);
+        Some(Footprint { path: path.to_path_buf(), ra, dec, radius, scale: wcs.pixel_scale() })
+    }
+
+    /// Angular distance between the centers, in degrees
+    pub fn separation(&self, other: &Footprint) -> f64 {
+        separation_deg(self.ra, self.dec, other.ra, other.dec)
+    }
+
+    /// Whether the footprints overlap
+    pub fn overlaps(&self, other: &Footprint) -> bool {
+        self.separation(other) < self.radius + other.radius
+    }
+
+    /// Whether both frames show the same field: centers within 10% of the field radius and similar scale
+    pub fn duplicates(&self, other: &Footprint) -> bool {
+        self.separation(other) < 0.1 * self.radius.min(other.radius) && (self.scale / other.scale - 1.0).abs() < 0.1
+    }
+}
+
+/// Angular separation in degrees
+fn separation_deg(ra1: f64, dec1: f64, ra2: f64, dec2: f64) -> f64 {
+    let rad = |degrees: f64| Radians::from(Degrees(degrees));
+    Degrees::from(math::angular_separation_radians(rad(ra1), rad(dec1), rad(ra2), rad(dec2))).0
+}
+
+/// Reads the WCS header files (.wcs) in a directory, sorted by path.
+/// Files that can't be parsed are skipped and reported.
+pub fn read_frames(dir: &Path) -> io::Result<Vec<(PathBuf, Wcs)>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "wcs"))
+        .collect();
+    paths.sort();
+    let mut frames = Vec::new();
+    for path in paths {
+        match Wcs::from_file(&path) {
+            Ok(wcs) => frames.push((path, wcs)),
+            Err(err) => println!("Skipping {}: {}", path.display(), err),
+        }
+    }
+    Ok(frames)
+}
+
+/// Reads the footprints of the WCS header files (.wcs) in a directory, sorted by path.
+/// Files that can't be parsed or lack the image size are skipped and reported.
+pub fn read_footprints(dir: &Path) -> io::Result<Vec<Footprint>> {
+    let mut footprints = Vec::new();
+    for (path, wcs) in read_frames(dir)? {
+        match Footprint::from_wcs(&path, &wcs) {
+            Some(footprint) => footprints.push(footprint),
+            None => println!("Skipping {}: no image size (IMAGEW/IMAGEH)", path.display()),
+        }
+    }
+    debug!("Archive > {} footprints in {}", footprints.len(), dir.display());
+    Ok(footprints)
+}
+
+/// Groups the footprints connected by overlaps. Returns the indices of each group with more than one frame.
+pub fn overlapping_groups(footprints: &[Footprint]) -> Vec<Vec<usize>> {
+    // Union-find over the overlapping pairs
+    let mut parent: Vec<usize> = (0..footprints.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..footprints.len() {
+        for j in (i + 1)..footprints.len() {
+            if footprints[i].overlaps(&footprints[j]) {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of_root = vec![usize::MAX; footprints.len()];
+    for i in 0..footprints.len() {
+        let r = root(&mut parent, i);
+        if group_of_root[r] == usize::MAX {
+            group_of_root[r] = groups.len();
+            groups.push(Vec::new());
+        }
+        groups[group_of_root[r]].push(i);
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
@@ -0,0 +1,51 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use rastap_core::angle::Degrees;
+
+use crate::polygon::Star;
+use crate::wcs::Wcs;
+
+/// Gnomonic chart of `size` x `size` pixels covering `fov` degrees around a center (R.A., Dec in degrees)
+pub fn chart_wcs(ra: f64, dec: f64, fov: f64, size: u32) -> Wcs {
+    let scale = fov / size as f64;
+    let center = (size as f64 + 1.0) / 2.0;
+    Wcs {
+        crval: [ra, dec],
+        crpix: [center, center],
+        cd: [[-scale, 0.0], [0.0, scale]], // North up, East left
+        sip: None,
+        image_size: Some([size as f64, size as f64]),
+    }
+}
+
+/// Writes an SVG star chart: stars sized by magnitude, the brightest labelled with their catalog id
+pub fn write_chart_svg(path: &Path, star_list: &[Star], wcs: &Wcs, magnitude_limit: f64, labels: usize) -> io::Result<()> {
+    let [width, height] = wcs.image_size.unwrap_or([0.0, 0.0]);
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">", width, height, width, height)?;
+    writeln!(writer, "  <rect width=\"100%\" height=\"100%\" fill=\"black\"/>")?;
+    // Stars are sorted by magnitude: the first ones are the brightest
+    for (i, star) in star_list.iter().enumerate() {
+        let (x, y) = match wcs.sky_to_pixel(Degrees::from(star.ra()).0, star.dec().0) {
+            Some(pixel) => pixel,
+            None => continue,
+        };
+        if x < 0.5 || x > width + 0.5 || y < 0.5 || y > height + 0.5 {
+            continue;
+        }
+        // FITS pixel (Y up) to SVG (Y down)
+        let (svg_x, svg_y) = (x - 0.5, height - (y - 0.5));
+        let radius = 1.0 + 0.8 * (magnitude_limit - star.magnitude).max(0.0);
+        writeln!(writer, "  <circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"white\"><title>{} mag {:.2}</title></circle>", svg_x, svg_y, radius, star.db_id, star.magnitude)?;
+        if i < labels {
+            writeln!(writer, "  <text x=\"{:.2}\" y=\"{:.2}\" fill=\"yellow\" font-family=\"helvetica\" font-size=\"10\">{} ({:.1})</text>", svg_x + radius + 2.0, svg_y + 3.0, star.db_id, star.magnitude)?;
+        }
+    }
+    writeln!(writer, "</svg>")?;
+    writer.flush()
+}
@@ -0,0 +1,49 @@
+use rastap_core::angle::{Degrees, Radians};
+
+use crate::math;
+use crate::wcs::Wcs;
+
+/// Differences between two solutions of the same frame, B relative to A
+pub struct Comparison {
+    pub center_offset: f64,       // Distance between the sky positions of the frame center, arcseconds
+    pub scale_difference: f64,    // Arcseconds per pixel
+    pub rotation_difference: f64, // Degrees, in (-180, 180]
+}
+
+/// Pixel position of the frame center: the image center if its size is known, else the reference pixel of A
+fn center_pixel(a: &Wcs) -> (f64, f64) {
+    match a.image_size {
+        Some([width, height]) => ((width + 1.0) / 2.0, (height + 1.0) / 2.0),
+        None => (a.crpix[0], a.crpix[1]),
+    }
+}
+
+/// Compares two solutions of the same frame
+pub fn compare(a: &Wcs, b: &Wcs) -> Comparison {
+    let (x, y) = center_pixel(a);
+    let rotation_difference = 180.0 - (180.0 - (b.rotation() - a.rotation())).rem_euclid(360.0);
+    Comparison {
+        center_offset: position_offset(a, b, x, y),
+        scale_difference: b.pixel_scale() - a.pixel_scale(),
+        rotation_difference,
+    }
+}
+
+/// Distance between the sky positions of a pixel in both solutions, arcseconds
+pub fn position_offset(a: &Wcs, b: &Wcs, x: f64, y: f64) -> f64 {
+    let (ra_a, dec_a) = a.pixel_to_sky(x, y);
+    let (ra_b, dec_b) = b.pixel_to_sky(x, y);
+    let rad = |degrees: f64| Radians::from(Degrees(degrees));
+    Degrees::from(math::haversine_separation_radians(rad(ra_a), rad(dec_a), rad(ra_b), rad(dec_b))).0 * 3600.0
+}
+
+/// Pixel positions sampling the frame: its corners and center, or the reference pixel of A if the image size is unknown
+pub fn sample_pixels(a: &Wcs) -> Vec<(f64, f64)> {
+    let mut pixels = Vec::new();
+    if let Some([width, height]) = a.image_size {
+        let (x_max, y_max) = (width + 0.5, height + 0.5);
+        pixels.extend_from_slice(&[(0.5, 0.5), (x_max, 0.5), (x_max, y_max), (0.5, y_max)]);
+    }
+    pixels.push(center_pixel(a));
+    pixels
+}
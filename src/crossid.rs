@@ -0,0 +1,69 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::Path,
+};
+use log::{debug};
+
+use crate::star_catalog::Catalog;
+
+/// Identifier mapping table, installed beside the star catalogs: gaia_source_id, hip and tyc columns
+pub const DB_PATH: &str = "catalog-crossid.csv";
+
+/// Identifiers of a star in the catalogs
+#[derive(Clone, Debug, Default)]
+pub struct Identifiers {
+    pub gaia: Option<u64>,   // Gaia DR2 source_id
+    pub hip: Option<u64>,    // Hipparcos
+    pub tyc: Option<String>, // Tycho-2, TYC1-TYC2-TYC3
+}
+
+/// Whether the identifier mapping table is installed
+pub fn installed() -> bool {
+    Path::new(DB_PATH).exists()
+}
+
+/// Own identifiers of a catalog star: the Gaia source_id, or the Hipparcos number (HYG, 0 if none)
+pub fn own_identifiers(catalog: Catalog, db_id: u64) -> Identifiers {
+    match catalog {
+        Catalog::Gaia => Identifiers { gaia: Some(db_id), ..Identifiers::default() },
+        Catalog::Hyg => Identifiers { hip: Some(db_id).filter(|&hip| hip > 0), ..Identifiers::default() },
+    }
+}
+
+/// Identifiers of catalog stars by their catalog id, completed from the mapping table. Only the rows of
+/// the requested stars are kept in memory.
+pub fn read_identifiers(path: &Path, catalog: Catalog, db_ids: &[u64]) -> Result<HashMap<u64, Identifiers>, Box<dyn Error>> {
+    let mut identifiers: HashMap<u64, Identifiers> = db_ids.iter().map(|&id| (id, own_identifiers(catalog, id))).collect();
+    let wanted: HashSet<u64> = db_ids.iter().cloned().collect();
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    debug!("Read identifiers > Headers > {:?}", headers);
+    let column = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("{}: column {} not found", path.display(), name));
+    let (gaia, hip, tyc) = (column("gaia_source_id")?, column("hip")?, column("tyc")?);
+    let key = match catalog {
+        Catalog::Gaia => gaia,
+        Catalog::Hyg => hip,
+    };
+    for (n, row) in reader.records().enumerate() {
+        let record = row?;
+        let number = |i: usize| -> Result<Option<u64>, Box<dyn Error>> {
+            match record.get(i).map(|value| value.trim()) {
+                None | Some("") => Ok(None),
+                Some(value) => Ok(Some(value.parse::<u64>().map_err(|_| format!("{}: row {}: invalid identifier '{}'", path.display(), n + 1, value))?)),
+            }
+        };
+        let id = match number(key)? {
+            Some(id) if wanted.contains(&id) => id,
+            _ => continue,
+        };
+        let entry = identifiers.entry(id).or_default();
+        entry.gaia = entry.gaia.or(number(gaia)?);
+        entry.hip = entry.hip.or(number(hip)?);
+        if entry.tyc.is_none() {
+            entry.tyc = record.get(tyc).map(|value| value.trim().to_owned()).filter(|value| !value.is_empty());
+        }
+    }
+    Ok(identifiers)
+}
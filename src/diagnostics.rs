@@ -0,0 +1,282 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::image::ImageStar;
+use crate::math;
+use crate::polygon::{self, Polygon, Star};
+
+/// Number of histogram bins
+const BINS: usize = 20;
+
+/// Lowest best similarity in the histogram
+const MIN_BEST_SIMILARITY: f64 = 0.8;
+
+/// Histogram of values in [min, max]. Values outside are clamped to the first/last bin.
+fn histogram(values: &[f64], min: f64, max: f64) -> Vec<usize> {
+    let mut bins = vec![0_usize; BINS];
+    for value in values {
+        let bin = ((value - min) / (max - min) * BINS as f64).floor().max(0.0) as usize;
+        bins[bin.min(BINS - 1)] += 1;
+    }
+    bins
+}
+
+/// Median of a list of values
+pub fn median(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    Some(sorted[sorted.len() / 2])
+}
+
+/// Normalized connection lengths of the polygons, except the longest one (always 1)
+fn edge_ratios(polygons: &[Polygon]) -> Vec<f64> {
+    let mut ratios = Vec::new();
+    for polygon in polygons {
+        let length_list = polygon.length_list();
+        ratios.extend_from_slice(&length_list[..length_list.len() - 1]);
+    }
+    ratios
+}
+
+/// Angular separation of two catalog stars, in radians
+fn sky_separation(a: &Star, b: &Star) -> f64 {
+    math::angular_separation_radians(a.ra_rad, a.dec_rad, b.ra_rad, b.dec_rad).0
+}
+
+/// Distance between two image stars on the image plane, in radians at the image scale
+fn plane_separation(a: &Star, b: &Star) -> f64 {
+    (b.ra_rad.0 - a.ra_rad.0).hypot(b.dec_rad.0 - a.dec_rad.0)
+}
+
+/// Polygon size in radians: longest separation between its stars
+fn polygon_size(star_list: &[Star], polygon: &Polygon, separation: fn(&Star, &Star) -> f64) -> f64 {
+    let stars = polygon.star_list();
+    let mut size = 0.0_f64;
+    for i in 0..stars.len() {
+        for j in (i + 1)..stars.len() {
+            size = size.max(separation(&star_list[stars[i]], &star_list[stars[j]]));
+        }
+    }
+    size
+}
+
+/// Log10 of the polygon sizes in arcseconds
+fn log_sizes(star_list: &[Star], polygons: &[Polygon], separation: fn(&Star, &Star) -> f64) -> Vec<f64> {
+    polygons.iter()
+        .map(|polygon| polygon_size(star_list, polygon, separation))
+        .filter(|size| *size > 0.0)
+        .map(|size| (size.to_degrees() * 3600.0).log10())
+        .collect()
+}
+
+/// Best similarity of each image polygon to any star database polygon
+fn best_similarities(image_polygons: &[Polygon], star_polygons: &[Polygon]) -> Vec<f64> {
+    image_polygons.iter()
+        .map(|image_pol| star_polygons.iter().map(|star_pol| polygon::polygon_similarity(image_pol, star_pol)).fold(0.0, f64::max))
+        .collect()
+}
+
+/// Writes histograms of the polygon code space of the catalog and the image (edge ratios, sizes
+/// and best similarities) as CSV, and prints a summary pointing at scale and tolerance problems
+pub fn write_code_space(path: &Path, star_list: &[Star], star_polygons: &[Polygon], image_star_list: &[Star], image_polygons: &[Polygon]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "quantity,bin_low,bin_high,catalog,image")?;
+
+    // Edge ratios
+    let star_ratios = edge_ratios(star_polygons);
+    let image_ratios = edge_ratios(image_polygons);
+    let (star_bins, image_bins) = (histogram(&star_ratios, 0.0, 1.0), histogram(&image_ratios, 0.0, 1.0));
+    for i in 0..BINS {
+        writeln!(writer, "edge_ratio,{},{},{},{}", i as f64 / BINS as f64, (i + 1) as f64 / BINS as f64, star_bins[i], image_bins[i])?;
+    }
+
+    // Polygon sizes
+    let star_sizes = log_sizes(star_list, star_polygons, sky_separation);
+    let image_sizes = log_sizes(image_star_list, image_polygons, plane_separation);
+    let all_sizes = star_sizes.iter().chain(image_sizes.iter());
+    let min = all_sizes.clone().cloned().fold(f64::MAX, f64::min).floor();
+    let max = all_sizes.cloned().fold(f64::MIN, f64::max).ceil().max(min + 1.0);
+    let (star_bins, image_bins) = (histogram(&star_sizes, min, max), histogram(&image_sizes, min, max));
+    let step = (max - min) / BINS as f64;
+    for i in 0..BINS {
+        writeln!(writer, "log10_size_arcsec,{},{},{},{}", min + i as f64 * step, min + (i + 1) as f64 * step, star_bins[i], image_bins[i])?;
+    }
+
+    // Best similarity of the image polygons
+    let similarities = best_similarities(image_polygons, star_polygons);
+    let bins = histogram(&similarities, MIN_BEST_SIMILARITY, 1.0);
+    let step = (1.0 - MIN_BEST_SIMILARITY) / BINS as f64;
+    for (i, count) in bins.iter().enumerate() {
+        writeln!(writer, "best_similarity,{},{},,{}", MIN_BEST_SIMILARITY + i as f64 * step, MIN_BEST_SIMILARITY + (i + 1) as f64 * step, count)?;
+    }
+    writer.flush()?;
+
+    // Summary
+    println!("Diagnostics > Edge ratio median: catalog {:.3} image {:.3}",
+        median(&star_ratios).unwrap_or(f64::NAN), median(&image_ratios).unwrap_or(f64::NAN));
+    if let (Some(star_size), Some(image_size)) = (median(&star_sizes), median(&image_sizes)) {
+        let ratio = 10.0_f64.powf(image_size - star_size);
+        println!("Diagnostics > Polygon size median: catalog {:.1}\" image {:.1}\" (image/catalog {:.2})",
+            10.0_f64.powf(star_size), 10.0_f64.powf(image_size), ratio);
+        if !(1.0 / 1.5..=1.5).contains(&ratio) {
+            println!("Diagnostics > Polygon sizes differ, check --scale or the catalog magnitude limit (--male)");
+        }
+    }
+    let similar = similarities.iter().filter(|s| **s >= polygon::MIN_SIMILARITY).count();
+    let near = similarities.iter().filter(|s| **s < polygon::MIN_SIMILARITY && **s >= polygon::MIN_SIMILARITY - 0.02).count();
+    println!("Diagnostics > Image polygons with a similar catalog polygon: {} of {}, just below the tolerance: {}",
+        similar, similarities.len(), near);
+    if near > similar {
+        println!("Diagnostics > Many polygons fall just below the tolerance, centroid noise may be too large for it");
+    }
+    Ok(())
+}
+
+/// Solve outcome, for batch pipelines: printed last and returned as the exit code
+pub enum Outcome {
+    Matched { star_matches: usize },
+    NoSolution { star_matches: usize },
+    NotEnoughDetections { count: usize, minimum: usize },
+    Diagnosed { star_polygons: usize, image_polygons: usize }, // Code space written, no matching
+}
+
+impl Outcome {
+    /// Process exit code. 1 is left for errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Matched { .. } | Outcome::Diagnosed { .. } => 0,
+            Outcome::NotEnoughDetections { .. } => 2,
+            Outcome::NoSolution { .. } => 3,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Matched { star_matches } => write!(f, "Matched star_matches={}", star_matches),
+            Outcome::NoSolution { star_matches } => write!(f, "NoSolution star_matches={}", star_matches),
+            Outcome::NotEnoughDetections { count, minimum } => write!(f, "NotEnoughDetections count={} minimum={}", count, minimum),
+            Outcome::Diagnosed { star_polygons, image_polygons } => write!(f, "Diagnosed star_polygons={} image_polygons={}", star_polygons, image_polygons),
+        }
+    }
+}
+
+/// Minimum star matches for a solution
+pub const MIN_STAR_MATCHES: usize = 3;
+
+/// Ratio of the nearest-neighbour scale estimate to the given scale beyond which the scale is probably wrong
+const MAX_SCALE_RATIO: f64 = 1.5;
+
+/// Detections below which the image is considered sparse
+const FEW_DETECTIONS: usize = 20;
+
+/// Probable cause of a failed solve
+pub enum Diagnosis {
+    TooFewDetections { count: usize, minimum: usize },
+    CatalogTooSparse { expected: f64, detections: usize },
+    CatalogTooDense { expected: f64, detections: usize },
+    ScaleMismatch { given: f64, estimated: f64 },
+    FieldLargerThanCone { field_radius_deg: f64, radii_deg: f64 },
+    HintOffset,
+    NoPolygons { list: &'static str },
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Diagnosis::TooFewDetections { count, minimum } =>
+                write!(f, "Too few detections ({}, at least {} needed). Extract deeper (lower the SExtractor DETECT_THRESH)", count, minimum),
+            Diagnosis::CatalogTooSparse { expected, detections } =>
+                write!(f, "Catalog sparser than the image ({:.0} catalog stars expected in the field, {} detections). Raise --male", expected, detections),
+            Diagnosis::CatalogTooDense { expected, detections } =>
+                write!(f, "Catalog denser than the image ({:.0} catalog stars expected in the field, {} detections). Lower --male or check --scale", expected, detections),
+            Diagnosis::ScaleMismatch { given, estimated } =>
+                write!(f, "Probable scale error: --scale {} but nearest-neighbour separations suggest {:.3}. Try --scale {:.3}", given, estimated, estimated),
+            Diagnosis::FieldLargerThanCone { field_radius_deg, radii_deg } =>
+                write!(f, "Image field radius {:.2} deg exceeds the search radius {} deg. Raise --radii", field_radius_deg, radii_deg),
+            Diagnosis::HintOffset =>
+                write!(f, "Detections and catalog look compatible, the search center may be off. Check --ra/--dec or raise --radii"),
+            Diagnosis::NoPolygons { list } =>
+                write!(f, "No polygons built from the {}. Check --max-polygons, --polygons-per-cell, --min-edge-ratio and --min-area-ratio", list),
+        }
+    }
+}
+
+/// Detected field: bounding box area (pixels²) and radius (pixels) of the detections
+pub fn detection_field(image_star_list: &[ImageStar]) -> (f64, f64) {
+    let mut min = (f64::MAX, f64::MAX);
+    let mut max = (f64::MIN, f64::MIN);
+    for star in image_star_list {
+        min = (min.0.min(star.pixel_x), min.1.min(star.pixel_y));
+        max = (max.0.max(star.pixel_x), max.1.max(star.pixel_y));
+    }
+    let (width, height) = ((max.0 - min.0).max(0.0), (max.1 - min.1).max(0.0));
+    (width * height, width.hypot(height) / 2.0)
+}
+
+/// Diagnose why the catalog and image stars didn't match
+pub fn diagnose_failure(star_list: &[Star], image_star_list: &[ImageStar], estimated_scale: Option<f64>, scale: f64, radii_deg: f64) -> Vec<Diagnosis> {
+    let mut diagnosis = Vec::new();
+    let detections = image_star_list.len();
+    if detections < FEW_DETECTIONS {
+        diagnosis.push(Diagnosis::TooFewDetections { count: detections, minimum: FEW_DETECTIONS });
+    }
+    // Catalog stars expected in the detected field, from the catalog density in the search cone
+    let (area_px, radius_px) = detection_field(image_star_list);
+    let cone_area_deg = 2.0 * std::f64::consts::PI * (1.0 - radii_deg.to_radians().cos()) * (180.0 / std::f64::consts::PI).powi(2);
+    let field_area_deg = area_px * (scale / 3600.0).powi(2);
+    let expected = star_list.len() as f64 / cone_area_deg * field_area_deg;
+    if expected < 0.5 * detections as f64 {
+        diagnosis.push(Diagnosis::CatalogTooSparse { expected, detections });
+    } else if expected > 4.0 * detections as f64 {
+        diagnosis.push(Diagnosis::CatalogTooDense { expected, detections });
+    }
+    // The nearest-neighbour estimate is rough: only a large ratio points at the scale
+    if let Some(estimated) = estimated_scale {
+        if !(1.0 / MAX_SCALE_RATIO..=MAX_SCALE_RATIO).contains(&(estimated / scale)) {
+            diagnosis.push(Diagnosis::ScaleMismatch { given: scale, estimated });
+        }
+    }
+    let field_radius_deg = radius_px * scale / 3600.0;
+    if field_radius_deg > radii_deg {
+        diagnosis.push(Diagnosis::FieldLargerThanCone { field_radius_deg, radii_deg });
+    }
+    if diagnosis.is_empty() {
+        diagnosis.push(Diagnosis::HintOffset);
+    }
+    diagnosis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rastap_core::angle::{Degrees, Hours, Radians};
+
+    #[test]
+    fn catalog_polygon_size_crosses_ra_zero() {
+        // Three stars 0.1 deg apart on the equator, on both sides of R.A. 0h
+        let star_list: Vec<Star> = [23.99, 0.0, 0.01].iter().enumerate()
+            .map(|(i, ra)| Star::sky(i as u64, i as u64, Hours(*ra), Degrees(0.0), 5.0))
+            .collect();
+        let polygon = Polygon {
+            star_index: 0,
+            edges: 3,
+            stars: [0, 1, 2, 0, 0, 0],
+            lengths: [0.0; polygon::MAX_POLYGON_CONNECTIONS],
+            center_ra_rad: Radians(0.0),
+            center_dec_rad: Radians(0.0),
+            length_error: 0.0,
+        };
+        let size = Degrees::from(Radians(polygon_size(&star_list, &polygon, sky_separation))).0;
+        assert!((size - 0.3).abs() < 1e-9);
+        assert_eq!(median(&[3.0, f64::NAN, 1.0]), Some(3.0));
+    }
+}
@@ -0,0 +1,98 @@
+use std::str::FromStr;
+
+/// Right Ascension output unit
+#[derive(Clone, Copy, Debug)]
+pub enum RaUnit {
+    Degrees,
+    Hours,
+}
+
+/// Right Ascension unit names, as accepted by the command line
+pub const RA_UNIT_NAMES: &[&str] = &["deg", "hours"];
+
+impl FromStr for RaUnit {
+    type Err = String;
+
+    fn from_str(unit: &str) -> Result<RaUnit, String> {
+        match unit {
+            "deg" => Ok(RaUnit::Degrees),
+            "hours" => Ok(RaUnit::Hours),
+            _ => Err(format!("Unknown R.A. unit '{}', expected deg or hours", unit)),
+        }
+    }
+}
+
+impl RaUnit {
+    /// Converts an R.A. in this unit to hours, checking its range
+    pub fn to_hours(self, ra: f64) -> Result<f64, String> {
+        match self {
+            RaUnit::Hours if (0.0..24.0).contains(&ra) => Ok(ra),
+            RaUnit::Hours => Err(format!("R.A. {} is out of the [0, 24) hours range. If it is in degrees, add --ra-unit deg", ra)),
+            RaUnit::Degrees if (0.0..360.0).contains(&ra) => Ok(ra / 15.0),
+            RaUnit::Degrees => Err(format!("R.A. {} is out of the [0, 360) degrees range", ra)),
+        }
+    }
+}
+
+/// Angular offset output unit
+#[derive(Clone, Copy, Debug)]
+pub enum OffsetUnit {
+    Arcsec,
+    Mas,
+}
+
+/// Angular offset unit names, as accepted by the command line
+pub const OFFSET_UNIT_NAMES: &[&str] = &["arcsec", "mas"];
+
+impl FromStr for OffsetUnit {
+    type Err = String;
+
+    fn from_str(unit: &str) -> Result<OffsetUnit, String> {
+        match unit {
+            "arcsec" => Ok(OffsetUnit::Arcsec),
+            "mas" => Ok(OffsetUnit::Mas),
+            _ => Err(format!("Unknown offset unit '{}', expected arcsec or mas", unit)),
+        }
+    }
+}
+
+/// Units and precision of the angles in the outputs
+#[derive(Clone, Copy, Debug)]
+pub struct Format {
+    pub ra_unit: RaUnit,
+    pub offset_unit: OffsetUnit,
+    pub precision: usize,        // Decimals of R.A. and Dec
+    pub offset_precision: usize, // Decimals of offsets
+}
+
+impl Format {
+    /// Unit symbol of the R.A. values (VOTable style)
+    pub fn ra_unit_symbol(&self) -> &str {
+        match self.ra_unit {
+            RaUnit::Degrees => "deg",
+            RaUnit::Hours => "h",
+        }
+    }
+
+    /// R.A. given in degrees, in the output unit and precision
+    pub fn ra(&self, ra_deg: f64) -> String {
+        let value = match self.ra_unit {
+            RaUnit::Degrees => ra_deg,
+            RaUnit::Hours => ra_deg / 15.0,
+        };
+        format!("{:.*}", self.precision, value)
+    }
+
+    /// Dec in degrees, in the output precision
+    pub fn dec(&self, dec_deg: f64) -> String {
+        format!("{:.*}", self.precision, dec_deg)
+    }
+
+    /// Angular offset given in arcseconds, in the output unit and precision, with its unit symbol
+    pub fn offset(&self, arcsec: f64) -> String {
+        match self.offset_unit {
+            OffsetUnit::Arcsec => format!("{:.*}\"", self.offset_precision, arcsec),
+            OffsetUnit::Mas => format!("{:.*}mas", self.offset_precision, arcsec * 1000.0),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::str::FromStr;
+
+use crate::wcs::Wcs;
+
+/// Sensor size in pixels
+#[derive(Clone, Copy, Debug)]
+pub struct Sensor {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Sensor {
+    type Err = String;
+
+    fn from_str(size: &str) -> Result<Sensor, String> {
+        let error = || format!("Invalid sensor size '{}', expected WIDTHxHEIGHT in pixels", size);
+        let mut parts = size.splitn(2, ['x', 'X']);
+        let width = parts.next().ok_or_else(error)?.trim().parse::<u32>().map_err(|_| error())?;
+        let height = parts.next().ok_or_else(error)?.trim().parse::<u32>().map_err(|_| error())?;
+        if width == 0 || height == 0 {
+            return Err(error());
+        }
+        Ok(Sensor { width, height })
+    }
+}
+
+/// Image scale in arcseconds per pixel of a pixel size (micrometres) at a focal length (millimetres)
+pub fn image_scale(pixel_size_um: f64, focal_length_mm: f64) -> f64 {
+    206.264806 * pixel_size_um / focal_length_mm
+}
+
+/// Gnomonic WCS of a sensor pointed at a center (R.A., Dec in degrees), with the Y axis rotated
+/// from the North through the East. North up, East left at rotation 0.
+pub fn pointing_wcs(ra: f64, dec: f64, rotation_deg: f64, sensor: Sensor, scale_arcsec: f64) -> Wcs {
+    let scale = scale_arcsec / 3600.0;
+    let (sin, cos) = rotation_deg.to_radians().sin_cos();
+    let (width, height) = (sensor.width as f64, sensor.height as f64);
+    Wcs {
+        crval: [ra, dec],
+        crpix: [(width + 1.0) / 2.0, (height + 1.0) / 2.0],
+        cd: [[-scale * cos, scale * sin], [scale * sin, scale * cos]],
+        sip: None,
+        image_size: Some([width, height]),
+    }
+}
@@ -6,76 +6,72 @@ use std::{
 };
 use log::{debug};
 
-use byteorder::ByteOrder;
-use byteorder::LittleEndian;
+use rastap_core::angle::{Degrees, Hours, Radians};
+use rastap_core::catalog;
+use rastap_core::limits;
 
 use crate::math;
 use crate::polygon;
 
-// Mini Gaia DR2 record struct (28 bytes)
-// #[repr(C)]
-// struct DbStar {
-//     id: u64,
-//     ra: f64,
-//     dec: f64,
-//     magnitude: f32
-// }
+/// Mini Gaia DR2 database file
+pub const DB_PATH: &str = "mini-gaia-dr2.db";
+
+/// Reads the catalog epoch (Julian year) from the database headers
+pub fn read_epoch() -> Result<f64, Box<dyn Error>> {
+    let mut file = File::open(DB_PATH)?;
+    let mut header_bin = [0u8; catalog::GAIA_HEADER_SIZE];
+    file.read_exact(&mut header_bin)?;
+    Ok(catalog::gaia_epoch(&catalog::gaia_headers(&header_bin)))
+}
 
 /// Reads Gaia DR2 star database CSV file to memory
-pub fn read_stars_from_file(ra_center: f64, dec_center: f64, radii: f64, magnitude_limit: f64) -> Result<Vec<polygon::Star>, Box<dyn Error>> {
-    let ra_center_rad = math::hours_to_radians(ra_center);
-    let dec_center_rad = dec_center.to_radians();
-    let radii_rad = radii.to_radians();
+pub fn read_stars_from_file(ra_center: Hours, dec_center: Degrees, radii: Degrees, magnitude_limit: f64, max_stars: Option<usize>) -> Result<Vec<polygon::Star>, Box<dyn Error>> {
+    let ra_center_rad = Radians::from(ra_center);
+    let dec_center_rad = Radians::from(dec_center);
+    let radii_rad = Radians::from(radii);
+    let cone_box = math::ConeBox::new(ra_center_rad, dec_center_rad, radii_rad);
 
     // Read database
-    let mut star_list: Vec<polygon::Star> = Vec::new();
-    let file = File::open("mini-gaia-dr2.db").unwrap();
+    let mut star_list = limits::BrightestStars::new(max_stars);
+    let file = File::open(DB_PATH)?;
+    // Check the file holds the header and whole records
+    let size = file.metadata()?.len();
+    let header_size = catalog::GAIA_HEADER_SIZE as u64;
+    let record_size = catalog::GAIA_RECORD_SIZE as u64;
+    if size < header_size {
+        return Err(format!("{}: {}", DB_PATH, catalog::CatalogError::ShortHeader { size: size as usize }).into());
+    }
+    let records = (size - header_size) / record_size;
+    if !(size - header_size).is_multiple_of(record_size) {
+        return Err(format!("{}: {}", DB_PATH, catalog::CatalogError::TruncatedRecord { offset: catalog::gaia_record_offset(records) }).into());
+    }
     let mut reader = BufReader::new(file); // Buffered read
     // Read headers
-    let mut headers = Vec::<String>::new();
-    for _i in 0..3 {
-        let mut length = [0u8;1];
-        let mut string = [0u8;255];
-        reader.read_exact(&mut length).unwrap();
-        reader.read_exact(&mut string).unwrap();
-        headers.push(String::from_utf8(string[0..length[0] as usize].to_vec()).unwrap());
-    }
-    // TODO: Parse headers
+    let mut header_bin = [0u8; catalog::GAIA_HEADER_SIZE];
+    reader.read_exact(&mut header_bin)?;
+    let headers = catalog::gaia_headers(&header_bin);
+    debug!("Headers: {:?} epoch: {} records: {}", headers, catalog::gaia_epoch(&headers), records);
     // Read stars
-    let mut star_bin = [0u8;28];
-    let mut n = 0u64;
-    loop {
-        match reader.read_exact(&mut star_bin) {
-            Ok(_) => (),
-            Err(e) => {
-                // Let's suppose this is the end of the file
-                break;
-            }
+    let mut star_bin = [0u8; catalog::GAIA_RECORD_SIZE];
+    for n in 0..records {
+        reader.read_exact(&mut star_bin)?;
+        // Decode and check record
+        let star = catalog::gaia_record_to_star(n, &star_bin);
+        if let Some(problem) = catalog::gaia_star_problem(&star) {
+            return Err(format!("{}: {}", DB_PATH, catalog::CatalogError::CorruptRecord { record: n, offset: catalog::gaia_record_offset(n), problem }).into());
         }
-        // Initialize record
-        let mut star = polygon::Star {
-            id: n,
-            db_id: LittleEndian::read_u64(&star_bin[0..8]),
-            ra: LittleEndian::read_f64(&star_bin[8..16]) / 360.0 * 24.0, // Convert from degrees to hours
-            dec: LittleEndian::read_f64(&star_bin[16..24]),
-            ra_rad: 0.0,
-            dec_rad: 0.0,
-            magnitude: LittleEndian::read_f32(&star_bin[24..28]) as f64,
-        };
-        // Transform degrees/hours to radians
-        star.ra_rad = math::hours_to_radians(star.ra);
-        star.dec_rad = star.dec.to_radians();
-        // Calculate angular separation between star and center
-        let sep_rad = math::angular_separation_radians(ra_center_rad, dec_center_rad, star.ra_rad, star.dec_rad);
-        // Filter by magnitude and angular separation
-        if star.magnitude < magnitude_limit && sep_rad <= radii_rad {
-            // Add star to the list
-            debug!("STAR: ra:{} dec:{} mag:{} sep:{}", star.ra, star.dec, star.magnitude, sep_rad);
-            star_list.push(star);
+        // Filter by magnitude and by the bounding box of the search cone
+        if star.magnitude < magnitude_limit && cone_box.contains(star.ra_rad, star.dec_rad) {
+            // Calculate angular separation between star and center
+            let sep_rad = math::angular_separation_radians(ra_center_rad, dec_center_rad, star.ra_rad, star.dec_rad);
+            // Filter by angular separation
+            if sep_rad <= radii_rad {
+                // Add star to the list
+                debug!("STAR: ra:{} dec:{} mag:{} sep:{}", star.ra().0, star.dec().0, star.magnitude, sep_rad.0);
+                star_list.push(star);
+            }
         }
-        n += 1;
     }
-    // Sort by magnitude
-    star_list.sort_by(|a, b| a.magnitude.partial_cmp(&b.magnitude).unwrap());
-    Ok(star_list)
+    // Sort by magnitude, keeping the brightest stars
+    Ok(star_list.into_sorted())
 }
\ No newline at end of file
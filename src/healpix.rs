@@ -0,0 +1,136 @@
+use std::{
+    f64::consts::{FRAC_PI_2, PI},
+    fs,
+    io,
+    path::Path,
+};
+
+use crate::wcs::{card, string_value, BLOCK_SIZE};
+
+/// Value of the pixels with no data (HEALPix UNSEEN)
+pub const UNSEEN: f32 = -1.6375e30;
+
+/// Number of pixels of a map
+pub fn pixel_count(nside: usize) -> usize {
+    12 * nside * nside
+}
+
+/// Pixel of a sky position in the RING scheme (Source: HEALPix, ang2pix_ring)
+pub fn ang_to_pixel_ring(nside: usize, ra_rad: f64, dec_rad: f64) -> usize {
+    let nside_f = nside as f64;
+    let z = dec_rad.sin();
+    let za = z.abs();
+    let tt = ra_rad.rem_euclid(2.0 * PI) / FRAC_PI_2; // In [0, 4)
+    if za <= 2.0 / 3.0 {
+        // Equatorial region
+        let temp1 = nside_f * (0.5 + tt);
+        let temp2 = nside_f * z * 0.75;
+        let jp = (temp1 - temp2) as i64; // Ascending edge line index
+        let jm = (temp1 + temp2) as i64; // Descending edge line index
+        let ir = nside as i64 + 1 + jp - jm; // Ring number counted from z = 2/3, in [1, 2 nside + 1]
+        let kshift = 1 - (ir & 1);
+        let ip = ((jp + jm - nside as i64 + kshift + 1) / 2).rem_euclid(4 * nside as i64);
+        2 * nside * (nside - 1) + (ir as usize - 1) * 4 * nside + ip as usize
+    } else {
+        // Polar caps
+        let tp = tt - tt.floor();
+        let tmp = nside_f * (3.0 * (1.0 - za)).sqrt();
+        let jp = (tp * tmp) as usize;
+        let jm = ((1.0 - tp) * tmp) as usize;
+        let ir = jp + jm + 1; // Ring number counted from the closest pole
+        let ip = ((tt * ir as f64) as usize).min(4 * ir - 1);
+        if z > 0.0 {
+            2 * ir * (ir - 1) + ip
+        } else {
+            pixel_count(nside) - 2 * ir * (ir + 1) + ip
+        }
+    }
+}
+
+/// Pads FITS data or header bytes to a whole block
+fn pad(bytes: &mut Vec<u8>, fill: u8) {
+    let padding = (BLOCK_SIZE - bytes.len() % BLOCK_SIZE) % BLOCK_SIZE;
+    bytes.resize(bytes.len() + padding, fill);
+}
+
+/// Writes a full sky RING map in equatorial coordinates as a HEALPix FITS binary table,
+/// with a COUNT and a MAG_LIMIT column
+pub fn write_fits_map(path: &Path, nside: usize, counts: &[u32], mag_limits: &[f32]) -> io::Result<()> {
+    let pixels = pixel_count(nside);
+    let mut bytes: Vec<u8> = Vec::new();
+    // Empty primary unit
+    let primary = [
+        card("SIMPLE", "T", "Standard FITS"),
+        card("BITPIX", "8", "No data"),
+        card("NAXIS", "0", "No data"),
+        card("EXTEND", "T", "Extensions follow"),
+        format!("{:<80}", "END"),
+    ];
+    bytes.extend(primary.concat().as_bytes());
+    pad(&mut bytes, b' ');
+    // Binary table: one row per pixel
+    let table = [
+        card("XTENSION", &string_value("BINTABLE"), "Binary table"),
+        card("BITPIX", "8", "Bytes"),
+        card("NAXIS", "2", "Table"),
+        card("NAXIS1", "8", "Row size (bytes)"),
+        card("NAXIS2", &pixels.to_string(), "Rows: pixels"),
+        card("PCOUNT", "0", "No heap"),
+        card("GCOUNT", "1", "One table"),
+        card("TFIELDS", "2", "Columns"),
+        card("TTYPE1", &string_value("COUNT"), "Catalog stars in the pixel"),
+        card("TFORM1", &string_value("J"), "32 bit integer"),
+        card("TTYPE2", &string_value("MAG_LIMIT"), "Faintest star magnitude in the pixel"),
+        card("TFORM2", &string_value("E"), "32 bit float"),
+        card("TUNIT2", &string_value("mag"), "Magnitude"),
+        card("PIXTYPE", &string_value("HEALPIX"), "HEALPix map"),
+        card("ORDERING", &string_value("RING"), "Pixel ordering scheme"),
+        card("COORDSYS", &string_value("C"), "Equatorial coordinates"),
+        card("NSIDE", &nside.to_string(), "Resolution parameter"),
+        card("FIRSTPIX", "0", "First pixel"),
+        card("LASTPIX", &(pixels - 1).to_string(), "Last pixel"),
+        card("INDXSCHM", &string_value("IMPLICIT"), "Row number is the pixel"),
+        card("OBJECT", &string_value("FULLSKY"), "Full sky map"),
+        format!("{:<80}", "END"),
+    ];
+    bytes.extend(table.concat().as_bytes());
+    pad(&mut bytes, b' ');
+    for pixel in 0..pixels {
+        bytes.extend(&(counts[pixel] as i32).to_be_bytes());
+        bytes.extend(&mag_limits[pixel].to_be_bytes());
+    }
+    pad(&mut bytes, 0);
+    fs::write(path, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_pixels_at_nside_one() {
+        // North cap 0-3, equatorial 4-7, south cap 8-11
+        assert_eq!(ang_to_pixel_ring(1, 0.0, FRAC_PI_2), 0);
+        assert_eq!(ang_to_pixel_ring(1, 0.0, 0.0), 4);
+        assert_eq!(ang_to_pixel_ring(1, PI, 0.0), 6);
+        assert_eq!(ang_to_pixel_ring(1, 0.0, -FRAC_PI_2), 8);
+        assert_eq!(ang_to_pixel_ring(1, 2.0 * PI - 1e-9, -FRAC_PI_2), 11);
+        assert_eq!(ang_to_pixel_ring(1, 0.1, 1.2), 0);
+        assert_eq!(ang_to_pixel_ring(1, 2.0 * PI - 0.1, -1.2), 11);
+    }
+
+    #[test]
+    fn covers_every_pixel_at_nside_four() {
+        let nside = 4;
+        let mut seen = vec![false; pixel_count(nside)];
+        for i in 0..=360 {
+            for j in 0..=180 {
+                let (ra, dec) = ((i as f64).to_radians(), (j as f64 - 90.0).to_radians());
+                let pixel = ang_to_pixel_ring(nside, ra, dec);
+                assert!(pixel < pixel_count(nside), "ra:{} dec:{} pixel:{}", i, j, pixel);
+                seen[pixel] = true;
+            }
+        }
+        assert!(seen.iter().all(|&seen| seen));
+    }
+}
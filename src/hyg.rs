@@ -3,52 +3,50 @@ use log::{debug};
 
 use csv;
 
+use rastap_core::angle::{Degrees, Hours, Radians};
+use rastap_core::limits;
+
 use crate::math;
 use crate::polygon;
 
+/// HYG star database file
+pub const DB_PATH: &str = "hygfull-compact.csv";
+
 /// Reads HYG star database CSV file to memory
-pub fn read_stars_from_file(ra_center: f64, dec_center: f64, radii: f64, magnitude_limit: f64) -> Result<Vec<polygon::Star>, Box<dyn Error>> {
-    let ra_center_rad = math::hours_to_radians(ra_center);
-    let dec_center_rad = dec_center.to_radians();
-    let radii_rad = radii.to_radians();
+pub fn read_stars_from_file(ra_center: Hours, dec_center: Degrees, radii: Degrees, magnitude_limit: f64, max_stars: Option<usize>) -> Result<Vec<polygon::Star>, Box<dyn Error>> {
+    let ra_center_rad = Radians::from(ra_center);
+    let dec_center_rad = Radians::from(dec_center);
+    let radii_rad = Radians::from(radii);
+    let cone_box = math::ConeBox::new(ra_center_rad, dec_center_rad, radii_rad);
 
     // Read database
-    let mut star_list: Vec<polygon::Star> = Vec::new();
-    let mut reader = csv::Reader::from_path("hygfull-compact.csv")?;
+    let mut star_list = limits::BrightestStars::new(max_stars);
+    let mut reader = csv::Reader::from_path(DB_PATH)?;
     let headers = reader.headers()?;
     debug!("{:?}", headers);
     for row in reader.records() {
-        // Initialize record
-        let mut star = polygon::Star {
-            id: 0,
-            db_id: 0,
-            ra: 0.0,
-            dec: 0.0,
-            ra_rad: 0.0,
-            dec_rad: 0.0,
-            magnitude: 0.0,
-        };
         // debug!("Row: {:?}", row);
         let record = row?;
-        // Read record data
-        star.id = record.get(0).unwrap().parse::<u32>().unwrap() as u64;
-        star.db_id = record.get(1).unwrap().parse::<u32>().unwrap() as u64;
-        star.ra = record.get(2).unwrap().parse::<f64>().unwrap();
-        star.dec = record.get(3).unwrap().parse::<f64>().unwrap();
-        star.magnitude = record.get(4).unwrap().parse::<f64>().unwrap();
-        // Transform degrees/hours to radians
-        star.ra_rad = math::hours_to_radians(star.ra);
-        star.dec_rad = star.dec.to_radians();
+        // Read record data, transforming degrees/hours to radians
+        let star = polygon::Star::sky(
+            record.get(0).unwrap().parse::<u32>().unwrap() as u64,
+            record.get(1).unwrap().parse::<u32>().unwrap() as u64,
+            Hours(record.get(2).unwrap().parse::<f64>().unwrap()),
+            Degrees(record.get(3).unwrap().parse::<f64>().unwrap()),
+            record.get(4).unwrap().parse::<f64>().unwrap(),
+        );
 
-        // Calculate angular separation between star and center
-        let sep_rad = math::angular_separation_radians(ra_center_rad, dec_center_rad, star.ra_rad, star.dec_rad);
-        // Filter by magnitude and angular separation
-        if star.magnitude < magnitude_limit && sep_rad <= radii_rad {
-            // Add star to the list
-            star_list.push(star);
+        // Filter by magnitude and by the bounding box of the search cone
+        if star.magnitude < magnitude_limit && cone_box.contains(star.ra_rad, star.dec_rad) {
+            // Calculate angular separation between star and center
+            let sep_rad = math::angular_separation_radians(ra_center_rad, dec_center_rad, star.ra_rad, star.dec_rad);
+            // Filter by angular separation
+            if sep_rad <= radii_rad {
+                // Add star to the list
+                star_list.push(star);
+            }
         }
     }
-    // Sort by magnitude
-    star_list.sort_by(|a, b| a.magnitude.partial_cmp(&b.magnitude).unwrap());
-    Ok(star_list)
+    // Sort by magnitude, keeping the brightest stars
+    Ok(star_list.into_sorted())
 }
@@ -1,13 +1,129 @@
+use std::{fmt, str::FromStr};
 use log::{debug};
 
+use rastap_core::angle::Radians;
+
+use crate::diagnostics;
 use crate::polygon;
 
-/// Star position in image
-#[derive(Clone)]
-pub struct ImageStar {
-    pub pixel_x: f64,
-    pub pixel_y: f64,
-    pub magnitude: f64
+pub use crate::polygon::ImageStar;
+
+/// Pixel coordinate convention of a detection list
+#[derive(Clone, Copy, Debug)]
+pub struct PixelConvention {
+    pub origin: u32,              // Coordinate of the first pixel center, 0 or 1
+    pub flip_height: Option<f64>, // Image height, when the Y axis points down
+}
+
+impl PixelConvention {
+    /// Converts the detections to the FITS convention: 1-based, origin at the bottom-left
+    pub fn to_fits(self, star_list: &mut [ImageStar]) {
+        let shift = 1.0 - self.origin as f64;
+        for star in star_list.iter_mut() {
+            star.pixel_x += shift;
+            star.pixel_y += shift;
+            if let Some(height) = self.flip_height {
+                star.pixel_y = height + 1.0 - star.pixel_y;
+            }
+        }
+    }
+}
+
+impl fmt::Display for PixelConvention {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FITS (1-based, origin bottom-left), converted from {}-based", self.origin)?;
+        match self.flip_height {
+            Some(height) => write!(f, ", origin top-left, height {}", height),
+            None => write!(f, ", origin bottom-left"),
+        }
+    }
+}
+
+/// Reference pixel (CRPIX) of the plate solution
+#[derive(Clone, Copy, Debug)]
+pub enum CrpixChoice {
+    Fit,             // Solved for with the tangent point
+    Center,          // Image center, from the image size
+    Pixel(f64, f64), // FITS pixel, such as the optical axis
+}
+
+impl FromStr for CrpixChoice {
+    type Err = String;
+
+    fn from_str(choice: &str) -> Result<CrpixChoice, String> {
+        let error = || format!("Invalid reference pixel '{}', expected fit, center or X,Y", choice);
+        match choice {
+            "fit" => Ok(CrpixChoice::Fit),
+            "center" => Ok(CrpixChoice::Center),
+            _ => {
+                let mut parts = choice.splitn(2, ',');
+                let x = parts.next().ok_or_else(error)?.trim().parse::<f64>().map_err(|_| error())?;
+                let y = parts.next().ok_or_else(error)?.trim().parse::<f64>().map_err(|_| error())?;
+                if !(x.is_finite() && y.is_finite()) {
+                    return Err(error());
+                }
+                Ok(CrpixChoice::Pixel(x, y))
+            }
+        }
+    }
+}
+
+/// Merges the detections closer than `radius` pixels into one, at their flux-weighted position with
+/// their total flux. The list is left sorted by magnitude, as a merged detection may be brighter than
+/// the ones before it. Returns the number of detections removed.
+pub fn merge_close_detections(star_list: &mut Vec<ImageStar>, radius: f64) -> usize {
+    let count = star_list.len();
+    let mut merged: Vec<ImageStar> = Vec::with_capacity(count);
+    let mut used = vec![false; count];
+    for i in 0..count {
+        if used[i] {
+            continue;
+        }
+        let (mut flux, mut x, mut y) = (0.0, 0.0, 0.0);
+        for j in i..count {
+            let (a, b) = (&star_list[i], &star_list[j]);
+            if !used[j] && (b.pixel_x - a.pixel_x).hypot(b.pixel_y - a.pixel_y) <= radius {
+                used[j] = true;
+                let star_flux = 10f64.powf(-0.4 * b.magnitude);
+                flux += star_flux;
+                x += star_flux * b.pixel_x;
+                y += star_flux * b.pixel_y;
+                if j != i {
+                    debug!("Merge > x:{} y:{} into x:{} y:{}", b.pixel_x, b.pixel_y, a.pixel_x, a.pixel_y);
+                }
+            }
+        }
+        merged.push(ImageStar { pixel_x: x / flux, pixel_y: y / flux, magnitude: -2.5 * flux.log10() });
+    }
+    merged.sort_by(|a, b| a.magnitude.total_cmp(&b.magnitude));
+    *star_list = merged;
+    count - star_list.len()
+}
+
+/// Star trail, from the second moments of a detection
+pub struct Trail {
+    pub length: f64, // Pixels
+    pub angle: f64,  // Degrees, from the X axis counter-clockwise
+}
+
+impl Trail {
+    /// Trail of a uniform line convolved with the PSF: the line adds length²/12 to the variance along it.
+    /// `a` and `b` are the RMS lengths along the major and minor axes (SExtractor A_IMAGE, B_IMAGE).
+    pub fn from_moments(a: f64, b: f64, theta: f64) -> Trail {
+        Trail { length: (12.0 * (a * a - b * b)).max(0.0).sqrt(), angle: theta }
+    }
+}
+
+/// Common trail of the detections: median length and mean axial angle
+pub fn common_trail(trails: &[Trail]) -> Option<Trail> {
+    let lengths: Vec<f64> = trails.iter().map(|trail| trail.length).collect();
+    let length = diagnostics::median(&lengths)?;
+    // Angles are axial (theta and theta + 180 are the same trail): average the doubled angle
+    let (sin, cos) = trails.iter().fold((0.0, 0.0), |(sin, cos), trail| {
+        let doubled = (2.0 * trail.angle).to_radians();
+        (sin + doubled.sin(), cos + doubled.cos())
+    });
+    Some(Trail { length, angle: sin.atan2(cos).to_degrees() / 2.0 })
 }
 
 /// Image metadata
@@ -19,28 +135,60 @@ pub struct Image {
 }
 
 /// Converts the image::Star structure to polygon::Star
-pub fn image_star_to_polygon(star_list: &Vec<ImageStar>, scale_app: f64) -> Vec<polygon::Star> {
+pub fn image_star_to_polygon(star_list: &[ImageStar], scale_app: f64) -> Vec<polygon::Star> {
     let scale_rad = (scale_app / 60.0 / 60.0).to_radians();
     let mut pol_star_list = Vec::new();
     debug!("Image Star to Polygon > Star list:{} Scale \"pp:{} Scale rpp:{}", star_list.len(), scale_app, scale_rad);
     for (i, star) in star_list.iter().enumerate() {
-        let polygon_star = polygon::Star {
-            id: i as u64,
-            db_id: 0,   // No catalogue reference
-            ra: 0.0,  // Right Ascension unknown
-            dec: 0.0, // Declination unknown
-            ra_rad: star.pixel_x * scale_rad,   // Relative RA
-            dec_rad: star.pixel_y * scale_rad,  // Relative Dec
-            magnitude: star.magnitude
-        };
+        // Offsets on the image plane, the sky position is unknown
+        let polygon_star = polygon::Star::image_plane(i as u64, Radians(star.pixel_x * scale_rad), Radians(star.pixel_y * scale_rad), star.magnitude);
         debug!(" i:{} x:{} y:{} ra_rad:{} dec_rad:{}",
                 i,
                 star.pixel_x,
                 star.pixel_y,
-                polygon_star.ra_rad,
-                polygon_star.dec_rad
+                polygon_star.ra_rad.0,
+                polygon_star.dec_rad.0
             );
         pol_star_list.push(polygon_star);
     }
     pol_star_list
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn star(pixel_x: f64, pixel_y: f64, magnitude: f64) -> ImageStar {
+        ImageStar { pixel_x, pixel_y, magnitude }
+    }
+
+    #[test]
+    fn merges_at_the_flux_weighted_position() {
+        // Fluxes 1 and 0.25
+        let mut star_list = vec![star(10.0, 20.0, 0.0), star(12.0, 20.0, -2.5 * 0.25f64.log10())];
+        assert_eq!(merge_close_detections(&mut star_list, 3.0), 1);
+        assert_eq!(star_list.len(), 1);
+        assert!((star_list[0].pixel_x - 10.4).abs() < 1e-12);
+        assert!((star_list[0].pixel_y - 20.0).abs() < 1e-12);
+        assert!((star_list[0].magnitude - -2.5 * 1.25f64.log10()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn merges_up_to_the_radius() {
+        let mut star_list = vec![star(0.0, 0.0, 5.0), star(3.0, 4.0, 6.0)];
+        assert_eq!(merge_close_detections(&mut star_list, 5.0), 1);
+        let mut star_list = vec![star(0.0, 0.0, 5.0), star(3.0, 4.0, 6.0)];
+        assert_eq!(merge_close_detections(&mut star_list, 4.999), 0);
+        assert_eq!(star_list.len(), 2);
+    }
+
+    #[test]
+    fn keeps_magnitude_order_after_merging() {
+        // The pair at the origin adds up brighter than the single star before it
+        let mut star_list = vec![star(100.0, 0.0, 1.0), star(0.0, 0.0, 1.2), star(1.0, 0.0, 1.2), star(50.0, 0.0, 3.0)];
+        assert_eq!(merge_close_detections(&mut star_list, 2.0), 1);
+        let magnitudes: Vec<f64> = star_list.iter().map(|star| star.magnitude).collect();
+        assert!(magnitudes.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!((star_list[0].pixel_x - 0.5).abs() < 1e-12);
+        assert_eq!(star_list[1].pixel_x, 100.0);
+    }
+}
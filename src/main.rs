@@ -1,25 +1,133 @@
 use std::{
-    io,
+    collections::HashSet,
+    env,
+    ffi::OsString,
+    io::{self, BufRead},
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 use structopt::StructOpt;
 
 use env_logger;
+use log::{debug};
+use rastap_core::{angle::{Degrees, Hours, Radians}, asterism, limits, matcher, output::OutputRegistry, polygon, solution};
 
+mod aladin;
+mod archive;
+mod chart;
+mod compare;
+mod crossid;
+mod diagnostics;
+mod format;
+mod fov;
 mod gaia_db;
+mod healpix;
 mod hyg;
 mod image;
 mod math;
-mod polygon;
+mod output;
+mod pairs;
+mod profile;
+mod region;
+mod scale;
 mod sextractor;
+mod star_catalog;
+mod targets;
+mod time;
+mod votable;
+mod wcs;
+mod xylist;
 
 /// Command line arguments
 #[derive(Debug, StructOpt)]
 #[structopt(about)]
+enum Command {
+    /// Match the image detections with the star catalog
+    Solve(Cli),
+    /// Convert pixel coordinates to sky coordinates (degrees) with a WCS header
+    Xy2sky(ConvertCli),
+    /// Convert sky coordinates (degrees) to pixel coordinates with a WCS header
+    Sky2xy(ConvertCli),
+    /// Show a WCS header and rewrite it in CD matrix form
+    Wcs(WcsCli),
+    /// Group the solved frames (.wcs) of a directory by overlapping sky footprint
+    Overlaps(OverlapsCli),
+    /// Write an Aladin Lite snippet showing the footprint of a solved frame (.wcs)
+    Aladin(AladinCli),
+    /// Render an SVG star chart of a field from the catalog
+    Chart(ChartCli),
+    /// Compare two solutions (.wcs) of the same frame
+    Compare(CompareCli),
+    /// Write a HEALPix map (FITS) of the catalog star density and limiting magnitude
+    Density(DensityCli),
+    /// Report which targets of a list fall on which solved frames (.wcs) of a directory
+    Targets(TargetsCli),
+    /// Plan a framing: footprint corners of a sensor and whether the targets fit
+    Fov(FovCli),
+    /// Write histograms of the polygon code space (edge ratios, sizes, best similarities) of the catalog
+    /// and the image, to find scale and tolerance problems, instead of matching
+    Diagnostics(DiagnosticsCli),
+}
+
+/// Subcommand run when the arguments start with an option, as in `rastap --ra 5.5 --sex-csv stars.csv`
+const DEFAULT_COMMAND: &str = "solve";
+
+/// Command line arguments, with the default subcommand inserted when none is given
+fn command_args() -> Vec<OsString> {
+    let mut args: Vec<OsString> = env::args_os().collect();
+    let leading_option = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some("-h") | Some("--help") | Some("-V") | Some("--version") => false,
+        Some(arg) => arg.starts_with('-'),
+        None => false,
+    };
+    if leading_option {
+        args.insert(1, OsString::from(DEFAULT_COMMAND));
+    }
+    args
+}
+
+/// Output format arguments
+#[derive(Debug, StructOpt)]
+struct FormatCli {
+    /// Unit of the R.A. outputs
+    #[structopt(long = "ra-format", default_value = "deg", possible_values = format::RA_UNIT_NAMES)]
+    ra_format: format::RaUnit,
+
+    /// Unit of the angular offsets outputs
+    #[structopt(long = "offset-unit", default_value = "arcsec", possible_values = format::OFFSET_UNIT_NAMES)]
+    offset_unit: format::OffsetUnit,
+
+    /// Decimals of the R.A. and Dec outputs
+    #[structopt(long = "precision", default_value = "6")]
+    precision: usize,
+
+    /// Decimals of the angular offsets outputs
+    #[structopt(long = "offset-precision", default_value = "3")]
+    offset_precision: usize,
+}
+
+impl FormatCli {
+    /// Gets the output units and precision.
+    pub fn format(&self) -> format::Format {
+        format::Format {
+            ra_unit: self.ra_format,
+            offset_unit: self.offset_unit,
+            precision: self.precision,
+            offset_precision: self.offset_precision,
+        }
+    }
+}
+
+/// Solve arguments
+#[derive(Debug, StructOpt)]
 struct Cli {
-    /// Right Ascension center of search in hours and decimals (hh.xx)
+    /// Right Ascension center of search in hours and decimals (hh.xx), or degrees with --ra-unit deg
     #[structopt(long = "ra")]
-    ra_deg: f64,
+    ra: f64,
+
+    /// Unit of --ra
+    #[structopt(long = "ra-unit", default_value = "hours", possible_values = format::RA_UNIT_NAMES)]
+    ra_unit: format::RaUnit,
 
     /// Declination center of search in degrees and decimals (dd.xx)
     #[structopt(long = "dec")]
@@ -33,19 +141,624 @@ struct Cli {
     #[structopt(long = "male", default_value="10.0")]
     male: f64,
 
-    /// Path to sextractor file.
+    /// Widen the search while the catalog returns fewer stars than this
+    #[structopt(long = "min-catalog-stars", default_value = "20")]
+    min_catalog_stars: usize,
+
+    /// Largest search radii when widening the search (default: twice --radii)
+    #[structopt(long = "max-radii")]
+    max_radii_deg: Option<f64>,
+
+    /// Deepest limiting magnitude when widening the search (default: --male + 2)
+    #[structopt(long = "max-male")]
+    max_male: Option<f64>,
+
+    /// Star catalog: auto picks HYG for fields wider than 20 deg, Mini Gaia DR2 otherwise
+    #[structopt(long = "catalog", default_value = "auto", possible_values = star_catalog::CATALOG_NAMES)]
+    catalog: star_catalog::CatalogChoice,
+
+    /// Path to sextractor file (CSV), or astrometry.net xylist (.axy, .xyls)
     #[structopt(long = "sex-csv", parse(from_os_str))]
     sex_csv: PathBuf,
 
-    /// Image scale in pixels per arcsecond
+    /// Path of the VOTable file to write the matched stars to
+    #[structopt(long = "votable", parse(from_os_str))]
+    votable: Option<PathBuf>,
+
+    /// Path of the DS9 region file to write the detections and matched stars to. The field box is the
+    /// image frame with --image-width and --image-height, or the extent of the detections.
+    #[structopt(long = "ds9", parse(from_os_str))]
+    ds9: Option<PathBuf>,
+
+    /// Path of the CSV file to write the matched star pairs (pixel and catalog positions) to
+    #[structopt(long = "pairs", parse(from_os_str))]
+    pairs: Option<PathBuf>,
+
+    /// Path of the CSV file to write polygon code space histograms to, set by the diagnostics command
+    #[structopt(skip)]
+    diagnostics: Option<PathBuf>,
+
+    /// Image scale in arcseconds per pixel. Estimated from the nearest-neighbour separations if not set
     #[structopt(short,long)]
-    scale: f64,
+    scale: Option<f64>,
+
+    /// Star pattern matching algorithm
+    #[structopt(long = "matcher", default_value = "quads", possible_values = matcher::MATCHER_NAMES)]
+    matcher: String,
+
+    /// Grid cells per side used to spread polygons over the field
+    #[structopt(long = "polygon-grid", default_value = "8")]
+    polygon_grid: usize,
+
+    /// Maximum polygons per grid cell, brightest stars first
+    #[structopt(long = "polygons-per-cell")]
+    polygons_per_cell: Option<usize>,
+
+    /// Maximum polygons in total, brightest stars first
+    #[structopt(long = "max-polygons")]
+    max_polygons: Option<usize>,
+
+    /// Maximum catalog stars kept, brightest first
+    #[structopt(long = "max-catalog-stars", default_value = "500")]
+    max_catalog_stars: usize,
+
+    /// Maximum detections kept, brightest first
+    #[structopt(long = "max-image-stars", default_value = "500")]
+    max_image_stars: usize,
+
+    /// Memory budget in MB for the star lists and polygons; fewer stars are kept to stay within it
+    #[structopt(long = "max-memory")]
+    max_memory_mb: Option<usize>,
+
+    /// Reject polygons whose shortest/longest edge ratio is below this value
+    #[structopt(long = "min-edge-ratio", default_value = "0.02")]
+    min_edge_ratio: f64,
+
+    /// Reject polygons with three nearly collinear stars (triangle area over longest side squared)
+    #[structopt(long = "min-area-ratio", default_value = "0.01")]
+    min_area_ratio: f64,
+
+    /// Fit SIP distortion polynomials of this order (2 or more) with the plate solution
+    #[structopt(long = "sip-order")]
+    sip_order: Option<usize>,
+
+    /// Reference pixel (CRPIX) of the plate solution: fit, center (needs the image size) or X,Y in FITS pixels
+    #[structopt(long = "crpix", default_value = "fit")]
+    crpix: image::CrpixChoice,
+
+    /// Reject star matches whose magnitude differs from the fitted zero point by more than this
+    #[structopt(long = "mag-tolerance")]
+    mag_tolerance: Option<f64>,
+
+    /// Centroid error in pixels, setting the polygon similarity tolerance
+    /// (default: from the FWHM_IMAGE and MAGERR_AUTO columns, or a fixed tolerance without them)
+    #[structopt(long = "centroid-error")]
+    centroid_error: Option<f64>,
+
+    /// Observation start time, UTC (yyyy-mm-ddThh:mm:ss)
+    #[structopt(long = "date-obs", parse(try_from_str = time::julian_day_from_iso))]
+    date_obs_jd: Option<f64>,
+
+    /// Exposure time in seconds (EXPTIME). Corrections use the mid-exposure time
+    #[structopt(long = "exptime", default_value = "0.0")]
+    exptime: f64,
+
+    /// Warn when catalog and observation epochs differ by more years than this
+    #[structopt(long = "max-epoch-gap", default_value = "10.0")]
+    max_epoch_gap: f64,
+
+    /// Site latitude in degrees and decimals, north positive (dd.xx)
+    #[structopt(long = "latitude", allow_hyphen_values = true)]
+    latitude_deg: Option<f64>,
+
+    /// Site longitude in degrees and decimals, east positive (dd.xx)
+    #[structopt(long = "longitude", allow_hyphen_values = true)]
+    longitude_deg: Option<f64>,
+
+    /// Atmospheric pressure in hPa
+    #[structopt(long = "pressure", default_value = "1010.0")]
+    pressure_hpa: f64,
+
+    /// Air temperature in degrees Celsius
+    #[structopt(long = "temperature", default_value = "10.0", allow_hyphen_values = true)]
+    temperature_c: f64,
+
+    /// Apply atmospheric refraction to catalog positions (needs --date-obs, --latitude and --longitude)
+    #[structopt(long = "refraction")]
+    refraction: bool,
+
+    /// Fit the scale of the atmospheric refraction with the plate solution, for wide fields (needs --refraction)
+    #[structopt(long = "fit-refraction", requires = "refraction")]
+    fit_refraction: bool,
+
+    /// Apply annual aberration to catalog positions (needs --date-obs)
+    #[structopt(long = "aberration")]
+    aberration: bool,
+
+    /// Apply gravitational light deflection by the Sun to catalog positions (needs --date-obs)
+    #[structopt(long = "light-deflection")]
+    light_deflection: bool,
+
+    /// Apply the aberration of a moving platform (aircraft, balloon, spacecraft) to catalog positions:
+    /// velocity relative to the Earth center in km/s, equatorial axes, VX,VY,VZ
+    #[structopt(long = "velocity", allow_hyphen_values = true)]
+    velocity: Option<math::Velocity>,
+
+    /// Coordinate of the first pixel center in the detection list (SExtractor and FITS: 1)
+    #[structopt(long = "pixel-origin", default_value = "1", possible_values = &["0", "1"])]
+    pixel_origin: u32,
+
+    /// The Y axis of the detection list points down, origin at the top-left (needs --image-height)
+    #[structopt(long = "flip-y", requires = "image-height")]
+    flip_y: bool,
+
+    /// Image height in pixels
+    #[structopt(long = "image-height")]
+    image_height: Option<f64>,
+
+    /// Image width in pixels
+    #[structopt(long = "image-width")]
+    image_width: Option<f64>,
+
+    /// Trailed frame: report the trail length and angle (needs A_IMAGE, B_IMAGE and THETA_IMAGE columns)
+    #[structopt(long = "trailed")]
+    trailed: bool,
+
+    /// Exclude detections that are not star-like (galaxies, artifacts) from matching, using the optional
+    /// CLASS_STAR, FWHM_IMAGE, FLAGS, ELONGATION (or A_IMAGE and B_IMAGE) and FLUX_MAX/FLUX_AUTO columns
+    #[structopt(long = "stellar-only")]
+    stellar_only: bool,
+
+    /// Minimum CLASS_STAR of a star-like detection
+    #[structopt(long = "min-class-star", default_value = "0.5")]
+    min_class_star: f64,
+
+    /// Maximum elongation (A/B) of a star-like detection. Not applied to trailed frames
+    #[structopt(long = "max-elongation", default_value = "2.0")]
+    max_elongation: f64,
+
+    /// Maximum relative deviation of the FWHM and peak/flux ratio of a star-like detection from the frame medians
+    #[structopt(long = "shape-tolerance", default_value = "0.5")]
+    shape_tolerance: f64,
+
+    /// Minimum detections to attempt a solve
+    #[structopt(long = "min-detections", default_value = "11")]
+    min_detections: usize,
+
+    /// Separation tolerance (relative) of the asterism match tried below --min-detections (needs --scale)
+    #[structopt(long = "asterism-tolerance", default_value = "0.02")]
+    asterism_tolerance: f64,
+
+    /// Merge detections closer than this many pixels (flux-weighted)
+    #[structopt(long = "merge-radius")]
+    merge_radius: Option<f64>,
+
+    /// Shell command run after matching. Placeholders: {sex_csv}, {matches}, {votable}, {ds9}, {pairs},
+    /// {solved}, {ra}, {dec} (degrees), {scale} (arcsec/pix), {rotation} (degrees)
+    #[structopt(long = "exec")]
+    exec: Option<String>,
+
+    #[structopt(flatten)]
+    format: FormatCli,
+
+    /// Equipment profile supplying the scale and pixel convention not set on the command line
+    #[structopt(long = "profile")]
+    profile: Option<String>,
+
+    /// Equipment profile file
+    #[structopt(long = "config", default_value = profile::PROFILE_PATH, parse(from_os_str))]
+    config: PathBuf,
+
+    /// Stop the polygon search after this many seconds and report the partial results
+    #[structopt(long = "max-time")]
+    max_time: Option<f64>,
+}
+
+/// Polygon code space diagnostics arguments
+#[derive(Debug, StructOpt)]
+struct DiagnosticsCli {
+    /// Path of the CSV file to write the polygon code space histograms to
+    #[structopt(long = "output", parse(from_os_str))]
+    output: PathBuf,
+
+    #[structopt(flatten)]
+    solve: Cli,
+}
+
+impl DiagnosticsCli {
+    /// Gets the solve arguments, writing the code space to the output instead of matching.
+    pub fn solve(self) -> Cli {
+        Cli { diagnostics: Some(self.output), ..self.solve }
+    }
+}
+
+/// Coordinate conversion arguments
+#[derive(Debug, StructOpt)]
+#[structopt(setting = structopt::clap::AppSettings::AllowNegativeNumbers)]
+struct ConvertCli {
+    /// WCS header file (.wcs)
+    #[structopt(long = "wcs", parse(from_os_str))]
+    wcs: PathBuf,
+
+    /// Coordinate pairs to convert (pixels, 1-based, or R.A./Dec in degrees). Read from the standard input if not given
+    coordinates: Vec<f64>,
+
+    #[structopt(flatten)]
+    format: FormatCli,
+}
+
+/// WCS header arguments
+#[derive(Debug, StructOpt)]
+struct WcsCli {
+    /// WCS header file (.wcs)
+    #[structopt(long = "wcs", parse(from_os_str))]
+    wcs: PathBuf,
+
+    /// Path of the WCS header file to write
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+/// Archive overlap arguments
+#[derive(Debug, StructOpt)]
+struct OverlapsCli {
+    /// Directory of WCS header files (.wcs)
+    #[structopt(parse(from_os_str))]
+    dir: PathBuf,
+}
+
+/// Aladin Lite export arguments
+#[derive(Debug, StructOpt)]
+struct AladinCli {
+    /// WCS header file (.wcs)
+    #[structopt(long = "wcs", parse(from_os_str))]
+    wcs: PathBuf,
+
+    /// HiPS survey shown under the footprint
+    #[structopt(long = "survey", default_value = "P/DSS2/color")]
+    survey: String,
+
+    /// Path of the HTML snippet to write. Printed if not set
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+/// Star chart arguments
+#[derive(Debug, StructOpt)]
+struct ChartCli {
+    /// Right Ascension of the chart center in hours and decimals (hh.xx), or degrees with --ra-unit deg
+    #[structopt(long = "ra")]
+    ra: f64,
+
+    /// Unit of --ra
+    #[structopt(long = "ra-unit", default_value = "hours", possible_values = format::RA_UNIT_NAMES)]
+    ra_unit: format::RaUnit,
+
+    /// Declination of the chart center in degrees and decimals (dd.xx)
+    #[structopt(long = "dec", allow_hyphen_values = true)]
+    dec_deg: f64,
+
+    /// Field of view (chart side) in degrees
+    #[structopt(long = "fov")]
+    fov_deg: f64,
+
+    /// Limiting magnitude
+    #[structopt(long = "male", default_value = "10.0")]
+    male: f64,
+
+    /// Chart side in pixels
+    #[structopt(long = "size", default_value = "800")]
+    size: u32,
+
+    /// Number of brightest stars labelled with their catalog id
+    #[structopt(long = "labels", default_value = "20")]
+    labels: usize,
+
+    /// Path of the SVG chart to write
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+}
+
+/// Framing planning arguments
+#[derive(Debug, StructOpt)]
+struct FovCli {
+    /// Right Ascension of the frame center in hours and decimals (hh.xx), or degrees with --ra-unit deg
+    #[structopt(long = "ra")]
+    ra: f64,
+
+    /// Unit of --ra
+    #[structopt(long = "ra-unit", default_value = "hours", possible_values = format::RA_UNIT_NAMES)]
+    ra_unit: format::RaUnit,
+
+    /// Declination of the frame center in degrees and decimals (dd.xx)
+    #[structopt(long = "dec", allow_hyphen_values = true)]
+    dec_deg: f64,
+
+    /// Rotation of the sensor Y axis from the North, through the East, in degrees
+    #[structopt(long = "rotation", default_value = "0", allow_hyphen_values = true)]
+    rotation_deg: f64,
+
+    /// Sensor size in pixels, WIDTHxHEIGHT
+    #[structopt(long = "sensor")]
+    sensor: fov::Sensor,
+
+    /// Focal length in millimetres
+    #[structopt(long = "focal")]
+    focal_length_mm: f64,
+
+    /// Pixel size in micrometres (binning included)
+    #[structopt(long = "pixel")]
+    pixel_size_um: f64,
+
+    /// Target list CSV file (name, ra and dec columns, in degrees) to check against the frame
+    #[structopt(long = "targets", parse(from_os_str))]
+    targets: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    format: FormatCli,
+}
+
+impl FovCli {
+    /// Gets the frame center R.A. in hours, checking its range.
+    pub fn ra_hours(&self) -> io::Result<f64> {
+        self.ra_unit.to_hours(self.ra).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    }
+
+    /// Gets the frame center Declination.
+    pub fn dec_deg(&self) -> f64 {
+        self.dec_deg
+    }
+
+    /// Gets the sensor rotation.
+    pub fn rotation_deg(&self) -> f64 {
+        self.rotation_deg
+    }
+
+    /// Gets the sensor size.
+    pub fn sensor(&self) -> fov::Sensor {
+        self.sensor
+    }
+
+    /// Gets the image scale in arcseconds per pixel.
+    pub fn scale(&self) -> f64 {
+        fov::image_scale(self.pixel_size_um, self.focal_length_mm)
+    }
+
+    /// Gets the path of the target list, if given.
+    pub fn targets(&self) -> Option<&Path> {
+        self.targets.as_deref()
+    }
+
+    /// Gets the output units and precision.
+    pub fn format(&self) -> format::Format {
+        self.format.format()
+    }
+}
+
+/// Target report arguments
+#[derive(Debug, StructOpt)]
+struct TargetsCli {
+    /// Target list CSV file: name, ra and dec columns
+    #[structopt(parse(from_os_str))]
+    targets: PathBuf,
+
+    /// Directory of WCS header files (.wcs)
+    #[structopt(parse(from_os_str))]
+    dir: PathBuf,
+
+    /// Unit of the target list R.A.
+    #[structopt(long = "ra-unit", default_value = "deg", possible_values = format::RA_UNIT_NAMES)]
+    ra_unit: format::RaUnit,
+
+    /// Minimum distance to the frame edge in pixels. Closer targets are flagged
+    #[structopt(long = "margin", default_value = "0")]
+    margin: f64,
+}
+
+impl TargetsCli {
+    /// Gets the path of the target list.
+    pub fn targets(&self) -> &Path {
+        self.targets.as_path()
+    }
+
+    /// Gets the directory of WCS header files.
+    pub fn dir(&self) -> &Path {
+        self.dir.as_path()
+    }
+
+    /// Gets the unit of the target list R.A.
+    pub fn ra_unit(&self) -> format::RaUnit {
+        self.ra_unit
+    }
+
+    /// Gets the minimum distance to the frame edge in pixels.
+    pub fn margin(&self) -> f64 {
+        self.margin
+    }
+}
+
+/// Catalog density map arguments
+#[derive(Debug, StructOpt)]
+struct DensityCli {
+    /// HEALPix resolution parameter, a power of two (12 nside² pixels)
+    #[structopt(long = "nside", default_value = "16")]
+    nside: usize,
+
+    /// Limiting magnitude. Every catalog star if not set
+    #[structopt(long = "male")]
+    male: Option<f64>,
+
+    /// Path of the HEALPix FITS map to write
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+}
+
+impl DensityCli {
+    /// Gets the HEALPix resolution parameter.
+    pub fn nside(&self) -> usize {
+        self.nside
+    }
+
+    /// Gets the limiting magnitude.
+    pub fn male(&self) -> f64 {
+        self.male.unwrap_or(f64::INFINITY)
+    }
+
+    /// Gets the path of the map to write.
+    pub fn output(&self) -> &Path {
+        self.output.as_path()
+    }
+}
+
+/// Solution comparison arguments
+#[derive(Debug, StructOpt)]
+struct CompareCli {
+    /// Reference solution, WCS header file (.wcs)
+    #[structopt(parse(from_os_str))]
+    reference: PathBuf,
+
+    /// Solution compared with the reference, WCS header file (.wcs)
+    #[structopt(parse(from_os_str))]
+    solution: PathBuf,
+
+    /// Sextractor CSV file, to compare the sky positions of its detections (FITS pixels, 1-based)
+    #[structopt(long = "sex-csv", parse(from_os_str))]
+    sex_csv: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    format: FormatCli,
+}
+
+impl CompareCli {
+    /// Gets the path of the reference solution.
+    pub fn reference(&self) -> &Path {
+        self.reference.as_path()
+    }
+
+    /// Gets the path of the compared solution.
+    pub fn solution(&self) -> &Path {
+        self.solution.as_path()
+    }
+
+    /// Gets the path of the sextractor file, if given.
+    pub fn sex_csv(&self) -> Option<&Path> {
+        self.sex_csv.as_deref()
+    }
+
+    /// Gets the output units and precision.
+    pub fn format(&self) -> format::Format {
+        self.format.format()
+    }
+}
+
+impl ChartCli {
+    /// Gets the chart center Right Ascension in hours, checking its range.
+    pub fn ra_hours(&self) -> io::Result<f64> {
+        self.ra_unit.to_hours(self.ra).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    }
+
+    /// Gets the chart center Declination in degrees.
+    pub fn dec_deg(&self) -> f64 {
+        self.dec_deg
+    }
+
+    /// Gets the field of view in degrees.
+    pub fn fov_deg(&self) -> f64 {
+        self.fov_deg
+    }
+
+    /// Gets the limiting magnitude.
+    pub fn male(&self) -> f64 {
+        self.male
+    }
+
+    /// Gets the chart side in pixels.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Gets the number of labelled stars.
+    pub fn labels(&self) -> usize {
+        self.labels
+    }
+
+    /// Gets the path of the SVG chart.
+    pub fn output(&self) -> &Path {
+        self.output.as_path()
+    }
+}
+
+impl AladinCli {
+    /// Gets the path of the WCS header file.
+    pub fn wcs(&self) -> &Path {
+        self.wcs.as_path()
+    }
+
+    /// Gets the HiPS survey identifier.
+    pub fn survey(&self) -> &str {
+        &self.survey
+    }
+
+    /// Gets the path of the snippet to write, if requested.
+    pub fn output(&self) -> Option<&Path> {
+        self.output.as_deref()
+    }
+}
+
+impl OverlapsCli {
+    /// Gets the directory of WCS header files.
+    pub fn dir(&self) -> &Path {
+        self.dir.as_path()
+    }
+}
+
+impl WcsCli {
+    /// Gets the path of the WCS header file.
+    pub fn wcs(&self) -> &Path {
+        self.wcs.as_path()
+    }
+
+    /// Gets the path of the WCS header file to write, if requested.
+    pub fn output(&self) -> Option<&Path> {
+        self.output.as_deref()
+    }
+}
+
+impl ConvertCli {
+    /// Gets the output units and precision.
+    pub fn format(&self) -> format::Format {
+        self.format.format()
+    }
+
+    /// Gets the path of the WCS header file.
+    pub fn wcs(&self) -> &Path {
+        self.wcs.as_path()
+    }
+
+    /// Gets the coordinate pairs, from the command line or the standard input (two numbers per line).
+    pub fn coordinates(&self) -> io::Result<Vec<(f64, f64)>> {
+        let values = if self.coordinates.is_empty() {
+            let mut values = Vec::new();
+            for line in io::stdin().lock().lines() {
+                for field in line?.split(|c: char| c == ',' || c.is_whitespace()).filter(|field| !field.is_empty()) {
+                    values.push(field.parse::<f64>().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid coordinate '{}'", field)))?);
+                }
+            }
+            values
+        } else {
+            self.coordinates.clone()
+        };
+        if values.len() % 2 != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Coordinates must be given in pairs"));
+        }
+        Ok(values.chunks(2).map(|pair| (pair[0], pair[1])).collect())
+    }
 }
 
 impl Cli {
-    /// Gets the search center Right Ascension (R.A.)
-    pub fn ra_deg(&self) -> f64 {
-        self.ra_deg
+    /// Gets the search center Right Ascension (R.A.) in hours, checking its range.
+    pub fn ra_hours(&self) -> io::Result<f64> {
+        self.ra_unit.to_hours(self.ra).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
     }
 
     /// Gets the search center Declination (Dec)
@@ -63,21 +776,350 @@ impl Cli {
         self.male
     }
 
+    /// Gets the star catalog choice.
+    pub fn catalog(&self) -> star_catalog::CatalogChoice {
+        self.catalog
+    }
+
+    /// Gets the star catalog in use: the chosen one, or the one for the search field.
+    pub fn selected_catalog(&self) -> star_catalog::Catalog {
+        star_catalog::select(self.catalog, 2.0 * self.radii_deg)
+    }
+
+    /// Gets the catalog stars below which the search is widened, never above the catalog stars kept.
+    pub fn min_catalog_stars(&self) -> usize {
+        self.min_catalog_stars.min(self.solver_limits().catalog_stars().unwrap_or(usize::MAX))
+    }
+
+    /// Gets the largest search radii, never below the search radii.
+    pub fn max_radii_deg(&self) -> f64 {
+        self.max_radii_deg.unwrap_or(2.0 * self.radii_deg).max(self.radii_deg)
+    }
+
+    /// Checks the search radii is positive and its maximum is not below it, so widening the search ends.
+    pub fn check_radii(&self) -> io::Result<()> {
+        if !(self.radii_deg.is_finite() && self.radii_deg > 0.0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--radii {} is not a positive number of degrees", self.radii_deg)));
+        }
+        match self.max_radii_deg {
+            Some(max_radii) if !(max_radii.is_finite() && max_radii >= self.radii_deg) =>
+                Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--max-radii {} is below --radii {}", max_radii, self.radii_deg))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Gets the deepest limiting magnitude, never brighter than the limiting magnitude.
+    pub fn max_male(&self) -> f64 {
+        self.max_male.unwrap_or(self.male + 2.0).max(self.male)
+    }
+
     /// Gets the path to the input sextractor file.
     pub fn sex_csv(&self) -> &Path {
         self.sex_csv.as_path()
     }
 
-    /// Gets the image scale in pixels per arcsecond.
-    pub fn scale(&self) -> f64 {
+    /// Gets the path of the matched stars VOTable, if requested.
+    pub fn votable(&self) -> Option<&Path> {
+        self.votable.as_deref()
+    }
+
+    /// Gets the path of the DS9 region file, if requested.
+    pub fn ds9(&self) -> Option<&Path> {
+        self.ds9.as_deref()
+    }
+
+    /// Gets the path of the star pairs CSV file, if requested.
+    pub fn pairs(&self) -> Option<&Path> {
+        self.pairs.as_deref()
+    }
+
+    /// Gets the path of the code space diagnostics file, if requested.
+    pub fn diagnostics(&self) -> Option<&Path> {
+        self.diagnostics.as_deref()
+    }
+
+    /// Gets the image scale in arcseconds per pixel, if set.
+    pub fn scale(&self) -> Option<f64> {
         self.scale
     }
+
+    /// Gets the star pattern matcher.
+    pub fn matcher(&self, tolerance: polygon::Tolerance) -> Box<dyn matcher::Matcher> {
+        matcher::from_name(&self.matcher, self.polygon_limits(), self.polygon_shape(), tolerance).unwrap()
+    }
+
+    /// Gets the limits on the number of polygons, tightened by the resource limits.
+    pub fn polygon_limits(&self) -> polygon::PolygonLimits {
+        self.solver_limits().polygon_limits(polygon::PolygonLimits {
+            grid_cells: self.polygon_grid,
+            max_per_cell: self.polygons_per_cell,
+            max_polygons: None,
+        })
+    }
+
+    /// Gets the per-solve resource limits.
+    pub fn solver_limits(&self) -> limits::SolverLimits {
+        limits::SolverLimits {
+            max_memory_mb: self.max_memory_mb,
+            max_catalog_stars: Some(self.max_catalog_stars),
+            max_image_stars: Some(self.max_image_stars),
+            max_quads: self.max_polygons,
+        }
+    }
+
+    /// Gets the centroid error in pixels, if given.
+    pub fn centroid_error(&self) -> Option<f64> {
+        self.centroid_error
+    }
+
+    /// Gets the magnitude tolerance of star matches, if enabled.
+    pub fn mag_tolerance(&self) -> Option<f64> {
+        self.mag_tolerance
+    }
+
+    /// Gets the terms of the plate solution fit, and its reference pixel.
+    pub fn fit_model(&self) -> io::Result<solution::FitModel> {
+        let reference_pixel = match self.crpix {
+            image::CrpixChoice::Fit => solution::ReferencePixel::Fit,
+            image::CrpixChoice::Pixel(x, y) => solution::ReferencePixel::Fixed([x, y]),
+            image::CrpixChoice::Center => match self.image_size() {
+                Some((width, height)) => solution::ReferencePixel::Fixed([(width + 1.0) / 2.0, (height + 1.0) / 2.0]),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "--crpix center needs --image-width and --image-height")),
+            },
+        };
+        Ok(solution::FitModel { sip_order: self.sip_order, refraction: None, reference_pixel })
+    }
+
+    /// Gets the thresholds rejecting degenerate polygons.
+    pub fn polygon_shape(&self) -> polygon::PolygonShape {
+        polygon::PolygonShape {
+            min_edge_ratio: self.min_edge_ratio,
+            min_area_ratio: self.min_area_ratio,
+        }
+    }
+
+    /// Gets the observation start time as Julian Day, if given.
+    pub fn date_obs_jd(&self) -> Option<f64> {
+        self.date_obs_jd
+    }
+
+    /// Gets the mid-exposure time as Julian Day, if the observation time was given.
+    pub fn mid_exposure_jd(&self) -> Option<f64> {
+        self.date_obs_jd().map(|jd| jd + self.exptime / 2.0 / 86400.0)
+    }
+
+    /// Gets the maximum catalog to observation epoch difference, in years.
+    pub fn max_epoch_gap(&self) -> f64 {
+        self.max_epoch_gap
+    }
+
+    /// Gets the observing site, if its coordinates were given.
+    pub fn site(&self) -> Option<math::Site> {
+        match (self.latitude_deg, self.longitude_deg) {
+            (Some(latitude_deg), Some(longitude_deg)) => Some(math::Site {
+                latitude_rad: Radians::from(Degrees(latitude_deg)),
+                longitude_rad: Radians::from(Degrees(longitude_deg)),
+                pressure_hpa: self.pressure_hpa,
+                temperature_c: self.temperature_c,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Gets whether atmospheric refraction must be applied to the catalog.
+    pub fn refraction(&self) -> bool {
+        self.refraction
+    }
+
+    /// Gets whether the refraction scale is fitted with the plate solution.
+    pub fn fit_refraction(&self) -> bool {
+        self.fit_refraction
+    }
+
+    /// Gets whether annual aberration must be applied to the catalog.
+    pub fn aberration(&self) -> bool {
+        self.aberration
+    }
+
+    /// Gets whether light deflection must be applied to the catalog.
+    pub fn light_deflection(&self) -> bool {
+        self.light_deflection
+    }
+
+    /// Gets the platform velocity, if given.
+    pub fn velocity(&self) -> Option<math::Velocity> {
+        self.velocity
+    }
+
+    /// Gets the coordinate of the first pixel center.
+    pub fn pixel_origin(&self) -> u32 {
+        self.pixel_origin
+    }
+
+    /// Gets whether the Y axis of the detection list points down.
+    pub fn flip_y(&self) -> bool {
+        self.flip_y
+    }
+
+    /// Gets the image height in pixels, if set.
+    pub fn image_height(&self) -> Option<f64> {
+        self.image_height
+    }
+
+    /// Gets the image width and height in pixels, if both are set.
+    pub fn image_size(&self) -> Option<(f64, f64)> {
+        self.image_width.zip(self.image_height)
+    }
+
+    /// Gets the pixel coordinate convention of the detection list.
+    pub fn pixel_convention(&self) -> image::PixelConvention {
+        image::PixelConvention {
+            origin: self.pixel_origin(),
+            flip_height: if self.flip_y() { self.image_height() } else { None },
+        }
+    }
+
+    /// Gets whether the frame is trailed.
+    pub fn trailed(&self) -> bool {
+        self.trailed
+    }
+
+    /// Gets the minimum detections to attempt a solve.
+    pub fn min_detections(&self) -> usize {
+        self.min_detections
+    }
+
+    /// Gets the relative separation tolerance of the asterism match.
+    pub fn asterism_tolerance(&self) -> f64 {
+        self.asterism_tolerance
+    }
+
+    /// Gets the thresholds of star-like detections, if non-stellar ones must be excluded.
+    pub fn stellar_filter(&self) -> Option<sextractor::StellarFilter> {
+        if !self.stellar_only {
+            return None;
+        }
+        Some(sextractor::StellarFilter {
+            min_class_star: self.min_class_star,
+            // Trailed stars are elongated
+            max_elongation: if self.trailed { f64::INFINITY } else { self.max_elongation },
+            shape_tolerance: self.shape_tolerance,
+            reject_flags: sextractor::REJECT_FLAGS,
+        })
+    }
+
+    /// Gets the detection merge radius in pixels, if set.
+    pub fn merge_radius(&self) -> Option<f64> {
+        self.merge_radius
+    }
+
+    /// Gets the command template run after matching, if set.
+    pub fn exec(&self) -> Option<&str> {
+        self.exec.as_deref()
+    }
+
+    /// Gets the writers of the star matches, in order: files first, then the command
+    pub fn output_writers(&self) -> OutputRegistry {
+        let mut writers = OutputRegistry::new();
+        if let Some(path) = self.votable() {
+            writers.register(Box::new(output::VotableWriter { path: path.to_path_buf(), pixel_convention: self.pixel_convention(), format: self.format() }));
+        }
+        if let Some(path) = self.ds9() {
+            writers.register(Box::new(output::Ds9Writer { path: path.to_path_buf(), pixel_convention: self.pixel_convention(), image_size: self.image_size() }));
+        }
+        if let Some(path) = self.pairs() {
+            writers.register(Box::new(output::PairsWriter { path: path.to_path_buf(), catalog: self.selected_catalog(), format: self.format() }));
+        }
+        if let Some(template) = self.exec() {
+            writers.register(Box::new(output::ExecWriter {
+                template: template.to_owned(),
+                sex_csv: self.sex_csv().to_path_buf(),
+                votable: self.votable.clone(),
+                ds9: self.ds9.clone(),
+                pairs: self.pairs.clone(),
+            }));
+        }
+        writers
+    }
+
+    /// Gets the output units and precision.
+    pub fn format(&self) -> format::Format {
+        self.format.format()
+    }
+
+    /// Gets the equipment profile name, if set.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Gets the path of the equipment profile file.
+    pub fn config(&self) -> &Path {
+        self.config.as_path()
+    }
+
+    /// Fills the options not set on the command line from an equipment profile
+    pub fn apply_profile(&mut self, profile: &profile::Profile) -> io::Result<()> {
+        if self.scale.is_none() {
+            self.scale = profile.scale();
+        }
+        if self.image_height.is_none() {
+            self.image_height = profile.image_height;
+        }
+        if self.image_width.is_none() {
+            self.image_width = profile.image_width;
+        }
+        self.flip_y |= profile.flip_y;
+        if self.flip_y && self.image_height.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Profile '{}' flips the Y axis but sets no image_height", profile.name)));
+        }
+        Ok(())
+    }
+
+    /// Gets the maximum solve time, if set, checking it is a positive number of seconds.
+    pub fn max_time(&self) -> io::Result<Option<Duration>> {
+        match self.max_time {
+            Some(seconds) if !(seconds.is_finite() && seconds > 0.0) =>
+                Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--max-time {} is not a positive number of seconds", seconds))),
+            Some(seconds) => Ok(Some(Duration::from_secs_f64(seconds))),
+            None => Ok(None),
+        }
+    }
+}
+
+// Apply a position correction to the catalog star positions used for matching. The catalog positions are kept for the outputs
+fn correct_star_positions<F>(star_list: &mut [polygon::Star], name: &str, format: &format::Format, correction: F)
+where
+    F: Fn(Radians, Radians) -> (Radians, Radians),
+{
+    let mut max_shift = 0.0_f64;
+    let arcsec = |angle: Radians| Degrees::from(angle).0 * 3600.0;
+    // Shifts on the tangent plane (arcseconds), to separate the common part from the differential one
+    let mut shifts = Vec::with_capacity(star_list.len());
+    for star in star_list.iter_mut() {
+        let (ra_rad, dec_rad) = correction(star.ra_rad, star.dec_rad);
+        debug!("{} > Star id:{} d_ra:{}\" d_dec:{}\"",
+                name,
+                star.id,
+                arcsec(ra_rad - star.ra_rad),
+                arcsec(dec_rad - star.dec_rad)
+            );
+        max_shift = max_shift.max(arcsec(math::angular_separation_radians(star.ra_rad, star.dec_rad, ra_rad, dec_rad)));
+        shifts.push((arcsec(ra_rad - star.ra_rad) * dec_rad.cos(), arcsec(dec_rad - star.dec_rad)));
+        star.ra_rad = ra_rad;
+        star.dec_rad = dec_rad;
+    }
+    // Each star is corrected at its own position, so the differential part across the field is kept
+    let count = shifts.len().max(1) as f64;
+    let mean = (shifts.iter().map(|s| s.0).sum::<f64>() / count, shifts.iter().map(|s| s.1).sum::<f64>() / count);
+    let differential = shifts.iter().map(|s| (s.0 - mean.0).hypot(s.1 - mean.1)).fold(0.0, f64::max);
+    println!("{} > max shift:{} differential:{}", name, format.offset(max_shift), format.offset(differential));
 }
 
 // Find polygons
-fn find_polygons_and_fit(star_list: Vec<polygon::Star>, image_star_list: Vec<image::ImageStar>, scale: f64) {
-    let mut star_polygons: Vec<polygon::Polygon> = Vec::new();
-    let mut image_polygons: Vec<polygon::Polygon> = Vec::new();
+fn find_polygons_and_fit(star_list: Vec<polygon::Star>, image_star_list: Vec<image::ImageStar>, scale: f64, centroid_error: Option<f64>, deadline: Option<Instant>, model: &solution::FitModel, cli: &Cli) -> diagnostics::Outcome {
+    // The image polygons are in radians, at the image scale
+    let scale_rad = (scale / 60.0 / 60.0).to_radians();
+    let matcher = cli.matcher(polygon::Tolerance { centroid_error: centroid_error.map(|error| error * scale_rad) });
 
     // Convert list to ImageStar
     let pol_star_list = image::image_star_to_polygon(&image_star_list, scale);
@@ -85,44 +1127,437 @@ fn find_polygons_and_fit(star_list: Vec<polygon::Star>, image_star_list: Vec<ima
     // pol_star_list.truncate(image_star_list.len());
 
     // Find star polygons
-    match polygon::find_polygons(&star_list) {
-        Some(polygons) => {
+    println!("Matcher: {}", matcher.name());
+    let star_polygons = match matcher.find_polygons(&star_list) {
+        Some(polygons) if !polygons.is_empty() => {
             for polygon in &polygons {
-                println!("{}-gon for star {}: {:?} {:?}", polygon::POLYGON_EDGES, polygon.star_index, polygon.length_list, polygon.star_list);
+                debug!("{}-gon for star {}: {:?} {:?}", polygon.edges, polygon.star_index, polygon.length_list(), polygon.star_list());
             }
-            star_polygons = polygons;
+            polygons
         },
-        None => println!("None")
-    }
+        _ => {
+            println!("Diagnosis: {}", diagnostics::Diagnosis::NoPolygons { list: "catalog stars" });
+            return diagnostics::Outcome::NoSolution { star_matches: 0 };
+        }
+    };
     println!("Star list length: {}", star_list.len());
     for star in &pol_star_list {
-        println!("Polygon Star: x:{} y:{} mag:{}", star.ra_rad, star.dec_rad, star.magnitude);
+        debug!("Polygon Star: x:{} y:{} mag:{}", star.ra_rad.0, star.dec_rad.0, star.magnitude);
     }
 
     // Find image polygons
-    match polygon::find_polygons(&pol_star_list) {
-        Some(polygons) => {
-            println!("POL,pixel1_x,pixel1_y,pixel2_x,pixel2_y,pixel3_x,pixel3_y,pixel4_x,pixel4_y");
+    let image_polygons = match matcher.find_polygons(&pol_star_list) {
+        Some(polygons) if !polygons.is_empty() => {
+            let mut header = "POL".to_owned();
+            for i in 1..=matcher.edges() {
+                header.push_str(&format!(",pixel{}_x,pixel{}_y", i, i));
+            }
+            debug!("{}", header);
             'finish: for (n, pol) in polygons.iter().enumerate() {
-                println!("{}-gon for star {}: {:?} {:?}", polygon::POLYGON_EDGES, pol.star_index, pol.length_list, pol.star_list);
+                debug!("{}-gon for star {}: {:?} {:?}", pol.edges, pol.star_index, pol.length_list(), pol.star_list());
                 let mut pol_string = "".to_owned();
-                for (i, star) in pol.star_list.iter().enumerate() {
+                for (i, star) in pol.star_list().iter().enumerate() {
                     if i > 0 {
                         pol_string.push_str(",");
                     }
                     let coordinates = format!("{},{}", image_star_list[*star].pixel_x, image_star_list[*star].pixel_y);
                     pol_string.push_str(&coordinates);
                 }
-                println!("POL,{}", pol_string);
+                debug!("POL,{}", pol_string);
             }
-            image_polygons = polygons;
+            polygons
         },
-        None => println!("Couldn't find polygons in the image")
+        _ => {
+            println!("Diagnosis: {}", diagnostics::Diagnosis::NoPolygons { list: "image detections" });
+            return diagnostics::Outcome::NoSolution { star_matches: 0 };
+        }
+    };
+
+    // Dump the polygon code space instead of matching
+    if let Some(path) = cli.diagnostics() {
+        if let Err(err) = diagnostics::write_code_space(path, &star_list, &star_polygons, &pol_star_list, &image_polygons) {
+            println!("Error writing diagnostics: {}", err);
+        }
+        return diagnostics::Outcome::Diagnosed { star_polygons: star_polygons.len(), image_polygons: image_polygons.len() };
     }
 
     // Compare star database and image polygons
     println!("Searching similarities");
-    polygon::find_fit(&image_polygons, &star_polygons);
+    let (matches, completed) = matcher.find_fit_until(&image_polygons, &star_polygons, &mut || deadline.is_some_and(|deadline| Instant::now() >= deadline));
+    for m in &matches {
+        println!("Match image polygon:{} star polygon:{} similarity:{}", m.image_polygon, m.star_polygon, m.similarity);
+    }
+    println!("Similar polygons: {}", matches.len());
+    if !completed {
+        println!("Warning: --max-time reached, the polygon search was stopped and results are partial");
+    }
+
+    // Pair image and catalog stars
+    let mut star_matches = polygon::find_star_matches(&pol_star_list, &image_polygons, &star_list, &star_polygons, &matches);
+    // Check magnitude consistency of the pairs
+    if let Some(tolerance) = cli.mag_tolerance() {
+        let count = star_matches.len();
+        let (consistent, zero_point) = polygon::filter_star_matches_by_magnitude(&pol_star_list, &star_list, star_matches, tolerance);
+        println!("Magnitude check > zero point:{} rejected:{}", zero_point, count - consistent.len());
+        star_matches = consistent;
+    }
+    for m in &star_matches {
+        let image_star = &image_star_list[m.image_star];
+        let star = &star_list[m.star];
+        println!("Star match x:{} y:{} db_id:{} ra:{} dec:{} votes:{}", image_star.pixel_x, image_star.pixel_y, star.db_id, cli.format().ra(Degrees::from(star.ra()).0), cli.format().dec(star.dec().0), m.votes);
+    }
+    println!("Star matches: {}", star_matches.len());
+    // Plate solution from the star matches
+    let solution = if star_matches.len() >= diagnostics::MIN_STAR_MATCHES {
+        let pixels: Vec<(f64, f64)> = image_star_list.iter().map(|star| (star.pixel_x, star.pixel_y)).collect();
+        solution::fit(&pixels, &star_list, star_matches.clone(), diagnostics::MIN_STAR_MATCHES, model)
+    } else {
+        None
+    };
+    if let Some(solution) = &solution {
+        print_solution(solution, &cli.format());
+    } else {
+        // Explain the failure
+        println!("No solution: {} star matches", star_matches.len());
+        if !completed {
+            // Best effort answer: the most similar polygon pair found before the deadline
+            let best = matches.iter().max_by(|a, b| a.similarity.partial_cmp(&b.similarity).unwrap());
+            if let Some(best) = best {
+                println!("Best candidate (unverified): image polygon:{} star polygon:{} similarity:{}", best.image_polygon, best.star_polygon, best.similarity);
+            }
+        }
+        // Probable scale error: the nearest-neighbour separations do not depend on any polygon match
+        let estimated_scale = scale::estimate_from_neighbours(&star_list, &image_star_list);
+        for diagnosis in diagnostics::diagnose_failure(&star_list, &image_star_list, estimated_scale, scale, cli.radii_deg()) {
+            println!("Diagnosis: {}", diagnosis);
+        }
+    }
+    // The writers get the matches kept by the fit, or every pair when there is no solution
+    let matches = solution.as_ref().map_or(&star_matches, |solution| &solution.matches);
+    write_outputs(&image_star_list, &star_list, matches, solution.as_ref(), cli);
+    match &solution {
+        Some(solution) => diagnostics::Outcome::Matched { star_matches: solution.matches.len() },
+        None => diagnostics::Outcome::NoSolution { star_matches: star_matches.len() },
+    }
+}
+
+// Match the few detections of a sparse field as an asterism template, and fit. None if no solution.
+fn match_asterism_and_fit(star_list: &[polygon::Star], image_star_list: &[image::ImageStar], scale: f64, model: &solution::FitModel, cli: &Cli) -> Option<diagnostics::Outcome> {
+    let scale_rad = (scale / 60.0 / 60.0).to_radians();
+    // Each separation has the error of two centroids. One pixel if unknown.
+    let centroid_error = cli.centroid_error().unwrap_or(1.0);
+    let tolerance = asterism::AsterismTolerance {
+        relative: cli.asterism_tolerance(),
+        absolute: polygon::TOLERANCE_SIGMAS * std::f64::consts::SQRT_2 * centroid_error * scale_rad,
+        magnitude_slack: asterism::MAGNITUDE_SLACK,
+        candidates: asterism::CATALOG_CANDIDATES,
+    };
+    let pol_star_list = image::image_star_to_polygon(image_star_list, scale);
+    let found = match asterism::match_asterism(&pol_star_list, star_list, &tolerance) {
+        Some(found) => found,
+        None => {
+            println!("Asterism: no match among the {} brightest catalog stars", tolerance.candidates.min(star_list.len()));
+            return None;
+        }
+    };
+    println!("Asterism: {} stars, rms separation error {:.4}, {} candidate(s)", found.matches.len(), found.rms_relative, found.candidates);
+    if !found.is_unique() {
+        println!("Asterism: ambiguous, runner-up rms separation error {:.4}", found.runner_up_rms.unwrap_or(0.0));
+        return None;
+    }
+    for m in &found.matches {
+        let image_star = &image_star_list[m.image_star];
+        let star = &star_list[m.star];
+        println!("Star match x:{} y:{} db_id:{} ra:{} dec:{} votes:{}", image_star.pixel_x, image_star.pixel_y, star.db_id, cli.format().ra(Degrees::from(star.ra()).0), cli.format().dec(star.dec().0), m.votes);
+    }
+    let pixels: Vec<(f64, f64)> = image_star_list.iter().map(|star| (star.pixel_x, star.pixel_y)).collect();
+    let solution = solution::fit(&pixels, star_list, found.matches, asterism::MIN_STARS, model)?;
+    print_solution(&solution, &cli.format());
+    write_outputs(image_star_list, star_list, &solution.matches, Some(&solution), cli);
+    Some(diagnostics::Outcome::Matched { star_matches: solution.matches.len() })
+}
+
+// Print a plate solution
+fn print_solution(solution: &solution::Solution, format: &format::Format) {
+    println!("Solution center R.A.:{} Dec:{} at pixel x:{:.2} y:{:.2}", format.ra(solution.center.ra.0), format.dec(solution.center.dec.0), solution.reference_pixel[0], solution.reference_pixel[1]);
+    println!("Solution scale:{:.4}\"/px rotation:{:.3} deg parity:{:?}", solution.scale_arcsec_px, solution.rotation_deg, solution.parity);
+    if let Some(sip) = &solution.sip {
+        println!("Solution distortion: SIP order {}", sip.a.order);
+    }
+    if let Some(refraction_scale) = solution.refraction_scale {
+        println!("Solution refraction: {:.3} times the modelled refraction", refraction_scale);
+    }
+    println!("Solution fit: {} stars, {} rejected, rms:{} max:{}", solution.stats.stars, solution.stats.rejected, format.offset(solution.stats.rms_arcsec), format.offset(solution.stats.max_arcsec));
+}
+
+// Write the star matches with every requested writer
+fn write_outputs(image_star_list: &[image::ImageStar], star_list: &[polygon::Star], matches: &[polygon::StarMatch], solution: Option<&solution::Solution>, cli: &Cli) {
+    for (name, err) in cli.output_writers().write_all(image_star_list, star_list, matches, solution) {
+        println!("Error writing {}: {}", name, err);
+    }
+}
+
+// Convert pixel coordinates to sky coordinates
+fn xy2sky(cli: &ConvertCli) -> io::Result<()> {
+    let wcs = wcs::Wcs::from_file(cli.wcs())?;
+    let format = cli.format();
+    for (x, y) in cli.coordinates()? {
+        let (ra, dec) = wcs.pixel_to_sky(x, y);
+        println!("{} {} -> {} {}", x, y, format.ra(ra), format.dec(dec));
+    }
+    Ok(())
+}
+
+// Convert sky coordinates to pixel coordinates
+fn sky2xy(cli: &ConvertCli) -> io::Result<()> {
+    let wcs = wcs::Wcs::from_file(cli.wcs())?;
+    for (ra, dec) in cli.coordinates()? {
+        match wcs.sky_to_pixel(ra, dec) {
+            Some((x, y)) => println!("{} {} -> {:.3} {:.3}", ra, dec, x, y),
+            None => println!("{} {} -> not projectable", ra, dec),
+        }
+    }
+    Ok(())
+}
+
+// Show a WCS header and optionally rewrite it
+fn show_wcs(cli: &WcsCli) -> io::Result<()> {
+    let wcs = wcs::Wcs::from_file(cli.wcs())?;
+    println!("Reference pixel: {} {}", wcs.crpix[0], wcs.crpix[1]);
+    println!("Reference position: {:.8} {:.8}", wcs.crval[0], wcs.crval[1]);
+    println!("Scale: {:.4}\"/px", wcs.pixel_scale());
+    println!("Rotation: {:.4} deg", wcs.rotation());
+    match &wcs.sip {
+        Some(sip) => println!("Distortion: SIP order {}{}", sip.a.order, if sip.ap.is_some() { " with inverse" } else { "" }),
+        None => println!("Distortion: none"),
+    }
+    if let Some(path) = cli.output() {
+        wcs.write_file(path)?;
+    }
+    Ok(())
+}
+
+// Report overlapping and duplicate frames of an archive
+fn overlaps(cli: &OverlapsCli) -> io::Result<()> {
+    let footprints = archive::read_footprints(cli.dir())?;
+    for footprint in &footprints {
+        println!("Frame {} center:{:.5} {:.5} radius:{:.3} deg scale:{:.3}\"/px", footprint.path.display(), footprint.ra, footprint.dec, footprint.radius, footprint.scale);
+    }
+    let groups = archive::overlapping_groups(&footprints);
+    for (n, group) in groups.iter().enumerate() {
+        println!("Group {}: {} overlapping frames", n + 1, group.len());
+        for (k, &i) in group.iter().enumerate() {
+            println!("  {}", footprints[i].path.display());
+            for &j in &group[k + 1..] {
+                if footprints[i].duplicates(&footprints[j]) {
+                    println!("  Duplicate: {} {} separation:{:.1}\"", footprints[i].path.display(), footprints[j].path.display(), footprints[i].separation(&footprints[j]) * 3600.0);
+                }
+            }
+        }
+    }
+    println!("Frames: {} Overlapping groups: {}", footprints.len(), groups.len());
+    Ok(())
+}
+
+// Export the footprint of a solved frame for Aladin Lite
+fn export_aladin(cli: &AladinCli) -> io::Result<()> {
+    let wcs = wcs::Wcs::from_file(cli.wcs())?;
+    let snippet = match aladin::footprint_snippet(cli.wcs(), &wcs, cli.survey()) {
+        Some(snippet) => snippet,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "The WCS header has no image size (IMAGEW/IMAGEH)")),
+    };
+    match cli.output() {
+        Some(path) => std::fs::write(path, snippet),
+        None => {
+            print!("{}", snippet);
+            Ok(())
+        }
+    }
+}
+
+/// Reads the detections of a sextractor CSV file or an astrometry.net xylist
+fn read_image_stars(path: &Path) -> Result<Vec<image::ImageStar>, Box<dyn std::error::Error>> {
+    if xylist::is_xylist(path) {
+        xylist::read_image_stars_from_file(path)
+    } else {
+        sextractor::read_image_stars_from_file(path)
+    }
+}
+
+// Remove the detections the shape columns of the sextractor file classify as not star-like
+fn exclude_non_stellar(image_star_list: &mut Vec<image::ImageStar>, path: &Path, filter: &sextractor::StellarFilter) {
+    if xylist::is_xylist(path) {
+        println!("Stellar filter: xylists have no detection shapes, all detections kept");
+        return;
+    }
+    let shapes = match sextractor::read_source_shapes(path) {
+        Ok(Some(shapes)) => shapes,
+        Ok(None) => {
+            println!("Stellar filter: no shape columns in {}, all detections kept", path.display());
+            return;
+        }
+        Err(err) => {
+            println!("Error reading detection shapes: {}", err);
+            return;
+        }
+    };
+    // The detections are sorted by magnitude: they are found by their position
+    let mut rejected = HashSet::new();
+    for (shape, reason) in shapes.iter().zip(sextractor::classify_sources(&shapes, filter)) {
+        if let Some(reason) = reason {
+            println!("Non-stellar detection x:{} y:{} {}", shape.pixel_x, shape.pixel_y, reason);
+            rejected.insert((shape.pixel_x.to_bits(), shape.pixel_y.to_bits()));
+        }
+    }
+    let count = image_star_list.len();
+    image_star_list.retain(|star| !rejected.contains(&(star.pixel_x.to_bits(), star.pixel_y.to_bits())));
+    println!("Stellar filter: {} of {} detections excluded", count - image_star_list.len(), count);
+}
+
+// Report the differences between two solutions of the same frame
+fn compare_solutions(cli: &CompareCli) -> io::Result<()> {
+    let reference = wcs::Wcs::from_file(cli.reference())?;
+    let solution = wcs::Wcs::from_file(cli.solution())?;
+    let format = cli.format();
+    let comparison = compare::compare(&reference, &solution);
+    println!("Center offset: {}", format.offset(comparison.center_offset));
+    println!("Scale difference: {:+.6}\"/px ({:+.1} ppm)", comparison.scale_difference, comparison.scale_difference / reference.pixel_scale() * 1e6);
+    println!("Rotation difference: {:+.4} deg", comparison.rotation_difference);
+    // Detections, or the frame corners and center
+    let pixels = match cli.sex_csv() {
+        Some(path) => match read_image_stars(path) {
+            Ok(star_list) => star_list.iter().map(|star| (star.pixel_x, star.pixel_y)).collect(),
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Error reading {}: {}", path.display(), err))),
+        },
+        None => compare::sample_pixels(&reference),
+    };
+    let mut offsets = Vec::new();
+    for (x, y) in &pixels {
+        let offset = compare::position_offset(&reference, &solution, *x, *y);
+        println!("Position x:{} y:{} offset:{}", x, y, format.offset(offset));
+        offsets.push(offset);
+    }
+    if let Some(median) = diagnostics::median(&offsets) {
+        let max = offsets.iter().cloned().fold(0.0, f64::max);
+        let rms = (offsets.iter().map(|offset| offset * offset).sum::<f64>() / offsets.len() as f64).sqrt();
+        println!("Offsets: {} positions, median:{} rms:{} max:{}", offsets.len(), format.offset(median), format.offset(rms), format.offset(max));
+    }
+    Ok(())
+}
+
+// Render a star chart from the catalog
+fn render_chart(cli: &ChartCli) -> io::Result<()> {
+    // Search the circle around the chart square
+    let radius = cli.fov_deg() * std::f64::consts::FRAC_1_SQRT_2;
+    let ra_hours = cli.ra_hours()?;
+    let star_list = gaia_db::read_stars_from_file(Hours(ra_hours), Degrees(cli.dec_deg()), Degrees(radius), cli.male(), None)
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    let wcs = chart::chart_wcs(ra_hours * 15.0, cli.dec_deg(), cli.fov_deg(), cli.size());
+    chart::write_chart_svg(cli.output(), &star_list, &wcs, cli.male(), cli.labels())?;
+    println!("Chart stars: {}", star_list.len());
+    Ok(())
+}
+
+// Report the footprint of a planned framing and the targets it covers
+fn plan_fov(cli: &FovCli) -> io::Result<()> {
+    // NaN when both are zero
+    if cli.scale().is_nan() || cli.scale() <= 0.0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--focal and --pixel must be positive"));
+    }
+    let ra = cli.ra_hours()? * 15.0;
+    let sensor = cli.sensor();
+    let wcs = fov::pointing_wcs(ra, cli.dec_deg(), cli.rotation_deg(), sensor, cli.scale());
+    let format = cli.format();
+    println!("Center R.A.:{} Dec:{} rotation:{} deg", format.ra(ra), format.dec(cli.dec_deg()), cli.rotation_deg());
+    println!("Scale: {:.3}\"/px", cli.scale());
+    println!("Field: {:.3} x {:.3} deg", sensor.width as f64 * cli.scale() / 3600.0, sensor.height as f64 * cli.scale() / 3600.0);
+    for (corner, (corner_ra, corner_dec)) in ["x:min y:min", "x:max y:min", "x:max y:max", "x:min y:max"].iter().zip(wcs.corners().unwrap_or_default()) {
+        println!("Corner {} R.A.:{} Dec:{}", corner, format.ra(corner_ra), format.dec(corner_dec));
+    }
+    if let Some(path) = cli.targets() {
+        let target_list = targets::read_targets(path, format::RaUnit::Degrees)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        let mut inside = 0;
+        for target in &target_list {
+            match targets::place(target, &wcs) {
+                Some(placement) => {
+                    println!("Target {} in frame x:{:.1} y:{:.1} edge:{:.1} px", target.name, placement.x, placement.y, placement.edge_distance);
+                    inside += 1;
+                }
+                None => {
+                    let rad = |degrees: f64| Radians::from(Degrees(degrees));
+                    let separation = math::haversine_separation_radians(rad(ra), rad(cli.dec_deg()), rad(target.ra), rad(target.dec));
+                    println!("Target {} outside, {:.3} deg from the center", target.name, Degrees::from(separation).0);
+                }
+            }
+        }
+        println!("Targets in frame: {} of {}", inside, target_list.len());
+    }
+    Ok(())
+}
+
+// Report the frames and pixel positions of the targets of a list
+fn report_targets(cli: &TargetsCli) -> io::Result<()> {
+    let target_list = targets::read_targets(cli.targets(), cli.ra_unit())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    let frames = archive::read_frames(cli.dir())?;
+    for (path, wcs) in &frames {
+        if wcs.image_size.is_none() {
+            println!("Skipping {}: no image size (IMAGEW/IMAGEH)", path.display());
+        }
+    }
+    let mut placed = 0;
+    for target in &target_list {
+        let mut frame_count = 0;
+        for (path, wcs) in &frames {
+            if let Some(placement) = targets::place(target, wcs) {
+                let warning = if placement.edge_distance < cli.margin() { " near edge" } else { "" };
+                println!("Target {} frame:{} x:{:.2} y:{:.2} edge:{:.1} px{}", target.name, path.display(), placement.x, placement.y, placement.edge_distance, warning);
+                frame_count += 1;
+            }
+        }
+        if frame_count == 0 {
+            println!("Target {}: on no frame", target.name);
+        } else {
+            placed += 1;
+        }
+    }
+    println!("Targets: {} Frames: {} Targets on frames: {}", target_list.len(), frames.len(), placed);
+    Ok(())
+}
+
+// Map the catalog coverage, to find its holes
+fn density_map(cli: &DensityCli) -> io::Result<()> {
+    let nside = cli.nside();
+    if !nside.is_power_of_two() || nside > 8192 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--nside {} is not a power of two up to 8192", nside)));
+    }
+    // The whole sky
+    let star_list = gaia_db::read_stars_from_file(Hours(0.0), Degrees(0.0), Degrees(180.0), cli.male(), None)
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    let pixels = healpix::pixel_count(nside);
+    let mut counts = vec![0_u32; pixels];
+    let mut mag_limits = vec![healpix::UNSEEN; pixels];
+    for star in &star_list {
+        let pixel = healpix::ang_to_pixel_ring(nside, star.ra_rad.0, star.dec_rad.0);
+        counts[pixel] += 1;
+        if mag_limits[pixel] == healpix::UNSEEN || star.magnitude as f32 > mag_limits[pixel] {
+            mag_limits[pixel] = star.magnitude as f32;
+        }
+    }
+    healpix::write_fits_map(cli.output(), nside, &counts, &mag_limits)?;
+
+    let empty = counts.iter().filter(|&&count| count == 0).count();
+    let mut sorted: Vec<f64> = counts.iter().map(|&count| count as f64).collect();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let area = 4.0 * std::f64::consts::PI * (180.0 / std::f64::consts::PI).powi(2) / pixels as f64;
+    println!("Catalog stars: {}", star_list.len());
+    println!("Pixels: {} nside:{} ({:.3} sq deg each)", pixels, nside, area);
+    println!("Empty pixels: {} ({:.1}%)", empty, 100.0 * empty as f64 / pixels as f64);
+    if let Some(median) = diagnostics::median(&sorted) {
+        println!("Stars per pixel: min:{} median:{} max:{}", sorted[0], median, sorted[pixels - 1]);
+    }
+    Ok(())
 }
 
 fn main() -> io::Result<()> {
@@ -130,24 +1565,98 @@ fn main() -> io::Result<()> {
     env_logger::builder().format_timestamp(None).init();
  
     // CLI interface information
-    let cli = Cli::from_args();
+    match Command::from_iter(command_args()) {
+        Command::Solve(cli) => report_outcome(solve(cli)?),
+        Command::Xy2sky(cli) => xy2sky(&cli),
+        Command::Sky2xy(cli) => sky2xy(&cli),
+        Command::Wcs(cli) => show_wcs(&cli),
+        Command::Overlaps(cli) => overlaps(&cli),
+        Command::Aladin(cli) => export_aladin(&cli),
+        Command::Chart(cli) => render_chart(&cli),
+        Command::Compare(cli) => compare_solutions(&cli),
+        Command::Density(cli) => density_map(&cli),
+        Command::Targets(cli) => report_targets(&cli),
+        Command::Fov(cli) => plan_fov(&cli),
+        Command::Diagnostics(cli) => report_outcome(solve(cli.solve())?),
+    }
+}
+
+// Print the outcome of a solve, and exit with its code
+fn report_outcome(outcome: diagnostics::Outcome) -> io::Result<()> {
+    println!("Outcome: {}", outcome);
+    if outcome.exit_code() != 0 {
+        std::process::exit(outcome.exit_code());
+    }
+    Ok(())
+}
+
+/// Most catalog reads while widening the search
+const MAX_CATALOG_READS: usize = 32;
+
+/// Reads the catalog stars in the search cone. While there are too few, widens the
+/// radii and deepens the limiting magnitude up to their maximums, leaving the final values in `cli`.
+fn read_catalog_stars(cli: &mut Cli, ra_hours: f64, catalog: star_catalog::Catalog) -> Vec<polygon::Star> {
+    let (max_radii, max_male) = (cli.max_radii_deg(), cli.max_male());
+    let mut expanded = false;
+    for read in 1..=MAX_CATALOG_READS {
+        let star_list = match catalog.read_stars(Hours(ra_hours), Degrees(cli.dec_deg()), Degrees(cli.radii_deg()), cli.male(), cli.solver_limits().catalog_stars()) {
+            Ok(star_list) => star_list,
+            Err(err) => {
+                println!("Error {:?}", err);
+                return Vec::new();
+            }
+        };
+        let exhausted = (cli.radii_deg >= max_radii && cli.male >= max_male) || read == MAX_CATALOG_READS;
+        if star_list.len() >= cli.min_catalog_stars() || exhausted {
+            if expanded {
+                println!("Catalog search expanded to radii {:.3} deg, limiting magnitude {:.2}: {} stars", cli.radii_deg, cli.male, star_list.len());
+            }
+            if star_list.len() < cli.min_catalog_stars() {
+                println!("Warning: {} catalog stars, fewer than {}, at the maximum radii and magnitude", star_list.len(), cli.min_catalog_stars());
+            }
+            return star_list;
+        }
+        debug!("Catalog stars: {} in radii {} deg to magnitude {}, widening", star_list.len(), cli.radii_deg, cli.male);
+        // Widen by a quarter of the radii and half a magnitude per step
+        cli.radii_deg = (cli.radii_deg * 1.25).min(max_radii);
+        cli.male = (cli.male + 0.5).min(max_male);
+        expanded = true;
+    }
+    Vec::new()
+}
 
-    // Read star database (Mini Gaia DR2) file
-    let mut star_list: Vec<polygon::Star> = Vec::new();
+// Match the image detections with the star catalog
+fn solve(mut cli: Cli) -> io::Result<diagnostics::Outcome> {
+    let start = Instant::now();
+    let max_time = cli.max_time()?;
+    cli.check_radii()?;
 
-    match gaia_db::read_stars_from_file(cli.ra_deg(), cli.dec_deg(), cli.radii_deg(), cli.male()) {
-        Ok(star_list_read) => {
-            star_list = star_list_read;
+    // Equipment profile
+    if let Some(name) = cli.profile() {
+        let profile = profile::read_profile(cli.config(), name)?;
+        println!("Profile: {}", profile.name);
+        if let Some(scale) = profile.scale() {
+            println!("Profile scale: {:.3}\"/px", scale);
+        }
+        if let Some(rotation) = profile.rotation_deg {
+            // The polygon matcher is rotation independent
+            println!("Profile rotation: {} deg", rotation);
         }
-        Err(err) => println!("Error {:?}", err),
+        cli.apply_profile(&profile)?;
     }
-    for star in &star_list {
-        println!("Star id:{}\tdb_id:{}\tra:{} \tdec:{}\tmagnitude:{}", star.id, star.db_id, star.ra, star.dec, star.magnitude);
+
+    // Report the mid-exposure time used for epoch-dependent corrections
+    if let Some(jd) = cli.mid_exposure_jd() {
+        println!("Mid-exposure JD:{:.6} MJD:{:.6}", jd, time::modified_julian_day(jd));
     }
 
+    // Search center, in both R.A. units
+    let ra_hours = cli.ra_hours()?;
+    println!("Search center R.A.:{:.6}h ({:.6} deg) Dec:{:.6} deg", ra_hours, ra_hours * 15.0, cli.dec_deg());
+
     // Read star coordinates from sextractor
     let mut image_star_list: Vec<image::ImageStar> = Vec::new();
-    match sextractor::read_image_stars_from_file(cli.sex_csv()) {
+    match read_image_stars(cli.sex_csv()) {
         Ok(image_star_list_read) => {
             for star in &image_star_list_read {
                 println!("Image Star x:{} y:{} mag:{}", star.pixel_x, star.pixel_y, star.magnitude);
@@ -156,15 +1665,189 @@ fn main() -> io::Result<()> {
         }
         Err(err) => println!("Error reading image star list: {}", err)
     }
+    // Pick the catalog by the field of view: the detection extent at the given scale, or the search cone
+    let field_deg = match cli.scale() {
+        Some(scale) if !image_star_list.is_empty() => 2.0 * diagnostics::detection_field(&image_star_list).1 * scale / 3600.0,
+        _ => 2.0 * cli.radii_deg(),
+    };
+    let catalog = star_catalog::select(cli.catalog(), field_deg);
+    // Later stages (the star pairs identifiers) need the catalog in use
+    cli.catalog = star_catalog::CatalogChoice::Fixed(catalog);
+    println!("Catalog: {} ({}, field {:.2} deg)", catalog, catalog.path(), field_deg);
+
+    // Read star database file
+    let mut star_list = read_catalog_stars(&mut cli, ra_hours, catalog);
+    let mut model = cli.fit_model()?;
+    // Apply the requested corrections to the catalog positions: from catalog place to
+    // apparent place (deflection, aberration) and then to observed place (refraction)
+    if let Some(velocity) = cli.velocity() {
+        // Independent of the date: the platform motion relative to the Earth center
+        correct_star_positions(&mut star_list, "Platform aberration", &cli.format(), |ra, dec| math::velocity_aberration_radians(ra, dec, &velocity));
+    }
+    if cli.light_deflection() || cli.aberration() || cli.refraction() {
+        let jd = match cli.mid_exposure_jd() {
+            Some(jd) => jd,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Position corrections need --date-obs")),
+        };
+        if cli.light_deflection() {
+            correct_star_positions(&mut star_list, "Light deflection", &cli.format(), |ra, dec| math::light_deflection_radians(ra, dec, jd));
+        }
+        if cli.aberration() {
+            correct_star_positions(&mut star_list, "Aberration", &cli.format(), |ra, dec| math::annual_aberration_radians(ra, dec, jd));
+        }
+        if cli.refraction() {
+            if cli.fit_refraction() {
+                // The positions before refraction set the refraction term of the fit
+                model.refraction = Some(star_list.iter().map(|star| (star.ra_rad.0, star.dec_rad.0)).collect());
+            }
+            match cli.site() {
+                Some(site) => correct_star_positions(&mut star_list, "Refraction", &cli.format(), |ra, dec| math::refract_equatorial_radians(ra, dec, jd, &site)),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "--refraction needs --latitude and --longitude")),
+            }
+        }
+    }
+    // Compare the catalog and observation epochs. The catalog has no proper motions to apply.
+    if let (Some(jd), star_catalog::Catalog::Gaia) = (cli.mid_exposure_jd(), catalog) {
+        match gaia_db::read_epoch() {
+            Ok(catalog_epoch) => {
+                let epoch = time::julian_epoch(jd);
+                println!("Catalog epoch: J{:.1} Observation epoch: J{:.3}", catalog_epoch, epoch);
+                if (epoch - catalog_epoch).abs() > cli.max_epoch_gap() {
+                    println!("Warning: catalog epoch J{:.1} is {:.1} years from the observation, stars with large proper motion will be misplaced",
+                        catalog_epoch, (epoch - catalog_epoch).abs());
+                }
+            }
+            Err(err) => println!("Error reading catalog epoch: {:?}", err),
+        }
+    }
+    for star in &star_list {
+        println!("Star id:{}\tdb_id:{}\tra:{} \tdec:{}\tmagnitude:{}", star.id, star.db_id, cli.format().ra(Degrees::from(star.ra()).0), cli.format().dec(star.dec().0), star.magnitude);
+    }
+
+    // Trailed frames: the sextractor barycenter is already the trail midpoint, only the trail is reported
+    if cli.trailed() {
+        let trails = if xylist::is_xylist(cli.sex_csv()) {
+            Err("xylists have no detection shapes, trails need a sextractor CSV file".into())
+        } else {
+            sextractor::read_trails_from_file(cli.sex_csv())
+        };
+        match trails {
+            Ok(trails) => match image::common_trail(&trails) {
+                Some(trail) => println!("Trail length:{:.2} px angle:{:.2} deg ({} detections)", trail.length, trail.angle, trails.len()),
+                None => println!("Trail: no detections"),
+            },
+            Err(err) => println!("Error reading trails: {}", err),
+        }
+    }
+    // Exclude galaxies and artifacts, before the positions are converted
+    if let Some(filter) = cli.stellar_filter() {
+        exclude_non_stellar(&mut image_star_list, cli.sex_csv(), &filter);
+    }
+    // Work in the FITS convention: 1-based, origin at the bottom-left
+    let pixel_convention = cli.pixel_convention();
+    pixel_convention.to_fits(&mut image_star_list);
+    println!("Pixel convention: {}", pixel_convention);
+    if let Some(radius) = cli.merge_radius() {
+        let merged = image::merge_close_detections(&mut image_star_list, radius);
+        println!("Merged detections: {} (radius {} px)", merged, radius);
+    }
     println!("Image list length: {}", image_star_list.len());
 
-    star_list.truncate(500);
-    image_star_list.truncate(500);
+    // Resource limits: the catalog was read within them, the detections are cut to the brightest
+    let solver_limits = cli.solver_limits();
+    let dropped = limits::truncate_brightest(&mut image_star_list, solver_limits.image_stars());
+    if dropped > 0 {
+        println!("Limits: {} faintest detections dropped, {} kept", dropped, image_star_list.len());
+    }
 
-    // If stars found on the image, then find and match the polygons
-    if image_star_list.len() > 10 {
-        find_polygons_and_fit(star_list, image_star_list, cli.scale());
+    // Too few detections to form enough polygons
+    if image_star_list.len() < cli.min_detections() {
+        println!("Diagnosis: {}", diagnostics::Diagnosis::TooFewDetections { count: image_star_list.len(), minimum: cli.min_detections() });
+        // Too few for polygons: match the brightest detections as an asterism, at the known scale
+        if let Some(scale) = cli.scale().filter(|_| image_star_list.len() >= asterism::MIN_STARS && cli.diagnostics().is_none()) {
+            if let Some(outcome) = match_asterism_and_fit(&star_list, &image_star_list, scale, &model, &cli) {
+                return Ok(outcome);
+            }
+        }
+        return Ok(diagnostics::Outcome::NotEnoughDetections { count: image_star_list.len(), minimum: cli.min_detections() });
     }
- 
-    Ok(())
-}
\ No newline at end of file
+
+    // Rough scale from the nearest-neighbour separations, to seed or sanity-check --scale
+    let estimated_scale = scale::estimate_from_neighbours(&star_list, &image_star_list);
+    let scale = match (cli.scale(), estimated_scale) {
+        (Some(scale), Some(estimated)) => {
+            println!("Scale: {}\"/px (nearest-neighbour estimate {:.3}\"/px)", scale, estimated);
+            if estimated / scale > 2.0 || estimated / scale < 0.5 {
+                println!("Warning: --scale {} is far from the nearest-neighbour estimate {:.3}", scale, estimated);
+            }
+            scale
+        }
+        (Some(scale), None) => scale,
+        (None, Some(estimated)) => {
+            println!("Scale: {:.3}\"/px estimated from nearest-neighbour separations", estimated);
+            estimated
+        }
+        (None, None) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not enough stars to estimate the scale, set --scale")),
+    };
+
+    // Polygon similarity tolerance from the centroid error
+    let centroid_error = match cli.centroid_error() {
+        Some(error) => Some(error),
+        None if xylist::is_xylist(cli.sex_csv()) => None,
+        None => match sextractor::read_centroid_error(cli.sex_csv()) {
+            Ok(error) => error,
+            Err(err) => {
+                println!("Error reading centroid errors: {}", err);
+                None
+            }
+        },
+    };
+    match centroid_error {
+        Some(error) => println!("Centroid error: {:.3} px", error),
+        None => println!("Centroid error: unknown, fixed similarity tolerance {}", polygon::MIN_SIMILARITY),
+    }
+
+    // Find and match the polygons
+    let deadline = max_time.map(|max_time| start + max_time);
+    Ok(find_polygons_and_fit(star_list, image_star_list, scale, centroid_error, deadline, &model, &cli))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve_cli(args: &[&str]) -> Cli {
+        let base = ["solve", "--ra", "5.5", "--dec", "0", "--sex-csv", "stars.csv"];
+        Cli::from_iter(base.iter().chain(args.iter()))
+    }
+
+    #[test]
+    fn max_time_must_be_positive() {
+        assert_eq!(solve_cli(&["--radii", "10"]).max_time().unwrap(), None);
+        assert_eq!(solve_cli(&["--radii", "10", "--max-time", "1.5"]).max_time().unwrap(), Some(Duration::from_millis(1500)));
+        for arg in ["--max-time=0", "--max-time=-1", "--max-time=NaN", "--max-time=inf"].iter() {
+            let err = solve_cli(&["--radii", "10", arg]).max_time().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn radii_must_be_positive() {
+        assert!(solve_cli(&["--radii", "10"]).check_radii().is_ok());
+        assert!(solve_cli(&["--radii", "10", "--max-radii", "10"]).check_radii().is_ok());
+        for args in [&["--radii", "10", "--max-radii", "5"][..], &["--radii=0", "--max-radii", "20"], &["--radii=-1"], &["--radii=NaN"]].iter() {
+            let err = solve_cli(args).check_radii().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+    #[test]
+    fn crpix_center_needs_the_image_size() {
+        let model = solve_cli(&["--radii", "10", "--crpix", "center", "--image-width", "3000", "--image-height", "2000"]).fit_model().unwrap();
+        assert_eq!(model.reference_pixel, solution::ReferencePixel::Fixed([1500.5, 1000.5]));
+        let model = solve_cli(&["--radii", "10", "--crpix", "512.5,384"]).fit_model().unwrap();
+        assert_eq!(model.reference_pixel, solution::ReferencePixel::Fixed([512.5, 384.0]));
+        assert_eq!(solve_cli(&["--radii", "10"]).fit_model().unwrap().reference_pixel, solution::ReferencePixel::Fit);
+        let err = solve_cli(&["--radii", "10", "--crpix", "center", "--image-height", "2000"]).fit_model().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!("1,x".parse::<image::CrpixChoice>().is_err());
+    }
+}
@@ -1,10 +1,336 @@
-///  Hours (right ascension) to radians
-pub fn hours_to_radians(hours: f64) -> f64 {
-    hours * 0.26179938779914943653855361527329
-}
+use std::str::FromStr;
+
+use rastap_core::angle::{Degrees, Radians};
+
+/// Speed of light in km/s
+pub const SPEED_OF_LIGHT_KMS: f64 = 299792.458;
 
 /// Calculate angular separation (Source: Astronomical Algorithms, Meeus)
-pub fn angular_separation_radians(ra1: f64, dec1: f64, ra2: f64, dec2: f64) -> f64 {   
+pub fn angular_separation_radians(ra1: Radians, dec1: Radians, ra2: Radians, dec2: Radians) -> Radians {
     // cos(d) = sin(d1) * sin(d2) + cos(d1) * cos(d2) * cos(a1 - a2)
-    (dec1.sin() * dec2.sin() + dec1.cos() * dec2.cos() * (ra2 - ra1).cos()).acos()
+    Radians::acos(dec1.sin() * dec2.sin() + dec1.cos() * dec2.cos() * (ra2 - ra1).cos())
+}
+
+/// Angular separation by the haversine formula, accurate for small separations
+pub fn haversine_separation_radians(ra1: Radians, dec1: Radians, ra2: Radians, dec2: Radians) -> Radians {
+    let h = ((dec2 - dec1) / 2.0).sin().powi(2) + dec1.cos() * dec2.cos() * ((ra2 - ra1) / 2.0).sin().powi(2);
+    Radians::asin(h.sqrt().min(1.0)) * 2.0
+}
+
+/// Observing site and atmospheric conditions
+pub struct Site {
+    pub latitude_rad: Radians,  // North positive
+    pub longitude_rad: Radians, // East positive
+    pub pressure_hpa: f64,
+    pub temperature_c: f64,
+}
+
+/// Greenwich mean sidereal time in radians (Source: Astronomical Algorithms, Meeus, 12.4)
+pub fn greenwich_mean_sidereal_time_radians(jd: f64) -> Radians {
+    let d = jd - 2451545.0;
+    let t = d / 36525.0;
+    let theta = 280.46061837 + 360.98564736629 * d + 0.000387933 * t * t - t * t * t / 38710000.0;
+    Radians::from(Degrees(theta.rem_euclid(360.0)))
+}
+
+/// Equatorial to horizontal coordinates. Azimuth measured from the South (Source: Astronomical Algorithms, Meeus, 13.5)
+pub fn equatorial_to_horizontal_radians(hour_angle: Radians, dec: Radians, latitude: Radians) -> (Radians, Radians) {
+    let azimuth = Radians::atan2(hour_angle.sin(), hour_angle.cos() * latitude.sin() - dec.tan() * latitude.cos());
+    let altitude = Radians::asin(latitude.sin() * dec.sin() + latitude.cos() * dec.cos() * hour_angle.cos());
+    (azimuth, altitude)
+}
+
+/// Horizontal to equatorial coordinates. Returns hour angle and declination
+pub fn horizontal_to_equatorial_radians(azimuth: Radians, altitude: Radians, latitude: Radians) -> (Radians, Radians) {
+    let hour_angle = Radians::atan2(azimuth.sin(), azimuth.cos() * latitude.sin() + altitude.tan() * latitude.cos());
+    let dec = Radians::asin(latitude.sin() * altitude.sin() - latitude.cos() * altitude.cos() * azimuth.cos());
+    (hour_angle, dec)
+}
+
+/// Atmospheric refraction for a true altitude (Source: Astronomical Algorithms, Meeus, 16.4 Saemundsson)
+pub fn refraction_radians(altitude: Radians, pressure_hpa: f64, temperature_c: f64) -> Radians {
+    let h = Degrees::from(altitude).0;
+    if h < -1.0 {
+        // Below the horizon, the formula is meaningless
+        return Radians(0.0);
+    }
+    let r_arcmin = 1.02 / Radians::from(Degrees(h + 10.3 / (h + 5.11))).tan();
+    // Correct for pressure and temperature
+    let r_arcmin = r_arcmin * (pressure_hpa / 1010.0) * (283.0 / (273.0 + temperature_c));
+    Radians::from(Degrees(r_arcmin / 60.0))
+}
+
+/// Apparent (refracted) equatorial position of a star seen from the site at a Julian Day
+pub fn refract_equatorial_radians(ra: Radians, dec: Radians, jd: f64, site: &Site) -> (Radians, Radians) {
+    let lst = greenwich_mean_sidereal_time_radians(jd) + site.longitude_rad;
+    let hour_angle = lst - ra;
+    let (azimuth, altitude) = equatorial_to_horizontal_radians(hour_angle, dec, site.latitude_rad);
+    let altitude = altitude + refraction_radians(altitude, site.pressure_hpa, site.temperature_c);
+    let (hour_angle, dec) = horizontal_to_equatorial_radians(azimuth, altitude, site.latitude_rad);
+    ((lst - hour_angle).normalized(), dec)
+}
+
+/// Julian centuries since J2000.0
+fn julian_centuries(jd: f64) -> f64 {
+    (jd - 2451545.0) / 36525.0
+}
+
+/// Mean obliquity of the ecliptic (Source: Astronomical Algorithms, Meeus, 22.2)
+pub fn mean_obliquity_radians(jd: f64) -> Radians {
+    let t = julian_centuries(jd);
+    Radians::from(Degrees(23.4392911111 - 0.0130041667 * t - 0.0000001639 * t * t + 0.0000005036 * t * t * t))
+}
+
+/// Sun true geometric longitude (Source: Astronomical Algorithms, Meeus, chapter 25)
+pub fn sun_true_longitude_radians(jd: f64) -> Radians {
+    let t = julian_centuries(jd);
+    let l0 = 280.46646 + 36000.76983 * t + 0.0003032 * t * t;
+    let m = Radians::from(Degrees(357.52911 + 35999.05029 * t - 0.0001537 * t * t));
+    let c = (1.914602 - 0.004817 * t - 0.000014 * t * t) * m.sin()
+        + (0.019993 - 0.000101 * t) * (m * 2.0).sin()
+        + 0.000289 * (m * 3.0).sin();
+    Radians::from(Degrees((l0 + c).rem_euclid(360.0)))
+}
+
+/// Sun equatorial position, ignoring its ecliptic latitude
+pub fn sun_equatorial_radians(jd: f64) -> (Radians, Radians) {
+    let longitude = sun_true_longitude_radians(jd);
+    let obliquity = mean_obliquity_radians(jd);
+    let ra = Radians::atan2(obliquity.cos() * longitude.sin(), longitude.cos());
+    let dec = Radians::asin(obliquity.sin() * longitude.sin());
+    (ra.normalized(), dec)
+}
+
+/// Apply annual aberration to an equatorial position (Source: Astronomical Algorithms, Meeus, 23.3)
+pub fn annual_aberration_radians(ra: Radians, dec: Radians, jd: f64) -> (Radians, Radians) {
+    let t = julian_centuries(jd);
+    let kappa = Radians::from(Degrees(20.49552 / 3600.0));
+    let e = 0.016708634 - 0.000042037 * t - 0.0000001267 * t * t;
+    let pi = Radians::from(Degrees(102.93735 + 1.71946 * t + 0.00046 * t * t));
+    let sun = sun_true_longitude_radians(jd);
+    let eps = mean_obliquity_radians(jd);
+
+    let d_ra = (-kappa * (ra.cos() * sun.cos() * eps.cos() + ra.sin() * sun.sin())
+        + kappa * e * (ra.cos() * pi.cos() * eps.cos() + ra.sin() * pi.sin())) / dec.cos();
+    let d_dec = -kappa * (sun.cos() * eps.cos() * (eps.tan() * dec.cos() - ra.sin() * dec.sin()) + ra.cos() * dec.sin() * sun.sin())
+        + kappa * e * (pi.cos() * eps.cos() * (eps.tan() * dec.cos() - ra.sin() * dec.sin()) + ra.cos() * dec.sin() * pi.sin());
+    (ra + d_ra, dec + d_dec)
+}
+
+/// Observer velocity relative to the Earth center, equatorial (J2000) axes, km/s
+#[derive(Clone, Copy, Debug)]
+pub struct Velocity(pub [f64; 3]);
+
+impl FromStr for Velocity {
+    type Err = String;
+
+    fn from_str(components: &str) -> Result<Velocity, String> {
+        let error = || format!("Invalid velocity '{}', expected VX,VY,VZ in km/s", components);
+        let values = components.split(',')
+            .map(|value| value.trim().parse::<f64>().map_err(|_| error()))
+            .collect::<Result<Vec<f64>, String>>()?;
+        match values[..] {
+            [x, y, z] if (x * x + y * y + z * z).sqrt() < SPEED_OF_LIGHT_KMS => Ok(Velocity([x, y, z])),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// Apply the aberration of an observer moving at a velocity to a unit direction vector, relativistic
+/// form (Source: Explanatory Supplement to the Astronomical Almanac, 7.2.3)
+fn velocity_aberration_vector(star: [f64; 3], velocity: &Velocity) -> [f64; 3] {
+    let beta = [velocity.0[0] / SPEED_OF_LIGHT_KMS, velocity.0[1] / SPEED_OF_LIGHT_KMS, velocity.0[2] / SPEED_OF_LIGHT_KMS];
+    let beta2 = beta[0] * beta[0] + beta[1] * beta[1] + beta[2] * beta[2];
+    let inverse_gamma = (1.0 - beta2).sqrt();
+    let dot = star[0] * beta[0] + star[1] * beta[1] + star[2] * beta[2];
+    // u' = (u / gamma + (1 + u.beta / (1 + 1 / gamma)) beta) / (1 + u.beta)
+    let along = 1.0 + dot / (1.0 + inverse_gamma);
+    let mut moved = [0.0; 3];
+    for i in 0..3 {
+        moved[i] = (inverse_gamma * star[i] + along * beta[i]) / (1.0 + dot);
+    }
+    moved
+}
+
+/// Apply the aberration of an observer moving at a velocity to an equatorial position
+pub fn velocity_aberration_radians(ra: Radians, dec: Radians, velocity: &Velocity) -> (Radians, Radians) {
+    let moved = velocity_aberration_vector([dec.cos() * ra.cos(), dec.cos() * ra.sin(), dec.sin()], velocity);
+    (Radians::atan2(moved[1], moved[0]).normalized(), Radians::asin(moved[2] / (moved[0] * moved[0] + moved[1] * moved[1] + moved[2] * moved[2]).sqrt()))
+}
+
+/// Apply the gravitational deflection of light by the Sun to an equatorial position
+pub fn light_deflection_radians(ra: Radians, dec: Radians, jd: f64) -> (Radians, Radians) {
+    let (sun_ra, sun_dec) = sun_equatorial_radians(jd);
+    let psi = angular_separation_radians(ra, dec, sun_ra, sun_dec);
+    if psi < Radians::from(Degrees(0.3)) {
+        // Behind the solar disk
+        return (ra, dec);
+    }
+    // Deflection at 1 AU: 2GM/(c²R) = 0.00407", pushing the star away from the Sun
+    let deflection = Radians::from(Degrees(0.00407 / 3600.0)) / (psi / 2.0).tan();
+    let star = [dec.cos() * ra.cos(), dec.cos() * ra.sin(), dec.sin()];
+    let sun = [sun_dec.cos() * sun_ra.cos(), sun_dec.cos() * sun_ra.sin(), sun_dec.sin()];
+    let mut moved = [0.0; 3];
+    for i in 0..3 {
+        let away = (star[i] * psi.cos() - sun[i]) / psi.sin();
+        moved[i] = star[i] * deflection.cos() + away * deflection.sin();
+    }
+    (Radians::atan2(moved[1], moved[0]).normalized(), Radians::asin(moved[2]))
+}
+
+/// Right Ascension/Declination bounding box of a search cone
+pub struct ConeBox {
+    ra_center: f64,
+    dec_min: f64,
+    dec_max: f64,
+    ra_half_width: f64,
+}
+
+impl ConeBox {
+    /// Bounding box of the cone of the given radius around a center
+    pub fn new(ra_center: Radians, dec_center: Radians, radius: Radians) -> ConeBox {
+        let (ra_center, dec_center, radius) = (ra_center.0, dec_center.0, radius.0);
+        let half_pi = std::f64::consts::FRAC_PI_2;
+        let dec_min = (dec_center - radius).max(-half_pi);
+        let dec_max = (dec_center + radius).min(half_pi);
+        // R.A. width grows as 1/cos(dec). If the cone contains a pole, every R.A. is inside.
+        let ra_half_width = if dec_center.abs() + radius >= half_pi {
+            std::f64::consts::PI
+        } else {
+            (radius.sin() / dec_center.cos()).asin()
+        };
+        ConeBox { ra_center, dec_min, dec_max, ra_half_width }
+    }
+
+    /// Whether a position is inside the box. Cheap test before the exact angular separation.
+    pub fn contains(&self, ra: Radians, dec: Radians) -> bool {
+        let (ra, dec) = (ra.0, dec.0);
+        if dec < self.dec_min || dec > self.dec_max {
+            return false;
+        }
+        // R.A. difference wrapped to [-PI, PI)
+        let pi = std::f64::consts::PI;
+        let d_ra = (ra - self.ra_center + pi).rem_euclid(2.0 * pi) - pi;
+        d_ra.abs() <= self.ra_half_width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arcsec(angle: Radians) -> f64 {
+        Degrees::from(angle).0 * 3600.0
+    }
+
+    #[test]
+    fn annual_aberration_of_meeus_example() {
+        // Astronomical Algorithms, example 23.a: theta Persei on 2028 November 13.19 TD,
+        // at its position of the date. Aberration: +30.045" in R.A., +6.697" in Dec.
+        let (ra, dec) = (Radians::from(Degrees(41.5599646)), Radians::from(Degrees(49.3520685)));
+        let (ra_apparent, dec_apparent) = annual_aberration_radians(ra, dec, 2462088.69);
+        assert!((arcsec(ra_apparent - ra) - 30.045).abs() < 0.01);
+        assert!((arcsec(dec_apparent - dec) - 6.697).abs() < 0.01);
+    }
+
+    #[test]
+    fn refraction_of_meeus_example() {
+        // Astronomical Algorithms, example 16.a: the true altitude 0°01'15" is seen at 0°30',
+        // refracted by 28.754' (formula 16.3). Formula 16.4 agrees within 0.1'.
+        let r_arcmin = arcsec(refraction_radians(Radians::from(Degrees(1.25 / 60.0)), 1010.0, 10.0)) / 60.0;
+        assert!((r_arcmin - 28.754).abs() < 0.1);
+        // Vanishes at the zenith and scales with the air density
+        assert!(arcsec(refraction_radians(Radians::from(Degrees(90.0)), 1010.0, 10.0)).abs() < 0.2);
+        let at_45 = refraction_radians(Radians::from(Degrees(45.0)), 1010.0, 10.0);
+        assert!((arcsec(at_45) - 60.0).abs() < 1.0);
+        assert!((refraction_radians(Radians::from(Degrees(45.0)), 505.0, 10.0).0 - at_45.0 / 2.0).abs() < 1e-15);
+    }
+
+    #[test]
+    fn light_deflection_pushes_away_from_the_sun() {
+        // 90 degrees from the Sun, the deflection is 0.00407"
+        let jd = 2451545.0;
+        let (sun_ra, sun_dec) = sun_equatorial_radians(jd);
+        let (ra, dec) = (sun_ra + Radians::from(Degrees(90.0)), Radians(0.0));
+        let elongation = angular_separation_radians(ra, dec, sun_ra, sun_dec);
+        let (ra_deflected, dec_deflected) = light_deflection_radians(ra, dec, jd);
+        let shift = haversine_separation_radians(ra, dec, ra_deflected, dec_deflected);
+        let expected = 0.00407 / (elongation / 2.0).tan();
+        assert!((arcsec(shift) - expected).abs() < 1e-6);
+        assert!(angular_separation_radians(ra_deflected, dec_deflected, sun_ra, sun_dec) > elongation);
+        // Behind the solar disk, unchanged
+        assert_eq!(light_deflection_radians(sun_ra, sun_dec, jd), (sun_ra, sun_dec));
+    }
+
+    #[test]
+    fn velocity_aberration_without_velocity_is_identity() {
+        let (ra, dec) = (Radians(1.2), Radians(-0.4));
+        let (ra_moved, dec_moved) = velocity_aberration_radians(ra, dec, &Velocity([0.0, 0.0, 0.0]));
+        assert!((ra_moved - ra).abs().0 < 1e-15 && (dec_moved - dec).abs().0 < 1e-15);
+    }
+
+    #[test]
+    fn velocity_aberration_matches_classical_shift() {
+        // 7.5 km/s along X, star 60 degrees away from the apex: v/c sin(theta) towards the apex
+        let velocity = Velocity([7.5, 0.0, 0.0]);
+        let (ra, dec) = (Radians::from(Degrees(60.0)), Radians(0.0));
+        let (ra_moved, dec_moved) = velocity_aberration_radians(ra, dec, &velocity);
+        let classical = 7.5 / SPEED_OF_LIGHT_KMS * ra.sin();
+        // The relativistic terms are of order beta relative to it
+        assert!(((ra - ra_moved).0 - classical).abs() < 1e-4 * classical);
+        assert!(dec_moved.abs().0 < 1e-15);
+    }
+
+    #[test]
+    fn velocity_aberration_keeps_unit_vectors() {
+        let velocity = Velocity([30000.0, -20000.0, 10000.0]);
+        for star in [[1.0, 0.0, 0.0], [0.0, 0.6, 0.8], [-0.48, 0.6, -0.64]].iter() {
+            let moved = velocity_aberration_vector(*star, &velocity);
+            let norm = (moved[0] * moved[0] + moved[1] * moved[1] + moved[2] * moved[2]).sqrt();
+            assert!((norm - 1.0).abs() < 1e-12);
+        }
+    }
+
+    fn degrees(ra: f64, dec: f64) -> (Radians, Radians) {
+        (Radians::from(Degrees(ra)), Radians::from(Degrees(dec)))
+    }
+
+    #[test]
+    fn cone_box_containing_a_pole() {
+        let (ra, dec) = degrees(120.0, 85.0);
+        let cone = ConeBox::new(ra, dec, Radians::from(Degrees(10.0)));
+        // Every R.A. near the pole, across it from the center too
+        for ra in [0.0, 90.0, 180.0, 300.0, 359.9].iter() {
+            let (ra, dec) = degrees(*ra, 89.0);
+            assert!(cone.contains(ra, dec));
+        }
+        let (ra, dec) = degrees(300.0, 80.0);
+        assert!(cone.contains(ra, dec));
+        let (ra, dec) = degrees(120.0, 74.0);
+        assert!(!cone.contains(ra, dec));
+    }
+
+    #[test]
+    fn cone_box_crossing_ra_zero() {
+        let (ra, dec) = degrees(359.0, 0.0);
+        let cone = ConeBox::new(ra, dec, Radians::from(Degrees(3.0)));
+        for ra in [357.0, 359.9, 0.0, 1.9].iter() {
+            let (ra, dec) = degrees(*ra, 1.0);
+            assert!(cone.contains(ra, dec));
+        }
+        for ra in [2.5, 180.0, 355.0].iter() {
+            let (ra, dec) = degrees(*ra, 0.0);
+            assert!(!cone.contains(ra, dec));
+        }
+        // The box holds every star of the cone, away from the equator too
+        let (ra_center, dec_center) = degrees(0.5, 60.0);
+        let radius = Radians::from(Degrees(5.0));
+        let cone = ConeBox::new(ra_center, dec_center, radius);
+        for step in 0..3600 {
+            let (ra, dec) = degrees(step as f64 / 10.0, 55.0 + (step % 100) as f64 / 10.0);
+            if angular_separation_radians(ra_center, dec_center, ra, dec) <= radius {
+                assert!(cone.contains(ra, dec));
+            }
+        }
+    }
 }
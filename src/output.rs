@@ -0,0 +1,183 @@
+use std::{
+    path::{Path, PathBuf},
+    process,
+};
+use log::{debug};
+
+use rastap_core::output::OutputWriter;
+
+use crate::crossid;
+use crate::format::Format;
+use crate::image::{ImageStar, PixelConvention};
+use crate::pairs;
+use crate::polygon::{Star, StarMatch};
+use crate::solution::Solution;
+use crate::region;
+use crate::star_catalog::Catalog;
+use crate::votable;
+
+/// VOTable file writer
+pub struct VotableWriter {
+    pub path: PathBuf,
+    pub pixel_convention: PixelConvention,
+    pub format: Format,
+}
+
+impl OutputWriter for VotableWriter {
+    fn name(&self) -> &str {
+        "VOTable"
+    }
+
+    fn write(&self, image_star_list: &[ImageStar], star_list: &[Star], matches: &[StarMatch], solution: Option<&Solution>) -> Result<(), String> {
+        votable::write_star_matches(&self.path, image_star_list, star_list, matches, solution, &self.pixel_convention, &self.format)
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// DS9 region file writer
+pub struct Ds9Writer {
+    pub path: PathBuf,
+    pub pixel_convention: PixelConvention,
+    pub image_size: Option<(f64, f64)>, // Width and height in pixels, if known
+}
+
+impl OutputWriter for Ds9Writer {
+    fn name(&self) -> &str {
+        "DS9 regions"
+    }
+
+    fn write(&self, image_star_list: &[ImageStar], star_list: &[Star], matches: &[StarMatch], solution: Option<&Solution>) -> Result<(), String> {
+        region::write_ds9_regions(&self.path, image_star_list, star_list, matches, solution, &self.pixel_convention, self.image_size)
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Star pairs CSV file writer
+pub struct PairsWriter {
+    pub path: PathBuf,
+    pub catalog: Catalog,
+    pub format: Format,
+}
+
+impl OutputWriter for PairsWriter {
+    fn name(&self) -> &str {
+        "star pairs"
+    }
+
+    fn write(&self, image_star_list: &[ImageStar], star_list: &[Star], matches: &[StarMatch], _solution: Option<&Solution>) -> Result<(), String> {
+        // Cross-identify the matched stars when the mapping table is installed
+        let db_ids: Vec<u64> = matches.iter().map(|m| star_list[m.star].db_id).collect();
+        let identifiers = if crossid::installed() {
+            crossid::read_identifiers(Path::new(crossid::DB_PATH), self.catalog, &db_ids)
+        } else {
+            debug!("Pairs > {} not installed, catalog identifiers only", crossid::DB_PATH);
+            Ok(db_ids.iter().map(|&id| (id, crossid::own_identifiers(self.catalog, id))).collect())
+        };
+        identifiers.and_then(|identifiers| pairs::write_star_pairs(&self.path, image_star_list, star_list, matches, &identifiers, &self.format))
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Shell command run after matching. The template placeholders are replaced by their (quoted) values:
+/// {sex_csv}, {matches}, {votable}, {ds9}, {pairs}, {solved} (1 or 0) and, empty without a solution,
+/// {ra} and {dec} (degrees), {scale} (arcsec/pix) and {rotation} (degrees).
+pub struct ExecWriter {
+    pub template: String,
+    pub sex_csv: PathBuf,
+    pub votable: Option<PathBuf>,
+    pub ds9: Option<PathBuf>,
+    pub pairs: Option<PathBuf>,
+}
+
+/// Quotes a value for a POSIX shell
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Quoted path, or an empty string
+fn quoted_path(path: Option<&Path>) -> String {
+    shell_quote(&path.map(|path| path.display().to_string()).unwrap_or_default())
+}
+
+/// Replaces the `{name}` placeholders of a template by their values, in a single pass, so values
+/// are never expanded again. Unknown placeholders are kept as they are.
+fn expand_template<F: Fn(&str) -> Option<String>>(template: &str, value: F) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        expanded.push_str(&rest[..open]);
+        let placeholder = &rest[open..];
+        match placeholder.find('}').and_then(|close| value(&placeholder[1..close]).map(|value| (close, value))) {
+            Some((close, value)) => {
+                expanded.push_str(&value);
+                rest = &placeholder[close + 1..];
+            }
+            None => {
+                expanded.push('{');
+                rest = &placeholder[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+impl ExecWriter {
+    /// Command line of the template for the star matches and the plate solution, if found
+    fn command(&self, matches: &[StarMatch], solution: Option<&Solution>) -> String {
+        let solved = |value: fn(&Solution) -> f64| shell_quote(&solution.map(|solution| value(solution).to_string()).unwrap_or_default());
+        expand_template(&self.template, |name| match name {
+            "sex_csv" => Some(quoted_path(Some(&self.sex_csv))),
+            "matches" => Some(matches.len().to_string()),
+            "votable" => Some(quoted_path(self.votable.as_deref())),
+            "ds9" => Some(quoted_path(self.ds9.as_deref())),
+            "pairs" => Some(quoted_path(self.pairs.as_deref())),
+            "solved" => Some(if solution.is_some() { "1" } else { "0" }.to_owned()),
+            "ra" => Some(solved(|solution| solution.center.ra.0)),
+            "dec" => Some(solved(|solution| solution.center.dec.0)),
+            "scale" => Some(solved(|solution| solution.scale_arcsec_px)),
+            "rotation" => Some(solved(|solution| solution.rotation_deg)),
+            _ => None,
+        })
+    }
+}
+
+impl OutputWriter for ExecWriter {
+    fn name(&self) -> &str {
+        "exec"
+    }
+
+    fn write(&self, _image_star_list: &[ImageStar], _star_list: &[Star], matches: &[StarMatch], solution: Option<&Solution>) -> Result<(), String> {
+        let command = self.command(matches, solution);
+        debug!("Exec > {}", command);
+        let status = process::Command::new("sh").arg("-c").arg(&command).status().map_err(|err| err.to_string())?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("'{}' failed: {}", command, status))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exec_writer(template: &str, sex_csv: &str) -> ExecWriter {
+        ExecWriter { template: template.to_owned(), sex_csv: PathBuf::from(sex_csv), votable: Some(PathBuf::from("out.xml")), ds9: None, pairs: None }
+    }
+
+    #[test]
+    fn expands_placeholders_once() {
+        // A path holding a placeholder name is not expanded again
+        let writer = exec_writer("notify {sex_csv} {votable} {ds9} {unknown} {matches}", "/data/{votable}.csv");
+        let matches = vec![StarMatch { image_star: 0, star: 0, votes: 3 }];
+        assert_eq!(writer.command(&matches, None), "notify '/data/{votable}.csv' 'out.xml' '' {unknown} 1");
+    }
+
+    #[test]
+    fn leaves_the_solution_empty_when_unsolved() {
+        let writer = exec_writer("goto {solved} {ra} {dec} {scale} {rotation} {", "stars.csv");
+        assert_eq!(writer.command(&[], None), "goto 0 '' '' '' '' {");
+    }
+}
@@ -0,0 +1,43 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::Path,
+};
+
+use rastap_core::angle::Degrees;
+
+use crate::crossid::Identifiers;
+use crate::format::Format;
+use crate::image::ImageStar;
+use crate::polygon::{Star, StarMatch};
+
+/// Writes the matched image/catalog star pairs as CSV, one pair per row: pixel position (FITS
+/// convention) and instrumental magnitude, catalog id, position and magnitude, supporting votes
+/// and the identifiers of the star in each catalog, by catalog id (empty when unknown)
+pub fn write_star_pairs(path: &Path, image_star_list: &[ImageStar], star_list: &[Star], matches: &[StarMatch], identifiers: &HashMap<u64, Identifiers>, format: &Format) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+    let ra_header = format!("ra_{}", format.ra_unit_symbol());
+    writer.write_record(["x", "y", "mag_inst", "catalog_id", &ra_header, "dec_deg", "mag", "votes", "gaia_source_id", "hip", "tyc"])?;
+    let unknown = Identifiers::default();
+    let optional = |id: Option<u64>| id.map(|id| id.to_string()).unwrap_or_default();
+    for m in matches {
+        let image_star = &image_star_list[m.image_star];
+        let star = &star_list[m.star];
+        let ids = identifiers.get(&star.db_id).unwrap_or(&unknown);
+        writer.write_record(&[
+            image_star.pixel_x.to_string(),
+            image_star.pixel_y.to_string(),
+            image_star.magnitude.to_string(),
+            star.db_id.to_string(),
+            format.ra(Degrees::from(star.ra()).0),
+            format.dec(star.dec().0),
+            star.magnitude.to_string(),
+            m.votes.to_string(),
+            optional(ids.gaia),
+            optional(ids.hip),
+            ids.tyc.clone().unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use std::{
+    fs,
+    io,
+    path::Path,
+};
+
+use crate::fov;
+
+/// Default equipment profile file
+pub const PROFILE_PATH: &str = "rastap.conf";
+
+/// Camera and telescope profile
+pub struct Profile {
+    pub name: String,
+    pub focal_length_mm: Option<f64>,
+    pub pixel_size_um: Option<f64>,
+    pub binning: f64,
+    pub rotation_deg: Option<f64>,
+    pub flip_y: bool,
+    pub image_height: Option<f64>,
+    pub image_width: Option<f64>,
+}
+
+impl Profile {
+    /// Image scale in arcseconds per pixel
+    pub fn scale(&self) -> Option<f64> {
+        Some(fov::image_scale(self.pixel_size_um? * self.binning, self.focal_length_mm?))
+    }
+}
+
+/// Invalid profile file error
+fn invalid(path: &Path, line: usize, message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), line, message))
+}
+
+/// Reads a profile from an INI-like file: `[name]` sections of `key = value` lines, `#` comments
+pub fn read_profile(path: &Path, name: &str) -> io::Result<Profile> {
+    let text = fs::read_to_string(path)?;
+    let mut profile: Option<Profile> = None;
+    let mut section = String::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            section = line[1..line.len() - 1].trim().to_owned();
+            if section == name {
+                profile = Some(Profile {
+                    name: section.clone(),
+                    focal_length_mm: None,
+                    pixel_size_um: None,
+                    binning: 1.0,
+                    rotation_deg: None,
+                    flip_y: false,
+                    image_height: None,
+                    image_width: None,
+                });
+            }
+            continue;
+        }
+        if section != name {
+            continue;
+        }
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next().unwrap_or("").trim();
+        let value = parts.next().ok_or_else(|| invalid(path, n + 1, format!("Expected key = value, found '{}'", line)))?.trim();
+        let number = || value.parse::<f64>().map_err(|_| invalid(path, n + 1, format!("Invalid number '{}' for {}", value, key)));
+        let current = profile.as_mut().unwrap();
+        match key {
+            "focal_length" => current.focal_length_mm = Some(number()?),
+            "pixel_size" => current.pixel_size_um = Some(number()?),
+            "binning" => current.binning = number()?,
+            "rotation" => current.rotation_deg = Some(number()?),
+            "image_height" => current.image_height = Some(number()?),
+            "image_width" => current.image_width = Some(number()?),
+            "flip_y" => current.flip_y = value.parse::<bool>().map_err(|_| invalid(path, n + 1, format!("Invalid boolean '{}' for flip_y", value)))?,
+            _ => return Err(invalid(path, n + 1, format!("Unknown key '{}'", key))),
+        }
+    }
+    profile.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Profile '{}' not found in {}", name, path.display())))
+}
@@ -0,0 +1,70 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use rastap_core::angle::Degrees;
+
+use crate::image::{ImageStar, PixelConvention};
+use crate::polygon::{Star, StarMatch};
+use crate::solution::Solution;
+
+/// Writes a DS9 region file (image coordinates): detections in green, matched catalog stars in red and,
+/// with a solution, the field center and corners in cyan. The field is the image frame when its size
+/// (width, height) is known, or the extent of the detections otherwise. Catalog stars are placed by the
+/// solution, or at the matched detection without one.
+pub fn write_ds9_regions(path: &Path, image_star_list: &[ImageStar], star_list: &[Star], matches: &[StarMatch], solution: Option<&Solution>, pixel_convention: &PixelConvention, image_size: Option<(f64, f64)>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "# Region file format: DS9 version 4.1")?;
+    writeln!(writer, "# Pixel convention: {}", pixel_convention)?;
+    writeln!(writer, "global color=green width=1 font=\"helvetica 10 normal roman\"")?;
+    writeln!(writer, "image")?;
+    // Detections
+    for star in image_star_list {
+        writeln!(writer, "circle({},{},6) # color=green", star.pixel_x, star.pixel_y)?;
+    }
+    // Catalog stars, projected by the solution
+    for m in matches {
+        let star = &star_list[m.star];
+        let position = match solution {
+            // The solution was fit to the positions used for matching
+            Some(solution) => solution.sky_to_pixel(Degrees::from(star.ra_rad), Degrees::from(star.dec_rad)),
+            None => Some((image_star_list[m.image_star].pixel_x, image_star_list[m.image_star].pixel_y)),
+        };
+        if let Some((x, y)) = position {
+            writeln!(writer, "circle({},{},10) # color=red text={{{}}}", x, y, star.db_id)?;
+        }
+    }
+    // Field center and corners: the image frame, or the box of the detections
+    let field = match image_size {
+        Some(size) => Some((frame_box(size), "field")),
+        None => detection_box(image_star_list).map(|detections| (detections, "detection extent")),
+    };
+    if let (Some(solution), Some(((x_min, y_min, x_max, y_max), label))) = (solution, field) {
+        let (x_center, y_center) = ((x_min + x_max) / 2.0, (y_min + y_max) / 2.0);
+        writeln!(writer, "box({},{},{},{},0) # color=cyan dash=1 text={{{}}}", x_center, y_center, x_max - x_min, y_max - y_min, label)?;
+        let center = solution.pixel_to_sky(x_center, y_center);
+        writeln!(writer, "point({},{}) # point=cross color=cyan text={{center {:.5} {:.5}}}", x_center, y_center, center.ra.0, center.dec.0)?;
+        for (x, y) in [(x_min, y_min), (x_max, y_min), (x_max, y_max), (x_min, y_max)] {
+            let corner = solution.pixel_to_sky(x, y);
+            writeln!(writer, "point({},{}) # point=diamond color=cyan text={{{:.5} {:.5}}}", x, y, corner.ra.0, corner.dec.0)?;
+        }
+    }
+    writer.flush()
+}
+
+/// Box of the image frame, in FITS pixels: the pixel edges from 0.5 to the size + 0.5
+fn frame_box((width, height): (f64, f64)) -> (f64, f64, f64, f64) {
+    (0.5, 0.5, width + 0.5, height + 0.5)
+}
+
+/// Bounding box of the detections: x and y minimum, x and y maximum
+fn detection_box(image_star_list: &[ImageStar]) -> Option<(f64, f64, f64, f64)> {
+    if image_star_list.is_empty() {
+        return None;
+    }
+    Some(image_star_list.iter().fold((f64::MAX, f64::MAX, f64::MIN, f64::MIN), |(x_min, y_min, x_max, y_max), star| {
+        (x_min.min(star.pixel_x), y_min.min(star.pixel_y), x_max.max(star.pixel_x), y_max.max(star.pixel_y))
+    }))
+}
@@ -0,0 +1,46 @@
+use crate::diagnostics;
+use crate::image::ImageStar;
+use crate::math;
+use crate::polygon::Star;
+
+/// Median nearest-neighbour separation of the catalog stars in arcseconds
+pub fn catalog_neighbour_separation(star_list: &[Star]) -> Option<f64> {
+    let mut separations = Vec::new();
+    for (i, a) in star_list.iter().enumerate() {
+        let nearest = star_list.iter().enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, b)| math::angular_separation_radians(a.ra_rad, a.dec_rad, b.ra_rad, b.dec_rad).0)
+            .fold(f64::MAX, f64::min);
+        if nearest < f64::MAX {
+            separations.push(nearest.to_degrees() * 3600.0);
+        }
+    }
+    diagnostics::median(&separations)
+}
+
+/// Median nearest-neighbour separation of the image stars in pixels
+pub fn image_neighbour_separation(image_star_list: &[ImageStar]) -> Option<f64> {
+    let mut separations = Vec::new();
+    for (i, a) in image_star_list.iter().enumerate() {
+        let nearest = image_star_list.iter().enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, b)| (b.pixel_x - a.pixel_x).hypot(b.pixel_y - a.pixel_y))
+            .fold(f64::MAX, f64::min);
+        if nearest < f64::MAX {
+            separations.push(nearest);
+        }
+    }
+    diagnostics::median(&separations)
+}
+
+/// Rough image scale (arcseconds per pixel) from the nearest-neighbour separations.
+/// Assumes the catalog and the detections reach a similar depth, so both have the same star density.
+pub fn estimate_from_neighbours(star_list: &[Star], image_star_list: &[ImageStar]) -> Option<f64> {
+    let catalog = catalog_neighbour_separation(star_list)?;
+    let image = image_neighbour_separation(image_star_list)?;
+    if catalog > 0.0 && image > 0.0 {
+        Some(catalog / image)
+    } else {
+        None
+    }
+}
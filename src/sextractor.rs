@@ -1,12 +1,14 @@
 use std::{
     error::Error,
+    fmt,
     path::Path
 };
 
 use csv;
 use log::{debug};
 
-use crate::image::{ImageStar};
+use crate::diagnostics;
+use crate::image::{ImageStar, Trail};
 
 /// Reads a sextractor result file, converted to CSV
 pub fn read_image_stars_from_file(path: &Path) -> Result<Vec<ImageStar>, Box<dyn Error>> {
@@ -34,3 +36,204 @@ pub fn read_image_stars_from_file(path: &Path) -> Result<Vec<ImageStar>, Box<dyn
     star_list.sort_by(|a, b| a.magnitude.partial_cmp(&b.magnitude).unwrap());
     Ok(star_list)
 }
+
+/// Reads the detection trails from the A_IMAGE, B_IMAGE and THETA_IMAGE columns of a sextractor CSV file
+pub fn read_trails_from_file(path: &Path) -> Result<Vec<Trail>, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header.trim() == name)
+        .ok_or_else(|| format!("Column {} not found in {}, trails need A_IMAGE, B_IMAGE and THETA_IMAGE", name, path.display()));
+    let (a, b, theta) = (column("A_IMAGE")?, column("B_IMAGE")?, column("THETA_IMAGE")?);
+    let mut trails = Vec::new();
+    for row in reader.records() {
+        let record = row?;
+        let value = |i: usize| -> Result<f64, Box<dyn Error>> {
+            Ok(record.get(i).ok_or("Missing column")?.trim().parse::<f64>()?)
+        };
+        trails.push(Trail::from_moments(value(a)?, value(b)?, value(theta)?));
+    }
+    Ok(trails)
+}
+
+/// Estimates the centroid error in pixels, the median of FWHM / (2.355 SNR), from the FWHM_IMAGE and
+/// MAGERR_AUTO columns of a sextractor CSV file. None if the file lacks these columns.
+pub fn read_centroid_error(path: &Path) -> Result<Option<f64>, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header.trim() == name);
+    let (fwhm, magerr) = match (column("FWHM_IMAGE"), column("MAGERR_AUTO")) {
+        (Some(fwhm), Some(magerr)) => (fwhm, magerr),
+        _ => return Ok(None),
+    };
+    let mut errors = Vec::new();
+    for row in reader.records() {
+        let record = row?;
+        let value = |i: usize| -> Result<f64, Box<dyn Error>> {
+            Ok(record.get(i).ok_or("Missing column")?.trim().parse::<f64>()?)
+        };
+        let (fwhm, magerr) = (value(fwhm)?, value(magerr)?);
+        if fwhm > 0.0 && magerr > 0.0 {
+            // SNR from the magnitude error: 2.5 / ln(10) / MAGERR
+            let snr = 1.0857 / magerr;
+            errors.push(fwhm / (2.355 * snr));
+        }
+    }
+    debug!("Centroid errors: {:?}", errors);
+    Ok(diagnostics::median(&errors))
+}
+
+/// SExtractor FLAGS that reject a detection: saturated (4), truncated (8), corrupted aperture or
+/// isophotal data (16, 32), and memory overflows (64, 128)
+pub const REJECT_FLAGS: u32 = 4 | 8 | 16 | 32 | 64 | 128;
+
+/// Shape measurements of a detection, from the optional sextractor columns
+pub struct SourceShape {
+    pub pixel_x: f64,
+    pub pixel_y: f64,
+    pub class_star: Option<f64>, // CLASS_STAR: 1 star, 0 galaxy
+    pub fwhm: Option<f64>,       // FWHM_IMAGE, pixels
+    pub flags: Option<u32>,      // FLAGS
+    pub elongation: Option<f64>, // ELONGATION, or A_IMAGE / B_IMAGE
+    pub peak_ratio: Option<f64>, // FLUX_MAX / FLUX_AUTO
+}
+
+/// Thresholds of a star-like detection
+#[derive(Clone, Debug)]
+pub struct StellarFilter {
+    pub min_class_star: f64,  // Minimum CLASS_STAR
+    pub max_elongation: f64,  // Maximum ELONGATION
+    pub shape_tolerance: f64, // Maximum relative deviation of the FWHM and peak/flux ratio from the frame medians
+    pub reject_flags: u32,    // FLAGS bits rejecting a detection
+}
+
+/// Reason a detection is not star-like
+#[derive(Debug)]
+pub enum NonStellar {
+    Flags(u32),
+    Galaxy(f64),     // CLASS_STAR
+    Elongated(f64),  // Elongation
+    Fwhm(f64),       // FWHM, relative to the frame median
+    PeakRatio(f64),  // Peak/flux ratio, relative to the frame median
+}
+
+impl fmt::Display for NonStellar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NonStellar::Flags(flags) => write!(f, "flags {}", flags),
+            NonStellar::Galaxy(class_star) => write!(f, "class star {:.2}", class_star),
+            NonStellar::Elongated(elongation) => write!(f, "elongation {:.2}", elongation),
+            NonStellar::Fwhm(ratio) => write!(f, "FWHM {:.2} times the median", ratio),
+            NonStellar::PeakRatio(ratio) => write!(f, "peak/flux {:.2} times the median", ratio),
+        }
+    }
+}
+
+/// Reads the shape columns of a sextractor CSV file: CLASS_STAR, FWHM_IMAGE, FLAGS, ELONGATION (or
+/// A_IMAGE and B_IMAGE) and FLUX_MAX with FLUX_AUTO. Each is optional. None if the file has none of them.
+pub fn read_source_shapes(path: &Path) -> Result<Option<Vec<SourceShape>>, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header.trim() == name);
+    let (class_star, fwhm, flags) = (column("CLASS_STAR"), column("FWHM_IMAGE"), column("FLAGS"));
+    let (elongation, a, b) = (column("ELONGATION"), column("A_IMAGE"), column("B_IMAGE"));
+    let (flux_max, flux_auto) = (column("FLUX_MAX"), column("FLUX_AUTO"));
+    let axes = a.zip(b);
+    let fluxes = flux_max.zip(flux_auto);
+    if class_star.is_none() && fwhm.is_none() && flags.is_none() && elongation.is_none() && axes.is_none() && fluxes.is_none() {
+        return Ok(None);
+    }
+    let mut shapes = Vec::new();
+    for row in reader.records() {
+        let record = row?;
+        let value = |i: usize| -> Result<f64, Box<dyn Error>> {
+            Ok(record.get(i).ok_or("Missing column")?.trim().parse::<f64>()?)
+        };
+        let optional = |i: Option<usize>| i.map(value).transpose();
+        let elongation = match (optional(elongation)?, axes) {
+            (Some(elongation), _) => Some(elongation),
+            (None, Some((a, b))) => Some(value(a)? / value(b)?),
+            (None, None) => None,
+        };
+        let peak_ratio = match fluxes {
+            Some((flux_max, flux_auto)) => Some(value(flux_max)? / value(flux_auto)?),
+            None => None,
+        };
+        shapes.push(SourceShape {
+            pixel_x: value(0)?,
+            pixel_y: value(1)?,
+            class_star: optional(class_star)?,
+            fwhm: optional(fwhm)?,
+            flags: match flags {
+                Some(i) => Some(record.get(i).ok_or("Missing column")?.trim().parse::<u32>()?),
+                None => None,
+            },
+            elongation,
+            peak_ratio,
+        });
+    }
+    Ok(Some(shapes))
+}
+
+/// Classifies the detections: the reason each one is not star-like, or None. The FWHM and the peak/flux
+/// ratio are compared with their frame medians, which stars dominate.
+pub fn classify_sources(shapes: &[SourceShape], filter: &StellarFilter) -> Vec<Option<NonStellar>> {
+    let median_of = |values: Vec<f64>| diagnostics::median(&values.into_iter().filter(|v| v.is_finite() && *v > 0.0).collect::<Vec<f64>>());
+    let median_fwhm = median_of(shapes.iter().filter_map(|shape| shape.fwhm).collect());
+    let median_peak = median_of(shapes.iter().filter_map(|shape| shape.peak_ratio).collect());
+    let deviates = |ratio: f64| !((1.0 - filter.shape_tolerance)..=(1.0 + filter.shape_tolerance)).contains(&ratio);
+    shapes
+        .iter()
+        .map(|shape| {
+            if let Some(flags) = shape.flags.filter(|flags| flags & filter.reject_flags != 0) {
+                return Some(NonStellar::Flags(flags));
+            }
+            if let Some(class_star) = shape.class_star.filter(|&class_star| class_star < filter.min_class_star) {
+                return Some(NonStellar::Galaxy(class_star));
+            }
+            if let Some(elongation) = shape.elongation.filter(|&elongation| elongation > filter.max_elongation) {
+                return Some(NonStellar::Elongated(elongation));
+            }
+            if let Some(ratio) = shape.fwhm.zip(median_fwhm).map(|(fwhm, median)| fwhm / median).filter(|&ratio| deviates(ratio)) {
+                return Some(NonStellar::Fwhm(ratio));
+            }
+            if let Some(ratio) = shape.peak_ratio.zip(median_peak).map(|(peak, median)| peak / median).filter(|&ratio| deviates(ratio)) {
+                return Some(NonStellar::PeakRatio(ratio));
+            }
+            None
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(class_star: f64, fwhm: f64, flags: u32, elongation: f64) -> SourceShape {
+        SourceShape { pixel_x: 1.0, pixel_y: 1.0, class_star: Some(class_star), fwhm: Some(fwhm), flags: Some(flags), elongation: Some(elongation), peak_ratio: None }
+    }
+
+    #[test]
+    fn classifies_non_stellar_sources() {
+        let filter = StellarFilter { min_class_star: 0.5, max_elongation: 2.0, shape_tolerance: 0.5, reject_flags: REJECT_FLAGS };
+        let mut shapes: Vec<SourceShape> = (0..6).map(|_| shape(0.98, 3.0, 0, 1.1)).collect();
+        shapes.push(shape(0.98, 3.0, 4, 1.1)); // Saturated
+        shapes.push(shape(0.98, 3.0, 2, 1.1)); // Blended: kept
+        shapes.push(shape(0.05, 3.0, 0, 1.1));
+        shapes.push(shape(0.98, 3.0, 0, 3.5));
+        shapes.push(shape(0.98, 9.0, 0, 1.1));
+        let classes = classify_sources(&shapes, &filter);
+        assert!(classes[..6].iter().all(|class| class.is_none()));
+        assert!(matches!(classes[6], Some(NonStellar::Flags(4))));
+        assert!(classes[7].is_none());
+        assert!(matches!(classes[8], Some(NonStellar::Galaxy(_))));
+        assert!(matches!(classes[9], Some(NonStellar::Elongated(_))));
+        assert!(matches!(classes[10], Some(NonStellar::Fwhm(ratio)) if ratio == 3.0));
+    }
+
+    #[test]
+    fn ignores_missing_columns() {
+        let filter = StellarFilter { min_class_star: 0.5, max_elongation: 2.0, shape_tolerance: 0.5, reject_flags: REJECT_FLAGS };
+        let shapes = vec![SourceShape { pixel_x: 1.0, pixel_y: 1.0, class_star: None, fwhm: None, flags: None, elongation: None, peak_ratio: None }];
+        assert!(classify_sources(&shapes, &filter)[0].is_none());
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    error::Error,
+    fmt,
+    path::Path,
+    str::FromStr,
+};
+
+use rastap_core::angle::{Degrees, Hours};
+
+use crate::gaia_db;
+use crate::hyg;
+use crate::polygon;
+
+/// Star catalog names, as accepted by the command line
+pub const CATALOG_NAMES: &[&str] = &["auto", "hyg", "gaia"];
+
+/// Fields wider than this (degrees) are solved with the bright star catalog
+pub const WIDE_FIELD_DEG: f64 = 20.0;
+
+/// Installed star catalog
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Catalog {
+    Hyg,  // HYG bright stars
+    Gaia, // Mini Gaia DR2
+}
+
+/// Catalog choice of the command line
+#[derive(Clone, Copy, Debug)]
+pub enum CatalogChoice {
+    Auto,
+    Fixed(Catalog),
+}
+
+impl FromStr for CatalogChoice {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<CatalogChoice, String> {
+        match name {
+            "auto" => Ok(CatalogChoice::Auto),
+            "hyg" => Ok(CatalogChoice::Fixed(Catalog::Hyg)),
+            "gaia" => Ok(CatalogChoice::Fixed(Catalog::Gaia)),
+            _ => Err(format!("Unknown catalog '{}', expected auto, hyg or gaia", name)),
+        }
+    }
+}
+
+impl fmt::Display for Catalog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Catalog::Hyg => write!(f, "HYG"),
+            Catalog::Gaia => write!(f, "Mini Gaia DR2"),
+        }
+    }
+}
+
+impl Catalog {
+    /// Database file
+    pub fn path(&self) -> &'static str {
+        match self {
+            Catalog::Hyg => hyg::DB_PATH,
+            Catalog::Gaia => gaia_db::DB_PATH,
+        }
+    }
+
+    /// Whether the database file is installed
+    pub fn installed(&self) -> bool {
+        Path::new(self.path()).exists()
+    }
+
+    /// Reads the stars of a search cone, sorted by magnitude, keeping at most `max_stars` of the brightest
+    pub fn read_stars(&self, ra_center: Hours, dec_center: Degrees, radii: Degrees, magnitude_limit: f64, max_stars: Option<usize>) -> Result<Vec<polygon::Star>, Box<dyn Error>> {
+        match self {
+            Catalog::Hyg => hyg::read_stars_from_file(ra_center, dec_center, radii, magnitude_limit, max_stars),
+            Catalog::Gaia => gaia_db::read_stars_from_file(ra_center, dec_center, radii, magnitude_limit, max_stars),
+        }
+    }
+}
+
+/// Picks the catalog for a field of view (degrees): the bright star catalog for wide fields, the deep one
+/// otherwise. Falls back to the other catalog when the preferred one is not installed.
+pub fn select(choice: CatalogChoice, field_deg: f64) -> Catalog {
+    match choice {
+        CatalogChoice::Fixed(catalog) => catalog,
+        CatalogChoice::Auto => {
+            let (preferred, other) = if field_deg > WIDE_FIELD_DEG {
+                (Catalog::Hyg, Catalog::Gaia)
+            } else {
+                (Catalog::Gaia, Catalog::Hyg)
+            };
+            if !preferred.installed() && other.installed() {
+                other
+            } else {
+                preferred
+            }
+        }
+    }
+}
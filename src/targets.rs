@@ -0,0 +1,55 @@
+use std::{
+    error::Error,
+    path::Path,
+};
+use log::{debug};
+
+use crate::format::RaUnit;
+use crate::wcs::Wcs;
+
+/// Program object of an observing list
+pub struct Target {
+    pub name: String,
+    pub ra: f64,  // Degrees
+    pub dec: f64, // Degrees
+}
+
+/// Position of a target on a frame
+pub struct Placement {
+    pub x: f64,             // FITS pixels (1-based)
+    pub y: f64,
+    pub edge_distance: f64, // Pixels to the closest frame edge
+}
+
+/// Reads a target list CSV file with name, ra and dec columns (found by header, else the first three)
+pub fn read_targets(path: &Path, ra_unit: RaUnit) -> Result<Vec<Target>, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    debug!("Read targets > Headers > {:?}", headers);
+    let column = |name: &str, default: usize| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name)).unwrap_or(default);
+    let (name, ra, dec) = (column("name", 0), column("ra", 1), column("dec", 2));
+    let mut targets = Vec::new();
+    for (n, row) in reader.records().enumerate() {
+        let record = row?;
+        let field = |i: usize| record.get(i).map(|value| value.trim()).ok_or_else(|| format!("{}: row {} has too few columns", path.display(), n + 1));
+        let number = |i: usize| -> Result<f64, Box<dyn Error>> {
+            let value = field(i)?;
+            Ok(value.parse::<f64>().map_err(|_| format!("{}: row {}: invalid number '{}'", path.display(), n + 1, value))?)
+        };
+        let ra_hours = ra_unit.to_hours(number(ra)?).map_err(|err| format!("{}: row {}: {}", path.display(), n + 1, err))?;
+        targets.push(Target { name: field(name)?.to_owned(), ra: ra_hours * 15.0, dec: number(dec)? });
+    }
+    Ok(targets)
+}
+
+/// Position of a target on a frame. None if it falls outside, or the image size is unknown.
+pub fn place(target: &Target, wcs: &Wcs) -> Option<Placement> {
+    let [width, height] = wcs.image_size?;
+    let (x, y) = wcs.sky_to_pixel(target.ra, target.dec)?;
+    // Pixel centers run from 1 to the size, their edges from 0.5 to size + 0.5
+    let edge_distance = (x - 0.5).min(width + 0.5 - x).min(y - 0.5).min(height + 0.5 - y);
+    if edge_distance < 0.0 {
+        return None;
+    }
+    Some(Placement { x, y, edge_distance })
+}
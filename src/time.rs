@@ -0,0 +1,98 @@
+/// Julian Day of a calendar date (Source: Astronomical Algorithms, Meeus, chapter 7)
+pub fn julian_day(year: i32, month: u32, day: f64) -> f64 {
+    let (y, m) = if month <= 2 {
+        (year as f64 - 1.0, month as f64 + 12.0)
+    } else {
+        (year as f64, month as f64)
+    };
+    // Gregorian calendar correction
+    let a = (y / 100.0).floor();
+    let b = 2.0 - a + (a / 4.0).floor();
+    (365.25 * (y + 4716.0)).floor() + (30.6001 * (m + 1.0)).floor() + day + b - 1524.5
+}
+
+/// Days in a month of the Gregorian calendar
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parses a FITS DATE-OBS timestamp (yyyy-mm-dd[Thh:mm:ss[.sss]], UTC) into a Julian Day
+pub fn julian_day_from_iso(date: &str) -> Result<f64, String> {
+    let error = || format!("Invalid date '{}', expected yyyy-mm-ddThh:mm:ss", date);
+    let mut parts = date.trim().splitn(2, 'T');
+    let date_part = parts.next().ok_or_else(error)?;
+    let time_part = parts.next().unwrap_or("00:00:00");
+
+    let ymd: Vec<&str> = date_part.split('-').collect();
+    if ymd.len() != 3 {
+        return Err(error());
+    }
+    let year = ymd[0].parse::<i32>().map_err(|_| error())?;
+    let month = ymd[1].parse::<u32>().map_err(|_| error())?;
+    let day = ymd[2].parse::<u32>().map_err(|_| error())?;
+
+    let hms: Vec<&str> = time_part.trim_end_matches('Z').split(':').collect();
+    if hms.is_empty() || hms.len() > 3 {
+        return Err(error());
+    }
+    let mut seconds_of_day = 0.0;
+    for (i, field) in hms.iter().enumerate() {
+        let value = field.parse::<f64>().map_err(|_| error())?;
+        // Hours, minutes and seconds (a leap second included)
+        if !(0.0..[24.0, 60.0, 61.0][i]).contains(&value) {
+            return Err(error());
+        }
+        seconds_of_day += value * [3600.0, 60.0, 1.0][i];
+    }
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) || !(0.0..=86400.0).contains(&seconds_of_day) {
+        return Err(error());
+    }
+    Ok(julian_day(year, month, day as f64 + seconds_of_day / 86400.0))
+}
+
+/// Modified Julian Day of a Julian Day
+pub fn modified_julian_day(jd: f64) -> f64 {
+    jd - 2400000.5
+}
+
+/// Julian epoch (year) of a Julian Day
+pub fn julian_epoch(jd: f64) -> f64 {
+    2000.0 + (jd - 2451545.0) / 365.25
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn julian_day_of_meeus_example() {
+        // Astronomical Algorithms, example 7.a: 1957 October 4.81
+        assert!((julian_day(1957, 10, 4.81) - 2436116.31).abs() < 1e-6);
+        // J2000.0
+        assert!((julian_day(2000, 1, 1.5) - 2451545.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parses_iso_dates() {
+        let jd = julian_day_from_iso("1957-10-04T19:26:24").unwrap();
+        assert!((jd - 2436116.31).abs() < 1e-6);
+        assert_eq!(julian_day_from_iso("2000-01-01T12:00:00Z").unwrap(), 2451545.0);
+        assert_eq!(julian_day_from_iso("2000-01-01").unwrap(), 2451544.5);
+        assert!(julian_day_from_iso("2024-02-29").is_ok());
+        for date in ["2023-02-29", "2023-02-31", "2023-04-31", "2023-13-01", "2023-01-01T01:-30:00", "2023-01-01T-1:00:00", "2023-01-01T00:00:-5", "2023-01-01T00:60:00", "2023-01-01TNaN:00:00"].iter() {
+            assert!(julian_day_from_iso(date).is_err(), "{}", date);
+        }
+    }
+
+    #[test]
+    fn converts_julian_days() {
+        assert_eq!(modified_julian_day(2451545.0), 51544.5);
+        assert_eq!(julian_epoch(2451545.0), 2000.0);
+        assert!((julian_epoch(2451545.0 + 365.25 * 23.5) - 2023.5).abs() < 1e-12);
+    }
+}
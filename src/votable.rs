@@ -0,0 +1,62 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use rastap_core::angle::Degrees;
+
+use crate::format::Format;
+use crate::image::{ImageStar, PixelConvention};
+use crate::polygon::{Star, StarMatch};
+use crate::solution::Solution;
+
+/// Writes the image/catalog star cross-match as a VOTable (TABLEDATA)
+pub fn write_star_matches(path: &Path, image_star_list: &[ImageStar], star_list: &[Star], matches: &[StarMatch], solution: Option<&Solution>, pixel_convention: &PixelConvention, format: &Format) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<VOTABLE version=\"1.4\" xmlns=\"http://www.ivoa.net/xml/VOTable/v1.3\">")?;
+    writeln!(writer, "  <RESOURCE name=\"rastap\">")?;
+    writeln!(writer, "    <COOSYS ID=\"system\" system=\"ICRS\"/>")?;
+    writeln!(writer, "    <INFO name=\"pixel_convention\" value=\"{}\"/>", pixel_convention)?;
+    if let Some(solution) = solution {
+        writeln!(writer, "    <INFO name=\"solution_ra\" value=\"{}\" unit=\"{}\"/>", format.ra(solution.center.ra.0), format.ra_unit_symbol())?;
+        writeln!(writer, "    <INFO name=\"solution_dec\" value=\"{}\" unit=\"deg\"/>", format.dec(solution.center.dec.0))?;
+        writeln!(writer, "    <INFO name=\"solution_pixel\" value=\"{} {}\"/>", solution.reference_pixel[0], solution.reference_pixel[1])?;
+        writeln!(writer, "    <INFO name=\"solution_scale\" value=\"{}\" unit=\"arcsec/pix\"/>", solution.scale_arcsec_px)?;
+        writeln!(writer, "    <INFO name=\"solution_rotation\" value=\"{}\" unit=\"deg\"/>", solution.rotation_deg)?;
+        writeln!(writer, "    <INFO name=\"solution_parity\" value=\"{:?}\"/>", solution.parity)?;
+        writeln!(writer, "    <INFO name=\"solution_rms\" value=\"{}\" unit=\"arcsec\"/>", solution.stats.rms_arcsec)?;
+    }
+    writeln!(writer, "    <TABLE name=\"matches\">")?;
+    writeln!(writer, "      <DESCRIPTION>Image detections matched to catalog stars by rastap</DESCRIPTION>")?;
+    writeln!(writer, "      <FIELD name=\"x\" datatype=\"double\" unit=\"pix\" ucd=\"pos.cartesian.x;instr.det\"/>")?;
+    writeln!(writer, "      <FIELD name=\"y\" datatype=\"double\" unit=\"pix\" ucd=\"pos.cartesian.y;instr.det\"/>")?;
+    writeln!(writer, "      <FIELD name=\"mag_inst\" datatype=\"double\" unit=\"mag\" ucd=\"phot.mag;instr\"/>")?;
+    writeln!(writer, "      <FIELD name=\"catalog_id\" datatype=\"long\" ucd=\"meta.id;meta.main\"/>")?;
+    writeln!(writer, "      <FIELD name=\"ra\" datatype=\"double\" unit=\"{}\" ucd=\"pos.eq.ra;meta.main\" ref=\"system\"/>", format.ra_unit_symbol())?;
+    writeln!(writer, "      <FIELD name=\"dec\" datatype=\"double\" unit=\"deg\" ucd=\"pos.eq.dec;meta.main\" ref=\"system\"/>")?;
+    writeln!(writer, "      <FIELD name=\"mag\" datatype=\"double\" unit=\"mag\" ucd=\"phot.mag\"/>")?;
+    writeln!(writer, "      <FIELD name=\"votes\" datatype=\"int\" ucd=\"meta.number\"/>")?;
+    writeln!(writer, "      <DATA>")?;
+    writeln!(writer, "        <TABLEDATA>")?;
+    for m in matches {
+        let image_star = &image_star_list[m.image_star];
+        let star = &star_list[m.star];
+        writeln!(writer, "          <TR><TD>{}</TD><TD>{}</TD><TD>{}</TD><TD>{}</TD><TD>{}</TD><TD>{}</TD><TD>{}</TD><TD>{}</TD></TR>",
+            image_star.pixel_x,
+            image_star.pixel_y,
+            image_star.magnitude,
+            star.db_id,
+            format.ra(Degrees::from(star.ra()).0),
+            format.dec(star.dec().0),
+            star.magnitude,
+            m.votes)?;
+    }
+    writeln!(writer, "        </TABLEDATA>")?;
+    writeln!(writer, "      </DATA>")?;
+    writeln!(writer, "    </TABLE>")?;
+    writeln!(writer, "  </RESOURCE>")?;
+    writeln!(writer, "</VOTABLE>")?;
+    writer.flush()
+}
@@ -0,0 +1,390 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    path::Path,
+};
+
+pub use rastap_core::solution::{Sip, SipPolynomial};
+
+/// FITS header block size
+pub const BLOCK_SIZE: usize = 2880;
+
+/// FITS header card size
+pub const CARD_SIZE: usize = 80;
+
+/// Reads the `<prefix>_ORDER` and `<prefix>_p_q` keywords of a SIP polynomial. None if the order is missing.
+fn sip_from_cards(cards: &HashMap<String, String>, prefix: &str) -> Option<SipPolynomial> {
+    let order = cards.get(&format!("{}_ORDER", prefix))?.parse::<usize>().ok()?;
+    let mut coefficients = vec![0.0; (order + 1) * (order + 1)];
+    for p in 0..=order {
+        for q in 0..=(order - p) {
+            if let Some(value) = cards.get(&format!("{}_{}_{}", prefix, p, q)) {
+                coefficients[p * (order + 1) + q] = value.parse::<f64>().ok()?;
+            }
+        }
+    }
+    Some(SipPolynomial { order, coefficients })
+}
+
+/// Header cards of the `<prefix>_ORDER` and non-zero `<prefix>_p_q` keywords of a SIP polynomial
+fn sip_cards(polynomial: &SipPolynomial, prefix: &str) -> Vec<String> {
+    let mut cards = vec![card(&format!("{}_ORDER", prefix), &polynomial.order.to_string(), "SIP polynomial order")];
+    for p in 0..=polynomial.order {
+        for q in 0..=(polynomial.order - p) {
+            let coefficient = polynomial.coefficient(p, q);
+            if coefficient != 0.0 {
+                cards.push(card(&format!("{}_{}_{}", prefix, p, q), &number_value(coefficient), "SIP coefficient"));
+            }
+        }
+    }
+    cards
+}
+
+/// World Coordinate System: gnomonic (TAN) projection with optional SIP distortion
+pub struct Wcs {
+    pub crval: [f64; 2],    // Reference sky position (R.A., Dec), degrees
+    pub crpix: [f64; 2],    // Reference pixel, 1-based
+    pub cd: [[f64; 2]; 2],  // Pixel to intermediate coordinates, degrees per pixel
+    pub sip: Option<Sip>,
+    pub image_size: Option<[f64; 2]>, // Width and height in pixels
+}
+
+/// Invalid WCS header error
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Formats a header card: keyword, value and comment, padded to 80 characters
+pub fn card(keyword: &str, value: &str, comment: &str) -> String {
+    let text = if value.starts_with('\'') {
+        format!("{:<8}= {:<20} / {}", keyword, value, comment)
+    } else {
+        format!("{:<8}= {:>20} / {}", keyword, value, comment)
+    };
+    format!("{:<80}", text.chars().take(CARD_SIZE).collect::<String>())
+}
+
+/// Formats a number for a header card
+fn number_value(value: f64) -> String {
+    format!("{:.12E}", value)
+}
+
+/// Formats a string for a header card
+pub fn string_value(value: &str) -> String {
+    format!("'{:<8}'", value)
+}
+
+/// Splits a FITS header in keyword/value pairs. Accepts 80 character cards, with or without line breaks.
+pub fn header_cards(text: &str) -> HashMap<String, String> {
+    let lines: Vec<String> = if text.contains('\n') {
+        text.lines().map(|line| line.to_owned()).collect()
+    } else {
+        text.chars().collect::<Vec<char>>().chunks(CARD_SIZE).map(|card| card.iter().collect()).collect()
+    };
+    let mut cards = HashMap::new();
+    for line in lines {
+        let keyword = line.chars().take(8).collect::<String>().trim().to_owned();
+        if keyword == "END" {
+            break;
+        }
+        let rest: String = line.chars().skip(8).collect();
+        if !rest.starts_with("= ") {
+            continue;
+        }
+        let value = rest[2..].trim();
+        let value = if let Some(quoted) = value.strip_prefix('\'') {
+            // String value: up to the closing quote
+            quoted.split('\'').next().unwrap_or("").trim().to_owned()
+        } else {
+            // Number or logical: up to the comment
+            value.split('/').next().unwrap_or("").trim().to_owned()
+        };
+        cards.insert(keyword, value);
+    }
+    cards
+}
+
+impl Wcs {
+    /// Parses a FITS header text block (.wcs)
+    pub fn from_header(text: &str) -> io::Result<Wcs> {
+        let cards = header_cards(text);
+        let number = |keyword: &str| -> io::Result<Option<f64>> {
+            match cards.get(keyword) {
+                Some(value) => value.parse::<f64>().map(Some).map_err(|_| invalid(format!("Invalid {} value '{}'", keyword, value))),
+                None => Ok(None),
+            }
+        };
+        let required = |keyword: &str| -> io::Result<f64> {
+            number(keyword)?.ok_or_else(|| invalid(format!("Missing {} keyword", keyword)))
+        };
+
+        // Only the gnomonic projection is supported
+        for (axis, prefix) in [("CTYPE1", "RA---TAN"), ("CTYPE2", "DEC--TAN")].iter() {
+            match cards.get(*axis) {
+                Some(ctype) if ctype.starts_with(prefix) => (),
+                Some(ctype) => return Err(invalid(format!("Unsupported projection {} '{}', expected {}", axis, ctype, prefix))),
+                None => return Err(invalid(format!("Missing {} keyword", axis))),
+            }
+        }
+        let crval = [required("CRVAL1")?, required("CRVAL2")?];
+        let crpix = [required("CRPIX1")?, required("CRPIX2")?];
+
+        // Linear part: CD matrix, or CDELT with PC matrix or CROTA2 rotation
+        let cd = if let Some(cd1_1) = number("CD1_1")? {
+            [[cd1_1, number("CD1_2")?.unwrap_or(0.0)],
+             [number("CD2_1")?.unwrap_or(0.0), number("CD2_2")?.unwrap_or(0.0)]]
+        } else {
+            let cdelt = [required("CDELT1")?, required("CDELT2")?];
+            let pc = match number("PC1_1")? {
+                Some(pc1_1) => [[pc1_1, number("PC1_2")?.unwrap_or(0.0)],
+                                [number("PC2_1")?.unwrap_or(0.0), number("PC2_2")?.unwrap_or(1.0)]],
+                None => {
+                    let rotation = number("CROTA2")?.unwrap_or(0.0).to_radians();
+                    [[rotation.cos(), -rotation.sin()], [rotation.sin(), rotation.cos()]]
+                }
+            };
+            [[cdelt[0] * pc[0][0], cdelt[0] * pc[0][1]],
+             [cdelt[1] * pc[1][0], cdelt[1] * pc[1][1]]]
+        };
+        if cd[0][0] * cd[1][1] - cd[0][1] * cd[1][0] == 0.0 {
+            return Err(invalid("Singular CD matrix".to_owned()));
+        }
+
+        // SIP distortion, when declared in CTYPE
+        let sip = if cards.get("CTYPE1").is_some_and(|ctype| ctype.ends_with("-SIP")) {
+            let a = sip_from_cards(&cards, "A").ok_or_else(|| invalid("Invalid or missing SIP A polynomial".to_owned()))?;
+            let b = sip_from_cards(&cards, "B").ok_or_else(|| invalid("Invalid or missing SIP B polynomial".to_owned()))?;
+            let ap = sip_from_cards(&cards, "AP");
+            let bp = sip_from_cards(&cards, "BP");
+            Some(Sip { a, b, ap, bp })
+        } else {
+            None
+        };
+
+        // Image size: astrometry.net IMAGEW/IMAGEH, or the FITS axes when the header comes from an image
+        let image_size = match (number("IMAGEW")?.or(number("NAXIS1")?), number("IMAGEH")?.or(number("NAXIS2")?)) {
+            (Some(width), Some(height)) => Some([width, height]),
+            _ => None,
+        };
+        Ok(Wcs { crval, crpix, cd, sip, image_size })
+    }
+
+    /// Reads a FITS header file (.wcs)
+    pub fn from_file(path: &Path) -> io::Result<Wcs> {
+        let bytes = fs::read(path)?;
+        Wcs::from_header(&String::from_utf8_lossy(&bytes))
+    }
+
+    /// Serializes to a FITS header text block (.wcs): 80 character cards, with no line breaks, padded to 2880 bytes
+    pub fn to_header(&self) -> String {
+        let (ctype1, ctype2) = if self.sip.is_some() { ("RA---TAN-SIP", "DEC--TAN-SIP") } else { ("RA---TAN", "DEC--TAN") };
+        let mut cards = vec![
+            card("WCSAXES", "2", "Number of WCS axes"),
+            card("CTYPE1", &string_value(ctype1), "Gnomonic projection"),
+            card("CTYPE2", &string_value(ctype2), "Gnomonic projection"),
+            card("RADESYS", &string_value("ICRS"), "Reference frame"),
+            card("CUNIT1", &string_value("deg"), "Axis unit"),
+            card("CUNIT2", &string_value("deg"), "Axis unit"),
+            card("CRVAL1", &number_value(self.crval[0]), "R.A. of the reference pixel (deg)"),
+            card("CRVAL2", &number_value(self.crval[1]), "Dec of the reference pixel (deg)"),
+            card("CRPIX1", &number_value(self.crpix[0]), "X reference pixel"),
+            card("CRPIX2", &number_value(self.crpix[1]), "Y reference pixel"),
+            card("CD1_1", &number_value(self.cd[0][0]), "Transformation matrix"),
+            card("CD1_2", &number_value(self.cd[0][1]), "Transformation matrix"),
+            card("CD2_1", &number_value(self.cd[1][0]), "Transformation matrix"),
+            card("CD2_2", &number_value(self.cd[1][1]), "Transformation matrix"),
+        ];
+        if let Some([width, height]) = self.image_size {
+            cards.push(card("IMAGEW", &width.to_string(), "Image width (pixels)"));
+            cards.push(card("IMAGEH", &height.to_string(), "Image height (pixels)"));
+        }
+        if let Some(sip) = &self.sip {
+            cards.extend(sip_cards(&sip.a, "A"));
+            cards.extend(sip_cards(&sip.b, "B"));
+            if let (Some(ap), Some(bp)) = (&sip.ap, &sip.bp) {
+                cards.extend(sip_cards(ap, "AP"));
+                cards.extend(sip_cards(bp, "BP"));
+            }
+        }
+        cards.push(format!("{:<80}", "END"));
+        let mut header = cards.concat();
+        let padding = (BLOCK_SIZE - header.len() % BLOCK_SIZE) % BLOCK_SIZE;
+        header.push_str(&" ".repeat(padding));
+        header
+    }
+
+    /// Writes a FITS header file (.wcs)
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_header())
+    }
+
+    /// Sky position (degrees) of the image center, if the image size is known
+    pub fn center(&self) -> Option<(f64, f64)> {
+        let [width, height] = self.image_size?;
+        Some(self.pixel_to_sky((width + 1.0) / 2.0, (height + 1.0) / 2.0))
+    }
+
+    /// Sky positions (degrees) of the image corners, if the image size is known
+    pub fn corners(&self) -> Option<Vec<(f64, f64)>> {
+        let [width, height] = self.image_size?;
+        let (x_max, y_max) = (width + 0.5, height + 0.5);
+        Some([(0.5, 0.5), (x_max, 0.5), (x_max, y_max), (0.5, y_max)].iter()
+            .map(|(x, y)| self.pixel_to_sky(*x, *y))
+            .collect())
+    }
+
+    /// Pixel scale in arcseconds per pixel
+    pub fn pixel_scale(&self) -> f64 {
+        (self.cd[0][0] * self.cd[1][1] - self.cd[0][1] * self.cd[1][0]).abs().sqrt() * 3600.0
+    }
+
+    /// Rotation of the Y axis from the North, through the East, in degrees
+    pub fn rotation(&self) -> f64 {
+        self.cd[0][1].atan2(self.cd[1][1]).to_degrees()
+    }
+
+    /// Pixel (1-based) to sky coordinates (R.A., Dec) in degrees
+    pub fn pixel_to_sky(&self, x: f64, y: f64) -> (f64, f64) {
+        let (mut u, mut v) = (x - self.crpix[0], y - self.crpix[1]);
+        if let Some(sip) = &self.sip {
+            let (du, dv) = sip.distort(u, v);
+            u = du;
+            v = dv;
+        }
+        let xi = (self.cd[0][0] * u + self.cd[0][1] * v).to_radians();
+        let eta = (self.cd[1][0] * u + self.cd[1][1] * v).to_radians();
+        // Gnomonic deprojection
+        let (ra0, dec0) = (self.crval[0].to_radians(), self.crval[1].to_radians());
+        let denominator = dec0.cos() - eta * dec0.sin();
+        let ra = ra0 + xi.atan2(denominator);
+        let dec = (dec0.sin() + eta * dec0.cos()).atan2(xi.hypot(denominator));
+        (ra.to_degrees().rem_euclid(360.0), dec.to_degrees())
+    }
+
+    /// Sky coordinates (R.A., Dec) in degrees to pixel (1-based). None if the position is on the far side of the projection.
+    pub fn sky_to_pixel(&self, ra: f64, dec: f64) -> Option<(f64, f64)> {
+        let (ra0, dec0) = (self.crval[0].to_radians(), self.crval[1].to_radians());
+        let (ra, dec) = (ra.to_radians(), dec.to_radians());
+        // Gnomonic projection
+        let cos_c = dec0.sin() * dec.sin() + dec0.cos() * dec.cos() * (ra - ra0).cos();
+        if cos_c <= 0.0 {
+            return None;
+        }
+        let xi = (dec.cos() * (ra - ra0).sin() / cos_c).to_degrees();
+        let eta = ((dec0.cos() * dec.sin() - dec0.sin() * dec.cos() * (ra - ra0).cos()) / cos_c).to_degrees();
+        // Inverse of the CD matrix
+        let determinant = self.cd[0][0] * self.cd[1][1] - self.cd[0][1] * self.cd[1][0];
+        let mut u = (self.cd[1][1] * xi - self.cd[0][1] * eta) / determinant;
+        let mut v = (self.cd[0][0] * eta - self.cd[1][0] * xi) / determinant;
+        if let Some(sip) = &self.sip {
+            let (du, dv) = sip.undistort(u, v);
+            u = du;
+            v = dv;
+        }
+        Some((u + self.crpix[0], v + self.crpix[1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2000 x 1500 pixel plate at about 2"/px, rotated, with second order SIP distortion
+    fn plate() -> Wcs {
+        let scale = 2.0 / 3600.0;
+        let (sin, cos) = 30.0_f64.to_radians().sin_cos();
+        let polynomial = |c20: f64, c02: f64| {
+            let mut coefficients = vec![0.0; 9];
+            coefficients[2 * 3] = c20;
+            coefficients[2] = c02;
+            SipPolynomial { order: 2, coefficients }
+        };
+        Wcs {
+            crval: [83.8, -5.4],
+            crpix: [1000.5, 750.5],
+            cd: [[-scale * cos, scale * sin], [scale * sin, scale * cos]],
+            sip: Some(Sip { a: polynomial(2e-6, -1e-6), b: polynomial(-1.5e-6, 3e-6), ap: None, bp: None }),
+            image_size: Some([2000.0, 1500.0]),
+        }
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let wcs = plate();
+        let header = wcs.to_header();
+        assert_eq!(header.len() % BLOCK_SIZE, 0);
+        assert!(!header.contains('\n'));
+        let read = Wcs::from_header(&header).unwrap();
+        for i in 0..2 {
+            assert!((read.crval[i] - wcs.crval[i]).abs() < 1e-10);
+            assert!((read.crpix[i] - wcs.crpix[i]).abs() < 1e-10);
+            for j in 0..2 {
+                assert!((read.cd[i][j] - wcs.cd[i][j]).abs() < 1e-16);
+            }
+        }
+        assert_eq!(read.image_size, wcs.image_size);
+        let sip = read.sip.as_ref().unwrap();
+        assert_eq!(sip.a.order, 2);
+        assert!((sip.a.coefficient(2, 0) - 2e-6).abs() < 1e-18 && (sip.b.coefficient(0, 2) - 3e-6).abs() < 1e-18);
+        assert!(sip.ap.is_none());
+        // The same pixels map to the same sky positions
+        for &(x, y) in [(1.0, 1.0), (1000.5, 750.5), (2000.0, 1500.0), (250.0, 1200.0)].iter() {
+            let (ra, dec) = wcs.pixel_to_sky(x, y);
+            let (read_ra, read_dec) = read.pixel_to_sky(x, y);
+            assert!((ra - read_ra).abs() < 1e-9 && (dec - read_dec).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn pixel_round_trip() {
+        let wcs = plate();
+        for &(x, y) in [(1.0, 1.0), (1000.5, 750.5), (2000.0, 1500.0), (250.0, 1200.0)].iter() {
+            let (ra, dec) = wcs.pixel_to_sky(x, y);
+            let (x2, y2) = wcs.sky_to_pixel(ra, dec).unwrap();
+            assert!((x - x2).abs() < 1e-6 && (y - y2).abs() < 1e-6);
+        }
+        assert!(wcs.sky_to_pixel(wcs.crval[0] + 180.0, -wcs.crval[1]).is_none());
+    }
+
+    #[test]
+    fn reads_cdelt_and_rotation() {
+        let header = [
+            card("CTYPE1", &string_value("RA---TAN"), ""),
+            card("CTYPE2", &string_value("DEC--TAN"), ""),
+            card("CRVAL1", "10.0", ""),
+            card("CRVAL2", "20.0", ""),
+            card("CRPIX1", "512.0", ""),
+            card("CRPIX2", "512.0", ""),
+            card("CDELT1", "-0.001", ""),
+            card("CDELT2", "0.001", ""),
+            card("CROTA2", "90.0", ""),
+            format!("{:<80}", "END"),
+        ].join("\n");
+        let wcs = Wcs::from_header(&header).unwrap();
+        assert!(wcs.sip.is_none() && wcs.image_size.is_none());
+        assert!((wcs.pixel_scale() - 3.6).abs() < 1e-9);
+        assert!((wcs.cd[0][1] - 0.001).abs() < 1e-12 && (wcs.cd[1][0] - 0.001).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_unsupported_headers() {
+        let header = |ctype1: &str, cd2_2: &str| [
+            card("CTYPE1", &string_value(ctype1), ""),
+            card("CTYPE2", &string_value("DEC--TAN"), ""),
+            card("CRVAL1", "10.0", ""),
+            card("CRVAL2", "20.0", ""),
+            card("CRPIX1", "512.0", ""),
+            card("CRPIX2", "512.0", ""),
+            card("CD1_1", "0.001", ""),
+            card("CD2_2", cd2_2, ""),
+            format!("{:<80}", "END"),
+        ].concat();
+        let error = |ctype1: &str, cd2_2: &str| Wcs::from_header(&header(ctype1, cd2_2)).err().map(|err| err.to_string()).unwrap_or_default();
+        assert!(Wcs::from_header(&header("RA---TAN", "0.001")).is_ok());
+        assert!(error("RA---SIN", "0.001").contains("Unsupported projection"));
+        assert!(error("RA---TAN", "0.0").contains("Singular"));
+        // SIP declared but no polynomials
+        assert!(error("RA---TAN-SIP", "0.001").contains("SIP"));
+    }
+}
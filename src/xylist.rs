@@ -0,0 +1,239 @@
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    error::Error,
+    fs,
+    io,
+    path::Path,
+};
+use log::{debug};
+
+use crate::image::ImageStar;
+use crate::wcs::{self, BLOCK_SIZE, CARD_SIZE};
+
+/// File extensions of the astrometry.net detection lists (image2xy, augment-xylist)
+pub const XYLIST_EXTENSIONS: &[&str] = &["axy", "xyls"];
+
+/// Whether a detection file is an astrometry.net xylist, by its extension
+pub fn is_xylist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| XYLIST_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Malformed file error
+fn invalid(message: String) -> Box<dyn Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// FITS header unit at an offset: its cards and the offset of its data
+fn read_header(bytes: &[u8], offset: usize) -> Result<(HashMap<String, String>, usize), Box<dyn Error>> {
+    let mut end = offset;
+    loop {
+        let card = bytes.get(end..end + CARD_SIZE).ok_or_else(|| invalid("Truncated FITS header, END card not found".to_owned()))?;
+        end += CARD_SIZE;
+        if card.starts_with(b"END") && card[3..8].iter().all(|&c| c == b' ') {
+            break;
+        }
+    }
+    let cards = wcs::header_cards(&String::from_utf8_lossy(&bytes[offset..end]));
+    Ok((cards, padded(end)))
+}
+
+/// Rounds an offset up to a whole FITS block
+fn padded(offset: usize) -> usize {
+    offset.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+/// Integer value of a header keyword
+fn integer(cards: &HashMap<String, String>, keyword: &str) -> Result<i64, Box<dyn Error>> {
+    let value = cards.get(keyword).ok_or_else(|| invalid(format!("Missing {} keyword", keyword)))?;
+    value.parse::<i64>().map_err(|_| invalid(format!("Invalid {} value '{}'", keyword, value)))
+}
+
+/// Non-negative integer value (a count or size) of a header keyword
+fn count(cards: &HashMap<String, String>, keyword: &str) -> Result<usize, Box<dyn Error>> {
+    let value = integer(cards, keyword)?;
+    usize::try_from(value).map_err(|_| invalid(format!("Negative {} value {}", keyword, value)))
+}
+
+/// Binary table column: byte offset in the row and TFORM type code
+struct Column {
+    offset: usize,
+    code: char,
+}
+
+impl Column {
+    /// Decodes the (big-endian) value of the column in a row
+    fn value(&self, row: &[u8]) -> f64 {
+        let field = &row[self.offset..];
+        match self.code {
+            'E' => f32::from_be_bytes([field[0], field[1], field[2], field[3]]) as f64,
+            'D' => f64::from_be_bytes([field[0], field[1], field[2], field[3], field[4], field[5], field[6], field[7]]),
+            'I' => i16::from_be_bytes([field[0], field[1]]) as f64,
+            'J' => i32::from_be_bytes([field[0], field[1], field[2], field[3]]) as f64,
+            'K' => i64::from_be_bytes([field[0], field[1], field[2], field[3], field[4], field[5], field[6], field[7]]) as f64,
+            _ => f64::NAN,
+        }
+    }
+}
+
+/// Size in bytes of a TFORM code
+fn type_size(code: char) -> Option<usize> {
+    match code {
+        'L' | 'X' | 'B' | 'A' => Some(1),
+        'I' => Some(2),
+        'J' | 'E' => Some(4),
+        'K' | 'D' | 'C' | 'P' => Some(8),
+        'M' | 'Q' => Some(16),
+        _ => None,
+    }
+}
+
+/// Reads the detections of an astrometry.net xylist (.axy, .xyls): the X, Y and FLUX columns of the first
+/// binary table. Positions are FITS pixels (1-based). The magnitude is instrumental, from FLUX, or the row
+/// order (xylists are sorted by brightness) when there is no FLUX column.
+pub fn read_image_stars_from_file(path: &Path) -> Result<Vec<ImageStar>, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    // Skip the primary header and data
+    let (primary, mut offset) = read_header(&bytes, 0)?;
+    let overflow = || invalid(format!("{}: data size overflow", path.display()));
+    let axes = count(&primary, "NAXIS")?;
+    if axes > 0 {
+        // Negative BITPIX: floating point pixels
+        let bits = integer(&primary, "BITPIX")?.unsigned_abs() as usize;
+        let mut size = bits / 8;
+        for axis in 1..=axes {
+            size = size.checked_mul(count(&primary, &format!("NAXIS{}", axis))?).ok_or_else(overflow)?;
+        }
+        offset = padded(offset.checked_add(size).ok_or_else(overflow)?);
+    }
+    // First extension: binary table
+    let (table, data) = read_header(&bytes, offset)?;
+    match table.get("XTENSION") {
+        Some(extension) if extension == "BINTABLE" => (),
+        _ => return Err(invalid(format!("{}: the first extension is not a binary table", path.display()))),
+    }
+    let (row_size, rows, fields) = (count(&table, "NAXIS1")?, count(&table, "NAXIS2")?, count(&table, "TFIELDS")?);
+    if row_size == 0 && rows > 0 {
+        return Err(invalid(format!("{}: {} rows of 0 bytes", path.display(), rows)));
+    }
+    debug!("Read xylist > rows:{} row size:{} fields:{}", rows, row_size, fields);
+    let mut columns = HashMap::new();
+    let mut column_offset: usize = 0;
+    for n in 1..=fields {
+        let form = table.get(&format!("TFORM{}", n)).ok_or_else(|| invalid(format!("Missing TFORM{} keyword", n)))?;
+        let digits: String = form.chars().take_while(|c| c.is_ascii_digit()).collect();
+        let repeat = if digits.is_empty() { 1 } else { digits.parse::<usize>().map_err(|_| invalid(format!("Invalid TFORM{} '{}'", n, form)))? };
+        let code = form[digits.len()..].chars().next().ok_or_else(|| invalid(format!("Invalid TFORM{} '{}'", n, form)))?;
+        let size = type_size(code).ok_or_else(|| invalid(format!("Unsupported TFORM{} '{}'", n, form)))?;
+        let width = repeat.checked_mul(size).ok_or_else(overflow)?;
+        // The column must fit in the row
+        let end = column_offset.checked_add(width).ok_or_else(overflow)?;
+        if end > row_size {
+            return Err(invalid(format!("{}: column {} ends at byte {}, beyond the {} bytes row", path.display(), n, end, row_size)));
+        }
+        // Columns of zero width hold no value
+        if let (Some(name), true) = (table.get(&format!("TTYPE{}", n)), width > 0) {
+            columns.insert(name.to_uppercase(), Column { offset: column_offset, code });
+        }
+        column_offset = end;
+    }
+    let column = |name: &str| columns.get(name).ok_or_else(|| invalid(format!("{}: column {} not found", path.display(), name)));
+    let (x, y, flux) = (column("X")?, column("Y")?, columns.get("FLUX"));
+    let table_size = rows.checked_mul(row_size).ok_or_else(overflow)?;
+    if data.checked_add(table_size).ok_or_else(overflow)? > bytes.len() {
+        return Err(invalid(format!("{}: truncated table, {} rows of {} bytes expected", path.display(), rows, row_size)));
+    }
+
+    let mut star_list = Vec::with_capacity(rows);
+    for (n, row) in bytes[data..data + table_size].chunks_exact(row_size.max(1)).enumerate() {
+        let magnitude = match flux {
+            Some(flux) => -2.5 * flux.value(row).max(f64::MIN_POSITIVE).log10(),
+            None => n as f64,
+        };
+        star_list.push(ImageStar { pixel_x: x.value(row), pixel_y: y.value(row), magnitude });
+    }
+    // Sort by magnitude
+    star_list.sort_by(|a, b| a.magnitude.partial_cmp(&b.magnitude).unwrap());
+    Ok(star_list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// FITS header unit from keyword/value pairs, padded to a block
+    fn header(cards: &[(&str, &str)]) -> Vec<u8> {
+        let mut text = String::new();
+        for (keyword, value) in cards {
+            text.push_str(&format!("{:<8}= {:<70}", keyword, value));
+        }
+        text.push_str(&format!("{:<80}", "END"));
+        let mut bytes = text.into_bytes();
+        bytes.resize(padded(bytes.len()), b' ');
+        bytes
+    }
+
+    /// Writes an xylist with a float primary image and a binary table of X and Y (E) columns
+    fn write_xylist(name: &str, naxis1: &str, naxis2: &str, rows: &[(f32, f32)]) -> std::path::PathBuf {
+        let mut bytes = header(&[("SIMPLE", "T"), ("BITPIX", "-32"), ("NAXIS", "2"), ("NAXIS1", "3"), ("NAXIS2", "2")]);
+        bytes.resize(bytes.len() + BLOCK_SIZE, 0);
+        bytes.extend(header(&[
+            ("XTENSION", "'BINTABLE'"), ("BITPIX", "8"), ("NAXIS", "2"), ("NAXIS1", naxis1), ("NAXIS2", naxis2),
+            ("TFIELDS", "2"), ("TTYPE1", "'X'"), ("TFORM1", "'E'"), ("TTYPE2", "'Y'"), ("TFORM2", "'E'"),
+        ]));
+        for (x, y) in rows {
+            bytes.extend_from_slice(&x.to_be_bytes());
+            bytes.extend_from_slice(&y.to_be_bytes());
+        }
+        bytes.resize(padded(bytes.len()), 0);
+        let path = std::env::temp_dir().join(format!("rastap-xylist-{}-{}.axy", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn invalid_data(path: &Path) -> bool {
+        match read_image_stars_from_file(path) {
+            Err(err) => err.downcast_ref::<io::Error>().is_some_and(|err| err.kind() == io::ErrorKind::InvalidData),
+            Ok(_) => false,
+        }
+    }
+
+    #[test]
+    fn reads_after_float_primary_image() {
+        let path = write_xylist("float", "8", "2", &[(10.5, 20.5), (30.0, 40.0)]);
+        let stars = read_image_stars_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(stars.len(), 2);
+        assert_eq!((stars[0].pixel_x, stars[0].pixel_y), (10.5, 20.5));
+    }
+
+    #[test]
+    fn rejects_empty_rows() {
+        let path = write_xylist("empty-rows", "0", "2", &[]);
+        assert!(invalid_data(&path));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_columns_beyond_the_row() {
+        let path = write_xylist("short-row", "4", "2", &[(1.0, 2.0)]);
+        assert!(invalid_data(&path));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_table_size_overflow() {
+        let path = write_xylist("overflow", "8", "9223372036854775807", &[]);
+        assert!(invalid_data(&path));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_negative_sizes() {
+        let path = write_xylist("negative", "8", "-2", &[]);
+        assert!(invalid_data(&path));
+        fs::remove_file(&path).unwrap();
+    }
+}